@@ -0,0 +1,188 @@
+//! Regenerates the golden fixtures in `tests/fixtures` from a captured session,
+//! replacing hand-built fixtures with bytes real clients and servers sent.
+//!
+//! ```text
+//! cargo run --example extract_fixtures --features testing -- <capture> [fixtures dir]
+//! ```
+//!
+//! The capture uses the [RmcCapture] text format. Only the first successful call of each
+//! method is extracted, and types that are never sent on their own keep their hand-built fixture.
+
+use nex_protocols_rs::datastore_usum::{DataStoreMethod, DATASTORE_PROTOCOL_ID};
+use nex_protocols_rs::testing::{encode_hex, CapturedMessage, CapturedResult, RmcCapture};
+use nex_protocols_rs::ticket_granting::{TicketGrantingMethod, AUTHENTICATION_PROTOCOL_ID};
+use nex_rs::nex_types::NexString;
+use no_std_io::{StreamContainer, StreamReader};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Fixtures holding a whole request's parameters or a whole response, by method.
+const DATASTORE_FIXTURES: [(DataStoreMethod, Option<&str>, Option<&str>); 12] = [
+    (
+        DataStoreMethod::GetMetas,
+        Some("GetMetasRequest"),
+        Some("GetMetasResponse"),
+    ),
+    (
+        DataStoreMethod::RateObject,
+        Some("RateObjectRequest"),
+        Some("RateObjectResponse"),
+    ),
+    (
+        DataStoreMethod::PostMetaBinary,
+        Some("PostMetaBinaryRequest"),
+        Some("PostMetaBinaryResponse"),
+    ),
+    (
+        DataStoreMethod::ChangeMetas,
+        Some("ChangeMetasRequest"),
+        Some("ChangeMetasResponse"),
+    ),
+    (
+        DataStoreMethod::PrepareUploadPokemon,
+        None,
+        Some("PrepareUploadPokemonResponse"),
+    ),
+    (
+        DataStoreMethod::UploadPokemon,
+        Some("UploadPokemonRequest"),
+        None,
+    ),
+    (
+        DataStoreMethod::PrepareTradePokemon,
+        Some("PrepareTradePokemonRequest"),
+        Some("PrepareTradePokemonResponse"),
+    ),
+    (
+        DataStoreMethod::TradePokemon,
+        Some("TradePokemonRequest"),
+        Some("TradePokemonResponse"),
+    ),
+    (
+        DataStoreMethod::DownloadOtherPokemon,
+        Some("DownloadOtherPokemonRequest"),
+        Some("DownloadOtherPokemonResponse"),
+    ),
+    (
+        DataStoreMethod::DownloadMyPokemon,
+        Some("DownloadMyPokemonRequest"),
+        Some("DownloadMyPokemonResponse"),
+    ),
+    (
+        DataStoreMethod::DeletePokemon,
+        Some("DeletePokemonRequest"),
+        None,
+    ),
+    (
+        DataStoreMethod::SearchPokemonV2,
+        Some("SearchPokemonV2Request"),
+        Some("SearchPokemonV2Response"),
+    ),
+];
+
+fn get_datastore_fixture(method_id: u32, is_request: bool) -> Option<String> {
+    DATASTORE_FIXTURES
+        .iter()
+        .find(|(method, _, _)| u32::from(*method) == method_id)
+        .and_then(|(_, request, response)| if is_request { *request } else { *response })
+        .map(|name| format!("datastore_usum/{}.hex", name))
+}
+
+/// Returns the AuthenticationInfo a LoginEx request's DataHolder wraps.
+fn extract_authentication_info(parameters: &[u8]) -> Option<Vec<u8>> {
+    let mut stream = StreamContainer::new(parameters);
+    let _username = stream.read_stream_le::<NexString>().ok()?;
+    let name: String = stream.read_stream_le::<NexString>().ok()?.into();
+    let _length_1 = stream.read_stream_le::<u32>().ok()?;
+    let length_2 = stream.read_stream_le::<u32>().ok()? as usize;
+
+    let start = stream.get_index();
+    let object = parameters.get(start..start.checked_add(length_2)?)?;
+    (name == "AuthenticationInfo").then(|| object.to_vec())
+}
+
+fn write_fixture(
+    fixtures_dir: &Path,
+    fixture: &str,
+    source: &str,
+    call_id: u32,
+    bytes: &[u8],
+) -> std::io::Result<()> {
+    let name = Path::new(fixture)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or(fixture);
+    let mut text = format!(
+        "# {}\n# Extracted from {}, call {}.\n",
+        name, source, call_id
+    );
+
+    for line in bytes.chunks(20) {
+        text.push_str(&encode_hex(line));
+        text.push('\n');
+    }
+
+    let path = fixtures_dir.join(fixture);
+    std::fs::write(&path, text)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let capture_path = args
+        .next()
+        .ok_or("Usage: extract_fixtures <capture> [fixtures dir]")?;
+    let fixtures_dir = args
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures"));
+
+    let capture = RmcCapture::read_file(&capture_path)?;
+    let mut written = HashSet::new();
+
+    for message in capture.messages.iter() {
+        let (fixture, call_id, bytes) = match message {
+            CapturedMessage::Request(request) if request.protocol_id == DATASTORE_PROTOCOL_ID => {
+                match get_datastore_fixture(request.method_id, true) {
+                    Some(fixture) => (fixture, request.call_id, request.parameters.clone()),
+                    None => continue,
+                }
+            }
+            CapturedMessage::Request(request)
+                if request.protocol_id == AUTHENTICATION_PROTOCOL_ID
+                    && request.method_id == u32::from(TicketGrantingMethod::LoginEx) =>
+            {
+                match extract_authentication_info(&request.parameters) {
+                    Some(bytes) => (
+                        "ticket_granting/AuthenticationInfo.hex".to_string(),
+                        request.call_id,
+                        bytes,
+                    ),
+                    None => continue,
+                }
+            }
+            CapturedMessage::Response(response)
+                if response.protocol_id == DATASTORE_PROTOCOL_ID =>
+            {
+                let fixture = response
+                    .method_id
+                    .and_then(|method_id| get_datastore_fixture(method_id, false));
+
+                match (fixture, &response.result) {
+                    (Some(fixture), CapturedResult::Success(data)) => {
+                        (fixture, response.call_id, data.clone())
+                    }
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+
+        if written.insert(fixture.clone()) {
+            write_fixture(&fixtures_dir, &fixture, &capture_path, call_id, &bytes)?;
+        }
+    }
+
+    Ok(())
+}
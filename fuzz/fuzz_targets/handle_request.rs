@@ -0,0 +1,479 @@
+//! Dispatches arbitrary parameters to every protocol's handle_request, from a client with a session.
+//! The first byte picks the protocol and the second the method id, the rest are the parameters.
+//!
+//! Protocols with a server trait use their default implementations; the others are stubs,
+//! so the decoding in each handle_* method is what gets exercised.
+
+#![no_main]
+
+use async_trait::async_trait;
+use libfuzzer_sys::fuzz_target;
+use nex_protocols_rs::any_data_holder::AnyDataHolder;
+use nex_protocols_rs::client_context::{ClientContextProvider, ClientContextTable};
+use nex_protocols_rs::datastore_usum::*;
+use nex_protocols_rs::health::{HealthProbeRegistry, HealthProbeServer, HealthProtocol};
+use nex_protocols_rs::impl_mock_server;
+use nex_protocols_rs::match_making::{MatchMakingProtocol, MATCH_MAKING_PROTOCOL_ID};
+use nex_protocols_rs::matchmake_extension::{
+    MatchmakeExtensionProtocol, MatchmakeRegistry, MatchmakeRegistryServer,
+    MATCHMAKE_EXTENSION_PROTOCOL_ID,
+};
+use nex_protocols_rs::monitoring::{ClusterRegistry, MonitoringProtocol, MonitoringServer};
+use nex_protocols_rs::secure_connection::{
+    SecureConnectionProtocol, SECURE_CONNECTION_PROTOCOL_ID,
+};
+use nex_protocols_rs::testing::{
+    build_request_packet, fake_client, MockDispatch, MockHarness, MockServer,
+};
+use nex_protocols_rs::ticket_granting::{
+    Account, AccountServer, AccountStore, InMemoryAccountStore, TicketGrantingProtocol,
+    AUTHENTICATION_PROTOCOL_ID,
+};
+use nex_protocols_rs::title_profile::TitleProfileRegistry;
+use nex_protocols_rs::usum_117::{USUM117Protocol, USUM_117_PROTOCOL_ID};
+use nex_protocols_rs::{health::HEALTH_PROTOCOL_ID, monitoring::MONITORING_PROTOCOL_ID};
+use nex_rs::client::ClientConnection;
+use nex_rs::nex_types::{NexList, NexQBuffer, NexString, ResultCode};
+use nex_rs::packet::{Packet, PacketV1};
+use std::time::Instant;
+
+const PROTOCOL_IDS: [u8; 9] = [
+    AUTHENTICATION_PROTOCOL_ID,
+    SECURE_CONNECTION_PROTOCOL_ID,
+    HEALTH_PROTOCOL_ID,
+    MONITORING_PROTOCOL_ID,
+    MATCH_MAKING_PROTOCOL_ID,
+    MATCHMAKE_EXTENSION_PROTOCOL_ID,
+    DATASTORE_PROTOCOL_ID,
+    USUM_117_PROTOCOL_ID,
+    // Unknown protocols are ignored by the dispatcher.
+    0xFF,
+];
+
+struct FuzzServer {
+    mock: MockServer,
+    client_contexts: ClientContextTable,
+    accounts: InMemoryAccountStore,
+    matchmake_registry: MatchmakeRegistry,
+    health_probes: HealthProbeRegistry,
+    cluster_registry: ClusterRegistry,
+    start_time: Instant,
+}
+
+impl FuzzServer {
+    fn new() -> Self {
+        Self {
+            mock: MockServer::new(),
+            client_contexts: ClientContextTable::new(TitleProfileRegistry::new()),
+            accounts: InMemoryAccountStore::new(),
+            matchmake_registry: MatchmakeRegistry::new(),
+            health_probes: HealthProbeRegistry::new(),
+            cluster_registry: ClusterRegistry::new(),
+            start_time: Instant::now(),
+        }
+    }
+}
+
+impl_mock_server!(FuzzServer, mock);
+
+impl ClientContextProvider for FuzzServer {
+    fn get_client_context_table(&self) -> &ClientContextTable {
+        &self.client_contexts
+    }
+}
+
+impl MatchmakeRegistryServer for FuzzServer {
+    fn get_matchmake_registry(&self) -> &MatchmakeRegistry {
+        &self.matchmake_registry
+    }
+}
+
+impl HealthProbeServer for FuzzServer {
+    fn get_health_probes(&self) -> &HealthProbeRegistry {
+        &self.health_probes
+    }
+}
+
+impl MonitoringServer for FuzzServer {
+    fn get_cluster_registry(&self) -> &ClusterRegistry {
+        &self.cluster_registry
+    }
+
+    fn get_start_time(&self) -> Instant {
+        self.start_time
+    }
+}
+
+#[async_trait(?Send)]
+impl AccountServer for FuzzServer {
+    fn get_account_store(&self) -> &dyn AccountStore {
+        &self.accounts
+    }
+
+    async fn create_login_response(
+        &self,
+        _client: &mut ClientConnection,
+        _account: &Account,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn create_ticket_response(
+        &self,
+        _client: &mut ClientConnection,
+        _user: &Account,
+        _server: &Account,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+}
+
+#[async_trait(?Send)]
+impl SecureConnectionProtocol for FuzzServer {
+    async fn register(
+        &self,
+        _client: &mut ClientConnection,
+        _my_urls: NexList<NexString>,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn request_connection_data(
+        &self,
+        _client: &mut ClientConnection,
+        _cid_target: u32,
+        _pid_target: u32,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn request_urls(
+        &self,
+        _client: &mut ClientConnection,
+        _cid_target: u32,
+        _pid_target: u32,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn register_ex(
+        &self,
+        _client: &mut ClientConnection,
+        _my_urls: NexList<NexString>,
+        _custom_data: AnyDataHolder,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn test_connectivity(
+        &self,
+        _client: &mut ClientConnection,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Ok(vec![])
+    }
+
+    async fn update_urls(
+        &self,
+        _client: &mut ClientConnection,
+        _my_urls: NexList<NexString>,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Ok(vec![])
+    }
+
+    async fn replace_url(
+        &self,
+        _client: &mut ClientConnection,
+        _target: NexString,
+        _url: NexString,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Ok(vec![])
+    }
+
+    async fn send_report(
+        &self,
+        _client: &mut ClientConnection,
+        _report_id: u32,
+        _report_data: NexQBuffer,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Ok(vec![])
+    }
+}
+
+#[async_trait(?Send)]
+impl MatchMakingProtocol for FuzzServer {
+    async fn register_gathering(
+        &self,
+        _client: &mut ClientConnection,
+        _gathering: AnyDataHolder,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn unregister_gathering(
+        &self,
+        _client: &mut ClientConnection,
+        _gid: u32,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn update_gathering(
+        &self,
+        _client: &mut ClientConnection,
+        _gathering: AnyDataHolder,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn participate(
+        &self,
+        _client: &mut ClientConnection,
+        _gid: u32,
+        _message: String,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn cancel_participation(
+        &self,
+        _client: &mut ClientConnection,
+        _gid: u32,
+        _message: String,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn find_by_id(
+        &self,
+        _client: &mut ClientConnection,
+        _gids: NexList<u32>,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn find_by_single_id(
+        &self,
+        _client: &mut ClientConnection,
+        _gid: u32,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn get_session_urls(
+        &self,
+        _client: &mut ClientConnection,
+        _gid: u32,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn migrate_gathering_ownership(
+        &self,
+        _client: &mut ClientConnection,
+        _gid: u32,
+        _potential_new_owners: NexList<u32>,
+        _participants_only: bool,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+}
+
+#[async_trait(?Send)]
+impl DataStoreProtocol for FuzzServer {
+    async fn get_metas(
+        &self,
+        _client: &mut ClientConnection,
+        _data_ids: NexList<u64>,
+        _param: DataStoreGetMetaParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn rate_object(
+        &self,
+        _client: &mut ClientConnection,
+        _target: DataStoreRatingTarget,
+        _param: DataStoreRateObjectParam,
+        _fetch_ratings: bool,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn post_meta_binary(
+        &self,
+        _client: &mut ClientConnection,
+        _param: DataStorePreparePostParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn change_metas(
+        &self,
+        _client: &mut ClientConnection,
+        _param: ChangeMetasRequest,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn prepare_upload_pokemon(
+        &self,
+        _client: &mut ClientConnection,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn upload_pokemon(
+        &self,
+        _client: &mut ClientConnection,
+        _param: GlobalTradeStationUploadPokemonParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn prepare_trade_pokemon(
+        &self,
+        _client: &mut ClientConnection,
+        _param: GlobalTradeStationPrepareTradePokemonParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn trade_pokemon(
+        &self,
+        _client: &mut ClientConnection,
+        _param: GlobalTradeStationTradePokemonParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn download_other_pokemon(
+        &self,
+        _client: &mut ClientConnection,
+        _param: GlobalTradeStationDownloadOtherPokemonParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn download_my_pokemon(
+        &self,
+        _client: &mut ClientConnection,
+        _param: GlobalTradeStationDownloadMyPokemonParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn delete_pokemon(
+        &self,
+        _client: &mut ClientConnection,
+        _param: GlobalTradeStationDeletePokemonParam,
+    ) -> Result<(), ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn search_pokemon_v2(
+        &self,
+        _client: &mut ClientConnection,
+        _param: GlobalTradeStationSearchPokemonParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+}
+
+#[async_trait(?Send)]
+impl USUM117Protocol for FuzzServer {
+    async fn unknown_1(
+        &self,
+        _client: &mut ClientConnection,
+        _parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn unknown_7(
+        &self,
+        _client: &mut ClientConnection,
+        _parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn unknown_9(
+        &self,
+        _client: &mut ClientConnection,
+        _parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn unknown_10(
+        &self,
+        _client: &mut ClientConnection,
+        _parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn unknown_15(
+        &self,
+        _client: &mut ClientConnection,
+        _parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+}
+
+#[async_trait(?Send)]
+impl MockDispatch for FuzzServer {
+    fn get_mock_server(&self) -> &MockServer {
+        &self.mock
+    }
+
+    async fn dispatch_request(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> Result<(), &'static str> {
+        match packet.get_rmc_request().protocol_id {
+            AUTHENTICATION_PROTOCOL_ID => {
+                TicketGrantingProtocol::handle_request(self, client, packet).await
+            }
+            SECURE_CONNECTION_PROTOCOL_ID => {
+                SecureConnectionProtocol::handle_request(self, client, packet).await
+            }
+            HEALTH_PROTOCOL_ID => HealthProtocol::handle_request(self, client, packet).await,
+            MONITORING_PROTOCOL_ID => {
+                MonitoringProtocol::handle_request(self, client, packet).await
+            }
+            MATCH_MAKING_PROTOCOL_ID => {
+                MatchMakingProtocol::handle_request(self, client, packet).await
+            }
+            MATCHMAKE_EXTENSION_PROTOCOL_ID => {
+                MatchmakeExtensionProtocol::handle_request(self, client, packet).await
+            }
+            DATASTORE_PROTOCOL_ID => DataStoreProtocol::handle_request(self, client, packet).await,
+            USUM_117_PROTOCOL_ID => USUM117Protocol::handle_request(self, client, packet).await,
+            _ => Ok(()),
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (protocol_id, method_id, parameters) = match data {
+        [protocol, method, parameters @ ..] => (
+            PROTOCOL_IDS[*protocol as usize % PROTOCOL_IDS.len()],
+            *method as u32,
+            parameters,
+        ),
+        _ => return,
+    };
+
+    let server = FuzzServer::new();
+    let client = fake_client(1000, 1);
+    server
+        .get_client_context_table()
+        .start_session(&client, vec![]);
+
+    let packet = build_request_packet(protocol_id, method_id, 1, parameters.to_vec());
+    let mut harness = MockHarness::new(server, client);
+    let _ = futures::executor::block_on(harness.send(&packet));
+});
@@ -0,0 +1,83 @@
+//! Decodes arbitrary bytes as each version dependent structure and as a DataHolder.
+//! The first byte picks the decoder and the second the NEX version.
+//! Anything that decodes has to encode again, and the encoding has to decode.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nex_protocols_rs::any_data_holder::{
+    DataHolderRegistry, GameKey, MiiV2, NNAInfo, NintendoCreateAccountData, NintendoLoginData,
+    PrincipalBasicInfo,
+};
+use nex_protocols_rs::matchmake_extension::{
+    AutoMatchmakeParam, Community, CreateMatchmakeSessionParam, Gathering,
+    JoinMatchmakeSessionParam, MatchmakeParam, MatchmakeSession, MatchmakeSessionSearchCriteria,
+    PersistentGathering, UpdateMatchmakeSessionParam,
+};
+use nex_protocols_rs::structure::{
+    read_structure_list, NexStructure, NexVersion, DEFAULT_NEX_VERSION,
+};
+use nex_protocols_rs::ticket_granting::AuthenticationInfo;
+use no_std_io::StreamContainer;
+
+const NEX_VERSIONS: [NexVersion; 8] = [
+    DEFAULT_NEX_VERSION,
+    NexVersion::new(3, 0, 0),
+    NexVersion::new(3, 4, 0),
+    NexVersion::new(3, 5, 0),
+    NexVersion::new(3, 6, 0),
+    NexVersion::new(3, 7, 0),
+    NexVersion::new(3, 8, 0),
+    NexVersion::new(4, 0, 0),
+];
+
+fn round_trip<T: NexStructure>(bytes: &[u8], nex_version: NexVersion) {
+    let value = match T::read_structure(&mut StreamContainer::new(bytes), nex_version) {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+
+    let written = value
+        .write_structure(nex_version)
+        .expect("a decoded structure can be encoded");
+    T::read_structure(&mut StreamContainer::new(written.as_slice()), nex_version)
+        .expect("an encoded structure can be decoded");
+}
+
+fn read_list<T: NexStructure>(bytes: &[u8], nex_version: NexVersion) {
+    let _ = read_structure_list::<T>(&mut StreamContainer::new(bytes), nex_version);
+}
+
+fn read_data_holder(bytes: &[u8], nex_version: NexVersion) {
+    let _ = DataHolderRegistry::new().read(&mut StreamContainer::new(bytes), nex_version);
+}
+
+const DECODERS: [fn(&[u8], NexVersion); 20] = [
+    round_trip::<Gathering>,
+    round_trip::<MatchmakeParam>,
+    round_trip::<MatchmakeSession>,
+    round_trip::<MatchmakeSessionSearchCriteria>,
+    round_trip::<CreateMatchmakeSessionParam>,
+    round_trip::<JoinMatchmakeSessionParam>,
+    round_trip::<AutoMatchmakeParam>,
+    round_trip::<UpdateMatchmakeSessionParam>,
+    round_trip::<Community>,
+    round_trip::<PersistentGathering>,
+    round_trip::<AuthenticationInfo>,
+    round_trip::<NintendoLoginData>,
+    round_trip::<MiiV2>,
+    round_trip::<PrincipalBasicInfo>,
+    round_trip::<NNAInfo>,
+    round_trip::<NintendoCreateAccountData>,
+    round_trip::<GameKey>,
+    read_list::<MatchmakeSessionSearchCriteria>,
+    read_list::<Gathering>,
+    read_data_holder,
+];
+
+fuzz_target!(|data: &[u8]| {
+    if let [selector, version, bytes @ ..] = data {
+        let nex_version = NEX_VERSIONS[*version as usize % NEX_VERSIONS.len()];
+        DECODERS[*selector as usize % DECODERS.len()](bytes, nex_version);
+    }
+});
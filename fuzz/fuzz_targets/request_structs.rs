@@ -0,0 +1,45 @@
+//! Decodes arbitrary bytes as each *Request struct.
+//! The first byte picks the struct, the rest are its parameters.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nex_protocols_rs::{datastore_usum, match_making};
+use no_std_io::{StreamContainer, StreamReader};
+
+macro_rules! decoders {
+    ($($type:ty),* $(,)?) => {
+        [$(|parameters: &[u8]| {
+            let _ = StreamContainer::new(parameters).read_stream_le::<$type>();
+        }),*]
+    };
+}
+
+const DECODERS: [fn(&[u8]); 20] = decoders![
+    datastore_usum::GetMetasRequest,
+    datastore_usum::RateObjectRequest,
+    datastore_usum::PostMetaBinaryRequest,
+    datastore_usum::ChangeMetasRequest,
+    datastore_usum::UploadPokemonRequest,
+    datastore_usum::PrepareTradePokemonRequest,
+    datastore_usum::TradePokemonRequest,
+    datastore_usum::DownloadOtherPokemonRequest,
+    datastore_usum::DownloadMyPokemonRequest,
+    datastore_usum::DeletePokemonRequest,
+    datastore_usum::SearchPokemonV2Request,
+    match_making::RegisterGatheringRequest,
+    match_making::UnregisterGatheringRequest,
+    match_making::UpdateGatheringRequest,
+    match_making::ParticipateRequest,
+    match_making::CancelParticipationRequest,
+    match_making::FindByIDRequest,
+    match_making::FindBySingleIDRequest,
+    match_making::GetSessionURLsRequest,
+    match_making::MigrateGatheringOwnershipRequest,
+];
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((selector, parameters)) = data.split_first() {
+        DECODERS[*selector as usize % DECODERS.len()](parameters);
+    }
+});
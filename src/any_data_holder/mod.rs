@@ -0,0 +1,5 @@
+mod registry;
+pub use registry::*;
+
+mod types;
+pub use types::*;
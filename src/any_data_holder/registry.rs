@@ -0,0 +1,364 @@
+use crate::any_data_holder::{GameKey, NintendoCreateAccountData, NintendoLoginData};
+use crate::matchmake_extension::{Gathering, MatchmakeSession};
+use crate::structure::{check_data_holder_lengths, read_slice, NexStructure, NexVersion};
+use crate::ticket_granting::AuthenticationInfo;
+use nex_rs::nex_types::NexString;
+use no_std_io::{Error, StreamContainer, StreamReader};
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A decoded DataHolder payload.
+/// Class names without a registered decoder are kept as raw bytes.
+pub enum AnyDataHolder {
+    AuthenticationInfo(AuthenticationInfo),
+    NintendoLoginData(NintendoLoginData),
+    NintendoCreateAccountData(NintendoCreateAccountData),
+    GameKey(GameKey),
+    Gathering(Gathering),
+    MatchmakeSession(MatchmakeSession),
+    Custom { name: String, object: Box<dyn Any> },
+    Unknown { name: String, data: Vec<u8> },
+}
+
+impl AnyDataHolder {
+    pub fn get_name(&self) -> &str {
+        match self {
+            Self::AuthenticationInfo(_) => "AuthenticationInfo",
+            Self::NintendoLoginData(_) => "NintendoLoginData",
+            Self::NintendoCreateAccountData(_) => "NintendoCreateAccountData",
+            Self::GameKey(_) => "GameKey",
+            Self::Gathering(_) => "Gathering",
+            Self::MatchmakeSession(_) => "MatchmakeSession",
+            Self::Custom { name, .. } | Self::Unknown { name, .. } => name,
+        }
+    }
+
+    /// Returns the gathering of a Gathering or one of its subclasses.
+    pub fn get_gathering(&self) -> Option<&Gathering> {
+        match self {
+            Self::Gathering(gathering) => Some(gathering),
+            Self::MatchmakeSession(session) => Some(&session.gathering),
+            _ => None,
+        }
+    }
+
+    /// Returns the object of a payload decoded by [DataHolderRegistry::register].
+    pub fn downcast_custom<T: 'static>(&self) -> Option<&T> {
+        match self {
+            Self::Custom { object, .. } => object.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Shows decoded payloads, relying on their own Debug impls to redact secrets.
+/// Custom objects can't be formatted, so only their class name is shown.
+impl fmt::Debug for AnyDataHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AuthenticationInfo(object) => {
+                f.debug_tuple("AuthenticationInfo").field(object).finish()
+            }
+            Self::NintendoLoginData(object) => {
+                f.debug_tuple("NintendoLoginData").field(object).finish()
+            }
+            Self::NintendoCreateAccountData(object) => f
+                .debug_tuple("NintendoCreateAccountData")
+                .field(object)
+                .finish(),
+            Self::GameKey(object) => f.debug_tuple("GameKey").field(object).finish(),
+            Self::Gathering(object) => f.debug_tuple("Gathering").field(object).finish(),
+            Self::MatchmakeSession(object) => {
+                f.debug_tuple("MatchmakeSession").field(object).finish()
+            }
+            Self::Custom { name, .. } => f
+                .debug_struct("Custom")
+                .field("name", name)
+                .finish_non_exhaustive(),
+            Self::Unknown { name, data } => f
+                .debug_struct("Unknown")
+                .field("name", name)
+                .field("data", data)
+                .finish(),
+        }
+    }
+}
+
+type DataHolderDecoder =
+    Box<dyn Fn(&mut StreamContainer<&[u8]>, NexVersion) -> Result<AnyDataHolder, Error>>;
+
+/// Maps NEX class names to decoders for polymorphic DataHolder payloads.
+pub struct DataHolderRegistry {
+    decoders: HashMap<String, DataHolderDecoder>,
+    gathering_classes: HashSet<String>,
+}
+
+impl Default for DataHolderRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register_decoder("AuthenticationInfo", |stream, nex_version| {
+            Ok(AnyDataHolder::AuthenticationInfo(
+                AuthenticationInfo::read_structure(stream, nex_version)?,
+            ))
+        });
+        registry.register_decoder("NintendoLoginData", |stream, nex_version| {
+            Ok(AnyDataHolder::NintendoLoginData(
+                NintendoLoginData::read_structure(stream, nex_version)?,
+            ))
+        });
+        registry.register_decoder("NintendoCreateAccountData", |stream, nex_version| {
+            Ok(AnyDataHolder::NintendoCreateAccountData(
+                NintendoCreateAccountData::read_structure(stream, nex_version)?,
+            ))
+        });
+        registry.register_decoder("GameKey", |stream, nex_version| {
+            Ok(AnyDataHolder::GameKey(GameKey::read_structure(
+                stream,
+                nex_version,
+            )?))
+        });
+        registry.register_decoder("Gathering", |stream, nex_version| {
+            Ok(AnyDataHolder::Gathering(Gathering::read_structure(
+                stream,
+                nex_version,
+            )?))
+        });
+        registry.register_decoder("MatchmakeSession", |stream, nex_version| {
+            Ok(AnyDataHolder::MatchmakeSession(
+                MatchmakeSession::read_structure(stream, nex_version)?,
+            ))
+        });
+        registry.gathering_classes.insert("Gathering".to_string());
+        registry
+            .gathering_classes
+            .insert("MatchmakeSession".to_string());
+        registry
+    }
+}
+
+impl fmt::Debug for DataHolderRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.decoders.keys()).finish()
+    }
+}
+
+impl DataHolderRegistry {
+    /// Creates a registry with the built-in classes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry without built-in classes.
+    pub fn empty() -> Self {
+        Self {
+            decoders: HashMap::new(),
+            gathering_classes: HashSet::new(),
+        }
+    }
+
+    /// Registers a decoder for `name`, replacing an existing one.
+    pub fn register_decoder(
+        &mut self,
+        name: &str,
+        decoder: impl Fn(&mut StreamContainer<&[u8]>, NexVersion) -> Result<AnyDataHolder, Error>
+            + 'static,
+    ) {
+        self.decoders.insert(name.to_string(), Box::new(decoder));
+    }
+
+    /// Registers a structure decoded into [AnyDataHolder::Custom].
+    pub fn register<T: NexStructure + 'static>(&mut self, name: &str) {
+        let class_name = name.to_string();
+        self.register_decoder(name, move |stream, nex_version| {
+            Ok(AnyDataHolder::Custom {
+                name: class_name.clone(),
+                object: Box::new(T::read_structure(stream, nex_version)?),
+            })
+        });
+    }
+
+    /// Registers a Gathering subclass decoded into [AnyDataHolder::Custom],
+    /// accepted wherever a gathering is expected.
+    pub fn register_gathering<T: NexStructure + 'static>(&mut self, name: &str) {
+        self.register::<T>(name);
+        self.gathering_classes.insert(name.to_string());
+    }
+
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.decoders.contains_key(name)
+    }
+
+    /// Whether a decoded payload is a Gathering or one of its registered subclasses.
+    pub fn is_gathering(&self, data_holder: &AnyDataHolder) -> bool {
+        !matches!(data_holder, AnyDataHolder::Unknown { .. })
+            && self.gathering_classes.contains(data_holder.get_name())
+    }
+
+    /// Reads a DataHolder, decoding its object with the decoder registered for its class name.
+    /// Objects the decoder doesn't fully read are rejected.
+    pub fn read(
+        &self,
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<AnyDataHolder, Error> {
+        let name: String = stream.read_stream_le::<NexString>()?.into();
+        let length_1 = stream.read_stream_le::<u32>()?;
+        let length_2 = stream.read_stream_le::<u32>()?;
+        check_data_holder_lengths(length_1, length_2)?;
+
+        let data = read_slice(stream, length_2 as usize)?;
+
+        let decoder = match self.decoders.get(&name) {
+            Some(decoder) => decoder,
+            None => {
+                return Ok(AnyDataHolder::Unknown {
+                    name,
+                    data: data.to_vec(),
+                })
+            }
+        };
+
+        let mut object_stream = StreamContainer::new(data);
+        let data_holder = decoder(&mut object_stream, nex_version)?;
+
+        if object_stream.get_index() != data.len() {
+            return Err(Error::InvalidRead {
+                message: "DataHolder object doesn't match its length",
+            });
+        }
+
+        Ok(data_holder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::{write_data_holder_structure, DEFAULT_NEX_VERSION};
+
+    fn game_key(name: &str) -> Vec<u8> {
+        let game_key = GameKey {
+            title_id: 0x00040000001B5000,
+            title_version: 1,
+        };
+        write_data_holder_structure(name, &game_key, DEFAULT_NEX_VERSION).unwrap()
+    }
+
+    fn read(registry: &DataHolderRegistry, bytes: &[u8]) -> Result<AnyDataHolder, Error> {
+        let mut stream = StreamContainer::new(bytes);
+        let data_holder = registry.read(&mut stream, DEFAULT_NEX_VERSION)?;
+        assert_eq!(stream.get_index(), bytes.len());
+        Ok(data_holder)
+    }
+
+    #[test]
+    fn decodes_built_in_classes() {
+        let data_holder = read(&DataHolderRegistry::new(), &game_key("GameKey")).unwrap();
+
+        match data_holder {
+            AnyDataHolder::GameKey(game_key) => {
+                assert_eq!(game_key.title_id, 0x00040000001B5000);
+                assert_eq!(game_key.title_version, 1);
+            }
+            other => panic!("Expected a GameKey, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decodes_registered_classes_as_custom() {
+        let mut registry = DataHolderRegistry::empty();
+        registry.register::<GameKey>("TitleKey");
+        assert!(registry.is_registered("TitleKey"));
+
+        let data_holder = read(&registry, &game_key("TitleKey")).unwrap();
+        assert_eq!(data_holder.get_name(), "TitleKey");
+        assert_eq!(
+            data_holder
+                .downcast_custom::<GameKey>()
+                .map(|game_key| game_key.title_version),
+            Some(1)
+        );
+        assert!(data_holder.downcast_custom::<Gathering>().is_none());
+    }
+
+    #[test]
+    fn only_accepts_gathering_classes_as_gatherings() {
+        let mut registry = DataHolderRegistry::new();
+        registry.register::<GameKey>("TitleKey");
+        registry.register_gathering::<Gathering>("CustomGathering");
+        let gathering = write_data_holder_structure(
+            "CustomGathering",
+            &Gathering::default(),
+            DEFAULT_NEX_VERSION,
+        )
+        .unwrap();
+
+        assert!(registry.is_gathering(&read(&registry, &gathering).unwrap()));
+        assert!(!registry.is_gathering(&read(&registry, &game_key("TitleKey")).unwrap()));
+        assert!(!registry.is_gathering(&read(&registry, &game_key("GameKey")).unwrap()));
+        assert!(registry.is_gathering(&AnyDataHolder::Gathering(Gathering::default())));
+
+        let unknown = AnyDataHolder::Unknown {
+            name: "Gathering".to_string(),
+            data: vec![],
+        };
+        assert!(!registry.is_gathering(&unknown));
+    }
+
+    #[test]
+    fn keeps_unknown_classes_as_raw_bytes() {
+        let bytes = game_key("GameKey");
+        let data_holder = read(&DataHolderRegistry::empty(), &bytes).unwrap();
+
+        match data_holder {
+            AnyDataHolder::Unknown { name, data } => {
+                assert_eq!(name, "GameKey");
+                // The object follows the class name and both lengths
+                assert_eq!(data, bytes[bytes.len() - data.len()..]);
+                assert_eq!(data.len(), 10);
+            }
+            other => panic!("Expected an unknown class, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_objects_with_trailing_bytes() {
+        let mut bytes = game_key("GameKey");
+        let object_start = bytes.len() - 10;
+        bytes.push(0);
+        bytes[object_start - 8..object_start - 4].copy_from_slice(&15u32.to_le_bytes());
+        bytes[object_start - 4..object_start].copy_from_slice(&11u32.to_le_bytes());
+
+        let mut stream = StreamContainer::new(bytes.as_slice());
+        assert!(DataHolderRegistry::new()
+            .read(&mut stream, DEFAULT_NEX_VERSION)
+            .is_err());
+    }
+
+    #[test]
+    fn redacts_login_tokens_in_debug_output() {
+        let login_data = AnyDataHolder::NintendoLoginData(NintendoLoginData {
+            token: NexString::from("secret token".to_string()),
+        });
+        let mut authentication_info = AuthenticationInfo::new();
+        authentication_info.token = NexString::from("secret token".to_string());
+        authentication_info.token_type = 1;
+        let authentication_info = AnyDataHolder::AuthenticationInfo(authentication_info);
+
+        for data_holder in [login_data, authentication_info] {
+            let debug = format!("{:?}", data_holder);
+            assert!(!debug.contains("secret"), "{}", debug);
+            assert!(debug.contains("token: <redacted>"), "{}", debug);
+        }
+    }
+
+    #[test]
+    fn shows_decoded_payloads_in_debug_output() {
+        let data_holder = read(&DataHolderRegistry::new(), &game_key("GameKey")).unwrap();
+        let debug = format!("{:?}", data_holder);
+
+        assert!(debug.starts_with("GameKey(GameKey {"), "{}", debug);
+        assert!(debug.contains("title_version: 1"), "{}", debug);
+    }
+}
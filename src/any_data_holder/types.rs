@@ -0,0 +1,243 @@
+use crate::request_trace::Redacted;
+use crate::structure::{
+    impl_endian_for_nex_structure, read_buffer, read_structure_content, write_field,
+    write_structure_content, Data, NexStructure, NexVersion,
+};
+use nex_rs::nex_types::{DateTime, NexBuffer, NexString};
+use no_std_io::{Error, StreamContainer, StreamReader};
+use std::fmt;
+
+#[derive(Default)]
+pub struct NintendoLoginData {
+    pub token: NexString,
+}
+
+impl fmt::Debug for NintendoLoginData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NintendoLoginData")
+            .field("token", &Redacted)
+            .finish()
+    }
+}
+
+impl NexStructure for NintendoLoginData {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        Data::read_structure(stream, nex_version)?;
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                token: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.token)?;
+
+        let mut data = Data {}.write_structure(nex_version)?;
+        data.extend(write_structure_content(
+            Self::STRUCTURE_VERSION,
+            nex_version,
+            content,
+        )?);
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MiiV2 {
+    pub name: NexString,
+    pub unknown_1: u8,
+    pub unknown_2: u8,
+    pub data: NexBuffer,
+    pub datetime: DateTime,
+}
+
+impl NexStructure for MiiV2 {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                name: stream.read_stream_le()?,
+                unknown_1: stream.read_stream_le()?,
+                unknown_2: stream.read_stream_le()?,
+                data: read_buffer(stream)?,
+                datetime: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.name)?;
+        write_field(&mut content, &self.unknown_1)?;
+        write_field(&mut content, &self.unknown_2)?;
+        write_field(&mut content, &self.data)?;
+        write_field(&mut content, &self.datetime)?;
+        write_structure_content(Self::STRUCTURE_VERSION, nex_version, content)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PrincipalBasicInfo {
+    pub pid: u32,
+    pub nnid: NexString,
+    pub mii: MiiV2,
+    pub unknown: u8,
+}
+
+impl NexStructure for PrincipalBasicInfo {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                pid: stream.read_stream_le()?,
+                nnid: stream.read_stream_le()?,
+                mii: MiiV2::read_structure(stream, nex_version)?,
+                unknown: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.pid)?;
+        write_field(&mut content, &self.nnid)?;
+        content.extend(self.mii.write_structure(nex_version)?);
+        write_field(&mut content, &self.unknown)?;
+        write_structure_content(Self::STRUCTURE_VERSION, nex_version, content)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NNAInfo {
+    pub principal_basic_info: PrincipalBasicInfo,
+    pub unknown_1: u8,
+    pub unknown_2: u8,
+}
+
+impl NexStructure for NNAInfo {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                principal_basic_info: PrincipalBasicInfo::read_structure(stream, nex_version)?,
+                unknown_1: stream.read_stream_le()?,
+                unknown_2: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        content.extend(self.principal_basic_info.write_structure(nex_version)?);
+        write_field(&mut content, &self.unknown_1)?;
+        write_field(&mut content, &self.unknown_2)?;
+        write_structure_content(Self::STRUCTURE_VERSION, nex_version, content)
+    }
+}
+
+#[derive(Default)]
+pub struct NintendoCreateAccountData {
+    pub nna_info: NNAInfo,
+    pub token: NexString,
+    pub birthday: DateTime,
+    pub unknown: u64,
+}
+
+impl fmt::Debug for NintendoCreateAccountData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NintendoCreateAccountData")
+            .field("nna_info", &self.nna_info)
+            .field("token", &Redacted)
+            .field("birthday", &self.birthday)
+            .field("unknown", &self.unknown)
+            .finish()
+    }
+}
+
+impl NexStructure for NintendoCreateAccountData {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        Data::read_structure(stream, nex_version)?;
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                nna_info: NNAInfo::read_structure(stream, nex_version)?,
+                token: stream.read_stream_le()?,
+                birthday: stream.read_stream_le()?,
+                unknown: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        content.extend(self.nna_info.write_structure(nex_version)?);
+        write_field(&mut content, &self.token)?;
+        write_field(&mut content, &self.birthday)?;
+        write_field(&mut content, &self.unknown)?;
+
+        let mut data = Data {}.write_structure(nex_version)?;
+        data.extend(write_structure_content(
+            Self::STRUCTURE_VERSION,
+            nex_version,
+            content,
+        )?);
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GameKey {
+    pub title_id: u64,
+    pub title_version: u16,
+}
+
+impl NexStructure for GameKey {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        Data::read_structure(stream, nex_version)?;
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                title_id: stream.read_stream_le()?,
+                title_version: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.title_id)?;
+        write_field(&mut content, &self.title_version)?;
+
+        let mut data = Data {}.write_structure(nex_version)?;
+        data.extend(write_structure_content(
+            Self::STRUCTURE_VERSION,
+            nex_version,
+            content,
+        )?);
+        Ok(data)
+    }
+}
+
+impl_endian_for_nex_structure!(
+    NintendoLoginData,
+    MiiV2,
+    PrincipalBasicInfo,
+    NNAInfo,
+    NintendoCreateAccountData,
+    GameKey,
+);
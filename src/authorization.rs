@@ -0,0 +1,458 @@
+use crate::client_context::SessionContext;
+use crate::datastore_usum::{DataStoreMethod, DATASTORE_PROTOCOL_ID};
+use crate::health::HEALTH_PROTOCOL_ID;
+use crate::matchmake_extension::{MatchmakeExtensionMethod, MATCHMAKE_EXTENSION_PROTOCOL_ID};
+use crate::middleware::{Middleware, RmcCall};
+use crate::monitoring::MONITORING_PROTOCOL_ID;
+use crate::ticket_granting::GuestPolicy;
+use nex_rs::client::ClientConnection;
+use nex_rs::nex_types::ResultCode;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    Guest,
+    User,
+    Admin,
+}
+
+type PolicyRule = Rc<dyn Fn(&SessionContext, &[u8]) -> bool>;
+
+/// Decides whether a session may call a method.
+#[derive(Clone)]
+pub enum MethodPolicy {
+    Allow,
+    Deny,
+    /// Requires the session's role to be at least this role.
+    RequireRole(Role),
+    /// Runs a rule against the session and the raw RMC parameters,
+    /// e.g. to only let a gathering's owner close it.
+    Rule(PolicyRule),
+}
+
+impl MethodPolicy {
+    pub fn rule(rule: impl Fn(&SessionContext, &[u8]) -> bool + 'static) -> Self {
+        Self::Rule(Rc::new(rule))
+    }
+
+    fn is_allowed(&self, role: Role, session: &SessionContext, parameters: &[u8]) -> bool {
+        match self {
+            Self::Allow => true,
+            Self::Deny => false,
+            Self::RequireRole(required_role) => role >= *required_role,
+            Self::Rule(rule) => rule(session, parameters),
+        }
+    }
+}
+
+impl fmt::Debug for MethodPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Allow => write!(f, "Allow"),
+            Self::Deny => write!(f, "Deny"),
+            Self::RequireRole(role) => f.debug_tuple("RequireRole").field(role).finish(),
+            Self::Rule(_) => write!(f, "Rule"),
+        }
+    }
+}
+
+/// Per protocol and per method policies checked before a handler runs.
+/// Method policies take precedence over protocol policies, which take precedence
+/// over the default policy.
+#[derive(Debug, Clone)]
+pub struct AuthorizationPolicy {
+    default_policy: MethodPolicy,
+    protocol_policies: HashMap<u8, MethodPolicy>,
+    method_policies: HashMap<(u8, u32), MethodPolicy>,
+    roles: HashMap<u32, Role>,
+}
+
+impl Default for AuthorizationPolicy {
+    /// Allows everything. The guest account of the provider's
+    /// [GuestPolicy](crate::ticket_granting::GuestPolicy) is given [Role::Guest].
+    fn default() -> Self {
+        Self {
+            default_policy: MethodPolicy::Allow,
+            protocol_policies: HashMap::new(),
+            method_policies: HashMap::new(),
+            roles: HashMap::new(),
+        }
+    }
+}
+
+impl AuthorizationPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps guests away from methods that modify shared state and
+    /// only lets admins call the Health and Monitoring protocols.
+    pub fn recommended() -> Self {
+        let mut policy = Self::new();
+        let user_only = MethodPolicy::RequireRole(Role::User);
+
+        for method in [
+            DataStoreMethod::RateObject,
+            DataStoreMethod::PostMetaBinary,
+            DataStoreMethod::ChangeMetas,
+            DataStoreMethod::PrepareUploadPokemon,
+            DataStoreMethod::UploadPokemon,
+            DataStoreMethod::PrepareTradePokemon,
+            DataStoreMethod::TradePokemon,
+            DataStoreMethod::DeletePokemon,
+        ] {
+            policy.set_method_policy(DATASTORE_PROTOCOL_ID, method, user_only.clone());
+        }
+
+        policy.set_protocol_policy(MATCHMAKE_EXTENSION_PROTOCOL_ID, user_only.clone());
+        policy.set_method_policy(
+            MATCHMAKE_EXTENSION_PROTOCOL_ID,
+            MatchmakeExtensionMethod::BrowseMatchmakeSession,
+            MethodPolicy::Allow,
+        );
+
+        policy.set_protocol_policy(HEALTH_PROTOCOL_ID, MethodPolicy::RequireRole(Role::Admin));
+        policy.set_protocol_policy(
+            MONITORING_PROTOCOL_ID,
+            MethodPolicy::RequireRole(Role::Admin),
+        );
+
+        policy
+    }
+
+    pub fn set_default_policy(&mut self, policy: MethodPolicy) {
+        self.default_policy = policy;
+    }
+
+    pub fn set_protocol_policy(&mut self, protocol_id: u8, policy: MethodPolicy) {
+        self.protocol_policies.insert(protocol_id, policy);
+    }
+
+    pub fn set_method_policy(
+        &mut self,
+        protocol_id: u8,
+        method: impl Into<u32>,
+        policy: MethodPolicy,
+    ) {
+        self.method_policies
+            .insert((protocol_id, method.into()), policy);
+    }
+
+    pub fn set_role(&mut self, pid: u32, role: Role) {
+        self.roles.insert(pid, role);
+    }
+
+    /// Returns the pid's role, defaulting to [Role::User].
+    pub fn get_role(&self, pid: u32) -> Role {
+        self.roles.get(&pid).copied().unwrap_or(Role::User)
+    }
+
+    pub fn get_policy(&self, protocol_id: u8, method_id: u32) -> &MethodPolicy {
+        self.method_policies
+            .get(&(protocol_id, method_id))
+            .or_else(|| self.protocol_policies.get(&protocol_id))
+            .unwrap_or(&self.default_policy)
+    }
+
+    pub fn check(
+        &self,
+        session: &SessionContext,
+        protocol_id: u8,
+        method_id: u32,
+        parameters: &[u8],
+    ) -> Result<(), ResultCode> {
+        let role = self.get_role(session.pid);
+        self.check_role(role, session, protocol_id, method_id, parameters)
+    }
+
+    /// Checks the call as `role` instead of the session's configured role.
+    pub fn check_role(
+        &self,
+        role: Role,
+        session: &SessionContext,
+        protocol_id: u8,
+        method_id: u32,
+        parameters: &[u8],
+    ) -> Result<(), ResultCode> {
+        if !self
+            .get_policy(protocol_id, method_id)
+            .is_allowed(role, session, parameters)
+        {
+            return Err(ResultCode::RendezVous_PermissionDenied);
+        }
+
+        Ok(())
+    }
+}
+
+/// Rejects calls to methods requiring a session from clients without one, then checks the
+/// session against the guest policy and the authorization policy, if set.
+/// Run by the protocol dispatchers with the provider's policies.
+pub struct SessionAuthorization<'a> {
+    guest_policy: Option<&'a dyn GuestPolicy>,
+    authorization_policy: Option<&'a AuthorizationPolicy>,
+}
+
+impl<'a> SessionAuthorization<'a> {
+    pub fn new(
+        guest_policy: Option<&'a dyn GuestPolicy>,
+        authorization_policy: Option<&'a AuthorizationPolicy>,
+    ) -> Self {
+        Self {
+            guest_policy,
+            authorization_policy,
+        }
+    }
+}
+
+impl<'a> Middleware for SessionAuthorization<'a> {
+    fn before(&self, _client: &ClientConnection, call: &RmcCall) -> Result<(), ResultCode> {
+        if !call.requires_session {
+            return Ok(());
+        }
+
+        let session = call
+            .session
+            .as_ref()
+            .ok_or(ResultCode::RendezVous_NotAuthenticated)?;
+
+        let is_guest = match self.guest_policy {
+            Some(guest_policy) => {
+                guest_policy.check_call(session.pid, call.protocol_id, call.method_id)?;
+                guest_policy.is_guest(session.pid)
+            }
+            None => false,
+        };
+
+        if let Some(policy) = self.authorization_policy {
+            let role = if is_guest {
+                Role::Guest
+            } else {
+                policy.get_role(session.pid)
+            };
+            policy.check_role(
+                role,
+                session,
+                call.protocol_id,
+                call.method_id,
+                call.parameters,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::DEFAULT_NEX_VERSION;
+    use crate::ticket_granting::{GuestConfig, GUEST_PID};
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::time::Instant;
+
+    fn session(pid: u32) -> SessionContext {
+        SessionContext {
+            pid,
+            connection_id: 1,
+            title_id: None,
+            nex_version: DEFAULT_NEX_VERSION,
+            station_urls: vec![],
+        }
+    }
+
+    fn call(
+        session: Option<SessionContext>,
+        protocol_id: u8,
+        method_id: u32,
+        requires_session: bool,
+    ) -> RmcCall<'static> {
+        RmcCall {
+            protocol_id,
+            method_id,
+            call_id: 1,
+            parameters: &[],
+            session,
+            requires_session,
+            received_at: Instant::now(),
+        }
+    }
+
+    fn connect() -> ClientConnection {
+        ClientConnection::new(
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 1)),
+            Default::default(),
+        )
+    }
+
+    #[test]
+    fn prefers_method_over_protocol_over_default_policies() {
+        let mut policy = AuthorizationPolicy::new();
+        policy.set_default_policy(MethodPolicy::Deny);
+        policy.set_protocol_policy(DATASTORE_PROTOCOL_ID, MethodPolicy::Allow);
+        policy.set_method_policy(
+            DATASTORE_PROTOCOL_ID,
+            DataStoreMethod::PostMetaBinary,
+            MethodPolicy::Deny,
+        );
+        let session = session(1800000000);
+
+        assert_eq!(
+            policy.check(
+                &session,
+                DATASTORE_PROTOCOL_ID,
+                DataStoreMethod::PostMetaBinary.into(),
+                &[]
+            ),
+            Err(ResultCode::RendezVous_PermissionDenied)
+        );
+        assert_eq!(
+            policy.check(
+                &session,
+                DATASTORE_PROTOCOL_ID,
+                DataStoreMethod::GetMetas.into(),
+                &[]
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            policy.check(&session, HEALTH_PROTOCOL_ID, 1, &[]),
+            Err(ResultCode::RendezVous_PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn requires_roles_at_least_as_high() {
+        let mut policy = AuthorizationPolicy::new();
+        policy.set_default_policy(MethodPolicy::RequireRole(Role::User));
+        policy.set_role(1, Role::Guest);
+        policy.set_role(2, Role::Admin);
+
+        assert_eq!(
+            policy.check(&session(1), HEALTH_PROTOCOL_ID, 1, &[]),
+            Err(ResultCode::RendezVous_PermissionDenied)
+        );
+        assert_eq!(
+            policy.check(&session(2), HEALTH_PROTOCOL_ID, 1, &[]),
+            Ok(())
+        );
+        assert_eq!(policy.get_role(3), Role::User);
+        assert_eq!(
+            policy.check(&session(3), HEALTH_PROTOCOL_ID, 1, &[]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn runs_rules_against_the_session_and_parameters() {
+        let mut policy = AuthorizationPolicy::new();
+        policy.set_default_policy(MethodPolicy::rule(|session, parameters| {
+            parameters == session.pid.to_le_bytes()
+        }));
+        let session = session(1800000000);
+
+        assert_eq!(
+            policy.check(
+                &session,
+                HEALTH_PROTOCOL_ID,
+                1,
+                &1800000000u32.to_le_bytes()
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            policy.check(&session, HEALTH_PROTOCOL_ID, 1, &1u32.to_le_bytes()),
+            Err(ResultCode::RendezVous_PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn recommended_keeps_guests_and_users_in_their_lanes() {
+        let policy = AuthorizationPolicy::recommended();
+        let session = session(1800000000);
+        let post_meta_binary = DataStoreMethod::PostMetaBinary.into();
+
+        assert_eq!(
+            policy.check_role(
+                Role::Guest,
+                &session,
+                DATASTORE_PROTOCOL_ID,
+                post_meta_binary,
+                &[]
+            ),
+            Err(ResultCode::RendezVous_PermissionDenied)
+        );
+        assert_eq!(
+            policy.check(&session, DATASTORE_PROTOCOL_ID, post_meta_binary, &[]),
+            Ok(())
+        );
+        assert_eq!(
+            policy.check_role(
+                Role::Guest,
+                &session,
+                MATCHMAKE_EXTENSION_PROTOCOL_ID,
+                MatchmakeExtensionMethod::BrowseMatchmakeSession.into(),
+                &[]
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            policy.check(&session, MONITORING_PROTOCOL_ID, 1, &[]),
+            Err(ResultCode::RendezVous_PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn checks_guest_sessions_with_the_guest_role() {
+        let mut guest_config = GuestConfig::new();
+        guest_config.protocol_ids.push(DATASTORE_PROTOCOL_ID);
+        let policy = AuthorizationPolicy::recommended();
+        let authorization = SessionAuthorization::new(Some(&guest_config), Some(&policy));
+        let client = connect();
+
+        let guest_call = call(
+            Some(session(GUEST_PID)),
+            DATASTORE_PROTOCOL_ID,
+            DataStoreMethod::PostMetaBinary.into(),
+            true,
+        );
+        assert_eq!(
+            authorization.before(&client, &guest_call),
+            Err(ResultCode::RendezVous_PermissionDenied)
+        );
+
+        let user_call = call(
+            Some(session(1800000000)),
+            DATASTORE_PROTOCOL_ID,
+            DataStoreMethod::PostMetaBinary.into(),
+            true,
+        );
+        assert_eq!(authorization.before(&client, &user_call), Ok(()));
+    }
+
+    #[test]
+    fn only_requires_sessions_for_session_methods() {
+        let mut policy = AuthorizationPolicy::new();
+        policy.set_default_policy(MethodPolicy::Deny);
+        let authorization = SessionAuthorization::new(None, Some(&policy));
+        let client = connect();
+
+        assert_eq!(
+            authorization.before(&client, &call(None, DATASTORE_PROTOCOL_ID, 1, false)),
+            Ok(())
+        );
+        assert_eq!(
+            authorization.before(&client, &call(None, DATASTORE_PROTOCOL_ID, 1, true)),
+            Err(ResultCode::RendezVous_NotAuthenticated)
+        );
+        assert_eq!(
+            authorization.before(
+                &client,
+                &call(Some(session(1800000000)), DATASTORE_PROTOCOL_ID, 1, true)
+            ),
+            Err(ResultCode::RendezVous_PermissionDenied)
+        );
+    }
+}
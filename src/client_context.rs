@@ -0,0 +1,457 @@
+use crate::any_data_holder::{AnyDataHolder, DataHolderRegistry};
+use crate::authorization::AuthorizationPolicy;
+use crate::metrics::RmcMetrics;
+use crate::middleware::MiddlewareChain;
+use crate::rate_limit::RateLimiter;
+use crate::structure::NexVersion;
+use crate::ticket_granting::GuestPolicy;
+use crate::title_profile::{TitleProfile, TitleProfileRegistry};
+use crate::token::TokenValidator;
+use nex_rs::client::ClientConnection;
+use nex_rs::nex_types::ResultCode;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// The authenticated session a client established with SecureConnection Register or RegisterEx.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionContext {
+    pub pid: u32,
+    pub connection_id: u32,
+    pub title_id: Option<u64>,
+    pub nex_version: NexVersion,
+    pub station_urls: Vec<String>,
+}
+
+/// State tracked for a single client across protocol calls.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientContext {
+    pub title_id: Option<u64>,
+    pub title_profile: TitleProfile,
+    pub session: Option<SessionContext>,
+}
+
+/// Identifies one connection. Addresses are reused by reconnecting clients,
+/// which get a new connection id.
+type ClientKey = (SocketAddr, u32);
+
+fn get_client_key(client: &ClientConnection) -> ClientKey {
+    (client.get_address(), client.get_connection_id())
+}
+
+#[derive(Debug)]
+struct ClientContextEntry {
+    context: ClientContext,
+    last_seen: Instant,
+}
+
+/// Per client contexts, keyed by the client's address and connection id.
+/// Contexts live until the client disconnects or goes idle,
+/// see [ClientContextProvider::disconnect_client] and [ClientContextProvider::remove_idle_clients].
+#[derive(Debug, Default)]
+pub struct ClientContextTable {
+    title_profiles: TitleProfileRegistry,
+    data_holders: DataHolderRegistry,
+    contexts: RefCell<HashMap<ClientKey, ClientContextEntry>>,
+}
+
+impl ClientContextTable {
+    pub fn new(title_profiles: TitleProfileRegistry) -> Self {
+        Self::with_data_holders(title_profiles, DataHolderRegistry::new())
+    }
+
+    pub fn with_data_holders(
+        title_profiles: TitleProfileRegistry,
+        data_holders: DataHolderRegistry,
+    ) -> Self {
+        Self {
+            title_profiles,
+            data_holders,
+            contexts: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn get_title_profiles(&self) -> &TitleProfileRegistry {
+        &self.title_profiles
+    }
+
+    pub fn get_data_holders(&self) -> &DataHolderRegistry {
+        &self.data_holders
+    }
+
+    fn get_default_context(&self) -> ClientContext {
+        ClientContext {
+            title_id: None,
+            title_profile: self.title_profiles.get_default_profile().clone(),
+            session: None,
+        }
+    }
+
+    /// Returns the client's context, or a context using the default profile
+    /// if the client hasn't been seen yet.
+    pub fn get(&self, client: &ClientConnection) -> ClientContext {
+        self.contexts
+            .borrow()
+            .get(&get_client_key(client))
+            .map(|entry| entry.context.clone())
+            .unwrap_or_else(|| self.get_default_context())
+    }
+
+    pub fn update(&self, client: &ClientConnection, f: impl FnOnce(&mut ClientContext)) {
+        let mut contexts = self.contexts.borrow_mut();
+        let entry = contexts
+            .entry(get_client_key(client))
+            .or_insert_with(|| ClientContextEntry {
+                context: self.get_default_context(),
+                last_seen: Instant::now(),
+            });
+        entry.last_seen = Instant::now();
+        f(&mut entry.context);
+    }
+
+    /// Marks a known client as active, e.g. when it makes a call.
+    pub fn touch(&self, client: &ClientConnection) {
+        self.touch_at(client, Instant::now())
+    }
+
+    pub fn touch_at(&self, client: &ClientConnection, now: Instant) {
+        if let Some(entry) = self.contexts.borrow_mut().get_mut(&get_client_key(client)) {
+            entry.last_seen = now;
+        }
+    }
+
+    /// Attaches the registered profile for `title_id` to the client.
+    pub fn set_title_id(&self, client: &ClientConnection, title_id: u64) {
+        let title_profile = self.title_profiles.get_profile(title_id).clone();
+        self.update(client, |context| {
+            if let Some(session) = context.session.as_mut() {
+                session.title_id = Some(title_id);
+                session.nex_version = title_profile.nex_version;
+            }
+
+            context.title_id = Some(title_id);
+            context.title_profile = title_profile;
+        });
+    }
+
+    /// Records the client's session using its current title and profile.
+    pub fn start_session(
+        &self,
+        client: &ClientConnection,
+        station_urls: Vec<String>,
+    ) -> SessionContext {
+        self.start_session_with_title(client, station_urls, None)
+    }
+
+    /// Records the client's session, first attaching `title_id` if given. Registration can
+    /// change the connection id, so titles resolved while registering are attached here,
+    /// under the key the session is stored with.
+    pub fn start_session_with_title(
+        &self,
+        client: &ClientConnection,
+        station_urls: Vec<String>,
+        title_id: Option<u64>,
+    ) -> SessionContext {
+        if let Some(title_id) = title_id {
+            self.set_title_id(client, title_id);
+        }
+
+        let context = self.get(client);
+        let session = SessionContext {
+            pid: client.get_pid(),
+            connection_id: client.get_connection_id(),
+            title_id: context.title_id,
+            nex_version: context.title_profile.nex_version,
+            station_urls,
+        };
+
+        self.update(client, |context| context.session = Some(session.clone()));
+        session
+    }
+
+    /// Returns the session of a connected pid, e.g. to look up a host's station urls.
+    pub fn find_session(&self, pid: u32) -> Option<SessionContext> {
+        self.contexts
+            .borrow()
+            .values()
+            .filter_map(|entry| entry.context.session.as_ref())
+            .find(|session| session.pid == pid)
+            .cloned()
+    }
+
+    /// Returns the pids with a session, e.g. to sweep state of disconnected players in one pass.
+    pub fn get_session_pids(&self) -> HashSet<u32> {
+        self.contexts
+            .borrow()
+            .values()
+            .filter_map(|entry| entry.context.session.as_ref())
+            .map(|session| session.pid)
+            .collect()
+    }
+
+    pub fn remove(&self, client: &ClientConnection) -> Option<ClientContext> {
+        self.contexts
+            .borrow_mut()
+            .remove(&get_client_key(client))
+            .map(|entry| entry.context)
+    }
+
+    /// Removes the contexts of clients that haven't been seen for `timeout`.
+    pub fn remove_idle(&self, timeout: Duration) -> Vec<ClientContext> {
+        self.remove_idle_at(timeout, Instant::now())
+    }
+
+    pub fn remove_idle_at(&self, timeout: Duration, now: Instant) -> Vec<ClientContext> {
+        let mut removed = vec![];
+
+        self.contexts.borrow_mut().retain(|_, entry| {
+            let is_live = now.saturating_duration_since(entry.last_seen) <= timeout;
+
+            if !is_live {
+                removed.push(entry.context.clone());
+            }
+
+            is_live
+        });
+
+        removed
+    }
+}
+
+pub trait ClientContextProvider {
+    fn get_client_context_table(&self) -> &ClientContextTable;
+
+    fn get_client_context(&self, client: &ClientConnection) -> ClientContext {
+        self.get_client_context_table().get(client)
+    }
+
+    fn get_title_profile(&self, client: &ClientConnection) -> TitleProfile {
+        self.get_client_context(client).title_profile
+    }
+
+    fn get_session(&self, client: &ClientConnection) -> Option<SessionContext> {
+        self.get_client_context(client).session
+    }
+
+    /// Returns the client's session, rejecting clients that haven't registered with the secure server.
+    fn require_session(&self, client: &ClientConnection) -> Result<SessionContext, ResultCode> {
+        self.get_session(client)
+            .ok_or(ResultCode::RendezVous_NotAuthenticated)
+    }
+
+    /// Validates the tokens sent with LoginEx and RegisterEx when set,
+    /// attaching the title they were issued for to the client.
+    /// Without a validator titles only come from a GameKey, see
+    /// [Self::get_title_id_from_data_holder].
+    fn get_token_validator(&self) -> Option<&dyn TokenValidator> {
+        None
+    }
+
+    /// Returns the title a LoginEx or RegisterEx DataHolder was issued for, validating it.
+    /// Without a validator tokens can't be trusted, so only a GameKey names the title and
+    /// clients sending anything else keep the default profile.
+    fn get_title_id_from_data_holder(
+        &self,
+        data_holder: &AnyDataHolder,
+    ) -> Result<Option<u64>, ResultCode> {
+        if let Some(validator) = self.get_token_validator() {
+            return validator.get_title_id(data_holder);
+        }
+
+        match data_holder {
+            AnyDataHolder::GameKey(game_key) => Ok(Some(game_key.title_id)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Attaches the title a LoginEx DataHolder was issued for to the client.
+    /// RegisterEx attaches its title when the session starts instead.
+    fn set_title_from_data_holder(
+        &self,
+        client: &ClientConnection,
+        data_holder: &AnyDataHolder,
+    ) -> Result<(), ResultCode> {
+        if let Some(title_id) = self.get_title_id_from_data_holder(data_holder)? {
+            self.get_client_context_table()
+                .set_title_id(client, title_id);
+        }
+
+        Ok(())
+    }
+
+    /// Limits what the guest account may call when set. Its guest account is also
+    /// the only account given [Role::Guest](crate::authorization::Role::Guest).
+    fn get_guest_policy(&self) -> Option<&dyn GuestPolicy> {
+        None
+    }
+
+    /// Checked for calls with a session by every protocol dispatcher when set.
+    fn get_authorization_policy(&self) -> Option<&AuthorizationPolicy> {
+        None
+    }
+
+    /// Checked by every protocol dispatcher when set.
+    fn get_rate_limiter(&self) -> Option<&RateLimiter> {
+        None
+    }
+
+    /// Records every dispatched request when set.
+    fn get_metrics(&self) -> Option<&RmcMetrics> {
+        None
+    }
+
+    /// Run around every request by the protocol dispatchers when set.
+    fn get_middleware_chain(&self) -> Option<&MiddlewareChain> {
+        None
+    }
+
+    /// Forgets a client's context and its rate limits. Call this from the server's
+    /// disconnect and timeout handling so a reused address doesn't inherit the session.
+    fn disconnect_client(&self, client: &ClientConnection) -> Option<ClientContext> {
+        let context = self.get_client_context_table().remove(client)?;
+        self.forget_sessions(std::slice::from_ref(&context));
+        Some(context)
+    }
+
+    /// Forgets clients that haven't made a call for `timeout`, for servers that
+    /// can't observe disconnects. Returns the removed contexts.
+    fn remove_idle_clients(&self, timeout: Duration) -> Vec<ClientContext> {
+        let contexts = self.get_client_context_table().remove_idle(timeout);
+        self.forget_sessions(&contexts);
+        contexts
+    }
+
+    /// Drops the rate limits of sessions whose pid has no other connection.
+    fn forget_sessions(&self, contexts: &[ClientContext]) {
+        let rate_limiter = match self.get_rate_limiter() {
+            Some(rate_limiter) => rate_limiter,
+            None => return,
+        };
+
+        for session in contexts
+            .iter()
+            .filter_map(|context| context.session.as_ref())
+        {
+            if self
+                .get_client_context_table()
+                .find_session(session.pid)
+                .is_none()
+            {
+                rate_limiter.remove_pid(session.pid);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::any_data_holder::{GameKey, NintendoLoginData};
+    use crate::title_profile::POKEMON_ULTRA_SUN_TITLE_ID;
+    use std::net::Ipv4Addr;
+
+    fn connect(pid: u32, port: u16, connection_id: u32) -> ClientConnection {
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let mut client = ClientConnection::new(address, Default::default());
+        client.set_pid(pid);
+        client.set_connection_id(connection_id);
+        client
+    }
+
+    #[test]
+    fn reconnects_get_a_new_context() {
+        let table = ClientContextTable::default();
+        let client = connect(1800000000, 1, 1);
+        table.start_session(&client, vec![]);
+        let reconnected = connect(1800000000, 1, 2);
+
+        assert!(table.get(&client).session.is_some());
+        assert_eq!(table.get(&reconnected).session, None);
+    }
+
+    #[test]
+    fn attaches_titles_resolved_while_registering_to_the_session() {
+        let title_id = 0x0004000000123400;
+        let profile = TitleProfile::new("Test", vec![title_id], NexVersion::new(3, 5, 0));
+        let mut title_profiles = TitleProfileRegistry::new();
+        title_profiles.register(profile.clone());
+        let table = ClientContextTable::new(title_profiles);
+
+        // Registering assigns the connection id the session is stored with
+        let mut client = connect(1800000000, 1, 0);
+        client.set_connection_id(5);
+        let session = table.start_session_with_title(&client, vec![], Some(title_id));
+
+        assert_eq!(session.title_id, Some(title_id));
+        assert_eq!(session.nex_version, profile.nex_version);
+        assert_eq!(table.get(&client).title_profile, profile);
+        assert_eq!(
+            table
+                .find_session(1800000000)
+                .map(|session| session.connection_id),
+            Some(5)
+        );
+    }
+
+    #[test]
+    fn resolves_titles_from_game_keys_without_a_validator() {
+        struct Provider(ClientContextTable);
+
+        impl ClientContextProvider for Provider {
+            fn get_client_context_table(&self) -> &ClientContextTable {
+                &self.0
+            }
+        }
+
+        let provider = Provider(ClientContextTable::default());
+        let game_key = AnyDataHolder::GameKey(GameKey {
+            title_id: POKEMON_ULTRA_SUN_TITLE_ID,
+            title_version: 0,
+        });
+        let login_data = AnyDataHolder::NintendoLoginData(NintendoLoginData::default());
+
+        assert_eq!(
+            provider.get_title_id_from_data_holder(&game_key),
+            Ok(Some(POKEMON_ULTRA_SUN_TITLE_ID))
+        );
+        assert_eq!(
+            provider.get_title_id_from_data_holder(&login_data),
+            Ok(None)
+        );
+
+        let client = connect(1800000000, 1, 1);
+        provider
+            .set_title_from_data_holder(&client, &game_key)
+            .unwrap();
+        assert_eq!(provider.get_title_profile(&client), TitleProfile::usum());
+    }
+
+    #[test]
+    fn removes_disconnected_clients() {
+        let table = ClientContextTable::default();
+        let client = connect(1800000000, 1, 1);
+        table.start_session(&client, vec![]);
+
+        assert!(table.remove(&client).is_some());
+        assert_eq!(table.get(&client).session, None);
+        assert_eq!(table.find_session(1800000000), None);
+    }
+
+    #[test]
+    fn removes_idle_clients() {
+        let table = ClientContextTable::default();
+        let idle = connect(1800000000, 1, 1);
+        let active = connect(1800000001, 2, 2);
+        table.start_session(&idle, vec![]);
+        table.start_session(&active, vec![]);
+
+        let now = Instant::now() + Duration::from_secs(60);
+        table.touch_at(&active, now);
+
+        let removed = table.remove_idle_at(Duration::from_secs(30), now);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(table.find_session(1800000000), None);
+        assert!(table.find_session(1800000001).is_some());
+    }
+}
@@ -1,3 +1,6 @@
+mod persistence;
+pub use persistence::*;
+
 mod protocol;
 pub use protocol::*;
 
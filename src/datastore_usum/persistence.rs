@@ -0,0 +1,310 @@
+use crate::datastore_usum::{
+    DataStoreGetMetaParam, DataStorePersistenceInitParam, DataStorePersistenceTarget,
+};
+use nex_rs::nex_types::ResultCode;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+pub const DEFAULT_PERSISTENCE_SLOT_LIMIT: u16 = 16;
+pub const INVALID_PERSISTENCE_SLOT_ID: u16 = 0xFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersistenceAssignment {
+    /// The slot was empty before this object was assigned to it,
+    /// or already held this object.
+    Empty,
+    /// The previous object was removed from the slot and should be deleted.
+    Delete(u64),
+    /// The previous object was removed from the slot but should be kept.
+    Keep(u64),
+}
+
+#[derive(Debug)]
+pub struct DataStorePersistenceManager {
+    slot_limit: u16,
+    slots: RefCell<HashMap<(u32, u16), u64>>,
+}
+
+impl Default for DataStorePersistenceManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DataStorePersistenceManager {
+    pub fn new() -> Self {
+        Self::with_slot_limit(DEFAULT_PERSISTENCE_SLOT_LIMIT)
+    }
+
+    pub fn with_slot_limit(slot_limit: u16) -> Self {
+        Self {
+            slot_limit,
+            slots: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn get_slot_limit(&self) -> u16 {
+        self.slot_limit
+    }
+
+    pub fn get_data_id(&self, owner_id: u32, persistence_slot_id: u16) -> Option<u64> {
+        self.slots
+            .borrow()
+            .get(&(owner_id, persistence_slot_id))
+            .copied()
+    }
+
+    pub fn get_target_data_id(&self, target: &DataStorePersistenceTarget) -> Option<u64> {
+        self.get_data_id(target.owner_id, target.persistence_slot_id)
+    }
+
+    /// Rejects init params requesting a slot past the limit, so posts can be
+    /// checked before their object is created.
+    pub fn check_init_param(
+        &self,
+        param: &DataStorePersistenceInitParam,
+    ) -> Result<(), ResultCode> {
+        let persistence_slot_id = param.persistence_slot_id;
+
+        if persistence_slot_id != INVALID_PERSISTENCE_SLOT_ID
+            && persistence_slot_id >= self.slot_limit
+        {
+            return Err(ResultCode::DataStore_InvalidArgument);
+        }
+
+        Ok(())
+    }
+
+    /// Assigns a newly posted object to the slot requested by its init param.
+    /// Objects posted without a persistence slot are left untracked.
+    pub fn assign(
+        &self,
+        owner_id: u32,
+        data_id: u64,
+        param: &DataStorePersistenceInitParam,
+    ) -> Result<PersistenceAssignment, ResultCode> {
+        self.check_init_param(param)?;
+        let persistence_slot_id = param.persistence_slot_id;
+
+        if persistence_slot_id == INVALID_PERSISTENCE_SLOT_ID {
+            return Ok(PersistenceAssignment::Empty);
+        }
+
+        let previous = self
+            .slots
+            .borrow_mut()
+            .insert((owner_id, persistence_slot_id), data_id);
+
+        let assignment = match previous {
+            None => PersistenceAssignment::Empty,
+            Some(previous) if previous == data_id => PersistenceAssignment::Empty,
+            Some(previous) if param.delete_last_object => PersistenceAssignment::Delete(previous),
+            Some(previous) => PersistenceAssignment::Keep(previous),
+        };
+
+        Ok(assignment)
+    }
+
+    /// Frees the slot holding the given object, if any.
+    pub fn remove_data_id(&self, data_id: u64) -> Option<DataStorePersistenceTarget> {
+        let mut slots = self.slots.borrow_mut();
+        let key = slots
+            .iter()
+            .find(|(_, slot_data_id)| **slot_data_id == data_id)
+            .map(|(key, _)| *key)?;
+
+        slots.remove(&key);
+
+        Some(DataStorePersistenceTarget {
+            owner_id: key.0,
+            persistence_slot_id: key.1,
+        })
+    }
+
+    pub fn remove_owner(&self, owner_id: u32) -> Vec<u64> {
+        let mut slots = self.slots.borrow_mut();
+        let keys: Vec<(u32, u16)> = slots
+            .keys()
+            .filter(|(slot_owner_id, _)| *slot_owner_id == owner_id)
+            .copied()
+            .collect();
+
+        keys.iter().filter_map(|key| slots.remove(key)).collect()
+    }
+
+    /// Resolves the data id a GetMeta request refers to.
+    /// A data id of 0 means the object is addressed by its persistence target.
+    pub fn resolve_get_meta_param(&self, param: &DataStoreGetMetaParam) -> Result<u64, ResultCode> {
+        if param.data_id != 0 {
+            return Ok(param.data_id);
+        }
+
+        let target = &param.persistence_target;
+
+        if target.persistence_slot_id >= self.slot_limit {
+            return Err(ResultCode::DataStore_InvalidArgument);
+        }
+
+        self.get_target_data_id(target)
+            .ok_or(ResultCode::DataStore_NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_param(
+        persistence_slot_id: u16,
+        delete_last_object: bool,
+    ) -> DataStorePersistenceInitParam {
+        DataStorePersistenceInitParam {
+            persistence_slot_id,
+            delete_last_object,
+        }
+    }
+
+    fn get_meta_param(
+        data_id: u64,
+        owner_id: u32,
+        persistence_slot_id: u16,
+    ) -> DataStoreGetMetaParam {
+        DataStoreGetMetaParam {
+            data_id,
+            persistence_target: DataStorePersistenceTarget {
+                owner_id,
+                persistence_slot_id,
+            },
+            result_option: 0,
+            access_password: 0,
+        }
+    }
+
+    #[test]
+    fn assign_to_empty_slot() {
+        let manager = DataStorePersistenceManager::new();
+
+        assert_eq!(
+            manager.assign(1, 10, &init_param(0, true)),
+            Ok(PersistenceAssignment::Empty)
+        );
+        assert_eq!(manager.get_data_id(1, 0), Some(10));
+    }
+
+    #[test]
+    fn assign_replaces_previous_object() {
+        let manager = DataStorePersistenceManager::new();
+        manager.assign(1, 10, &init_param(0, true)).unwrap();
+
+        assert_eq!(
+            manager.assign(1, 11, &init_param(0, true)),
+            Ok(PersistenceAssignment::Delete(10))
+        );
+        assert_eq!(
+            manager.assign(1, 12, &init_param(0, false)),
+            Ok(PersistenceAssignment::Keep(11))
+        );
+        assert_eq!(manager.get_data_id(1, 0), Some(12));
+    }
+
+    #[test]
+    fn assign_same_object_again_deletes_nothing() {
+        let manager = DataStorePersistenceManager::new();
+        manager.assign(1, 10, &init_param(0, true)).unwrap();
+
+        assert_eq!(
+            manager.assign(1, 10, &init_param(0, true)),
+            Ok(PersistenceAssignment::Empty)
+        );
+        assert_eq!(manager.get_data_id(1, 0), Some(10));
+    }
+
+    #[test]
+    fn assign_without_slot_is_untracked() {
+        let manager = DataStorePersistenceManager::new();
+
+        assert_eq!(
+            manager.assign(1, 10, &init_param(INVALID_PERSISTENCE_SLOT_ID, true)),
+            Ok(PersistenceAssignment::Empty)
+        );
+        assert_eq!(manager.remove_data_id(10), None);
+    }
+
+    #[test]
+    fn assign_rejects_slot_past_limit() {
+        let manager = DataStorePersistenceManager::with_slot_limit(2);
+
+        assert_eq!(
+            manager.assign(1, 10, &init_param(2, true)),
+            Err(ResultCode::DataStore_InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn check_init_param_rejects_slot_past_limit() {
+        let manager = DataStorePersistenceManager::with_slot_limit(2);
+
+        assert_eq!(manager.check_init_param(&init_param(1, true)), Ok(()));
+        assert_eq!(
+            manager.check_init_param(&init_param(INVALID_PERSISTENCE_SLOT_ID, true)),
+            Ok(())
+        );
+        assert_eq!(
+            manager.check_init_param(&init_param(2, true)),
+            Err(ResultCode::DataStore_InvalidArgument)
+        );
+        assert_eq!(manager.get_data_id(0, 2), None);
+    }
+
+    #[test]
+    fn remove_data_id_frees_slot() {
+        let manager = DataStorePersistenceManager::new();
+        manager.assign(1, 10, &init_param(3, true)).unwrap();
+
+        assert_eq!(
+            manager.remove_data_id(10),
+            Some(DataStorePersistenceTarget {
+                owner_id: 1,
+                persistence_slot_id: 3,
+            })
+        );
+        assert_eq!(manager.get_data_id(1, 3), None);
+    }
+
+    #[test]
+    fn remove_owner_frees_only_their_slots() {
+        let manager = DataStorePersistenceManager::new();
+        manager.assign(1, 10, &init_param(0, true)).unwrap();
+        manager.assign(1, 11, &init_param(1, true)).unwrap();
+        manager.assign(2, 20, &init_param(0, true)).unwrap();
+
+        let mut removed = manager.remove_owner(1);
+        removed.sort_unstable();
+
+        assert_eq!(removed, vec![10, 11]);
+        assert_eq!(manager.get_data_id(2, 0), Some(20));
+    }
+
+    #[test]
+    fn resolve_get_meta_param() {
+        let manager = DataStorePersistenceManager::new();
+        manager.assign(1, 10, &init_param(4, true)).unwrap();
+
+        assert_eq!(
+            manager.resolve_get_meta_param(&get_meta_param(99, 0, 0)),
+            Ok(99)
+        );
+        assert_eq!(
+            manager.resolve_get_meta_param(&get_meta_param(0, 1, 4)),
+            Ok(10)
+        );
+        assert_eq!(
+            manager.resolve_get_meta_param(&get_meta_param(0, 1, 5)),
+            Err(ResultCode::DataStore_NotFound)
+        );
+        assert_eq!(
+            manager.resolve_get_meta_param(&get_meta_param(0, 1, DEFAULT_PERSISTENCE_SLOT_LIMIT)),
+            Err(ResultCode::DataStore_InvalidArgument)
+        );
+    }
+}
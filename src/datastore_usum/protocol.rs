@@ -1,16 +1,24 @@
+use crate::client_context::ClientContextProvider;
 use crate::datastore_usum::{
-    ChangeMetasRequest, DataStoreGetMetaParam, DataStorePreparePostParam, DataStoreRateObjectParam,
+    ChangeMetasRequest, DataStoreGetMetaParam, DataStorePersistenceInitParam,
+    DataStorePersistenceManager, DataStorePreparePostParam, DataStoreRateObjectParam,
     DataStoreRatingTarget, GlobalTradeStationDeletePokemonParam,
     GlobalTradeStationDownloadMyPokemonParam, GlobalTradeStationDownloadOtherPokemonParam,
     GlobalTradeStationPrepareTradePokemonParam, GlobalTradeStationSearchPokemonParam,
     GlobalTradeStationTradePokemonParam, GlobalTradeStationUploadPokemonParam,
+    PersistenceAssignment,
 };
+use crate::middleware::{HandlerResult, RequestPipeline};
+#[cfg(feature = "tracing")]
+use crate::request_trace::request_span;
+use crate::request_trace::trace_parameters;
+use crate::structure::{read_list, read_nex_struct, write_field};
 use async_trait::async_trait;
 use nex_rs::client::ClientConnection;
 use nex_rs::nex_types::{NexList, NexStruct, ResultCode};
 use nex_rs::packet::{Packet, PacketV1};
 use nex_rs::server::Server;
-use no_std_io::{StreamContainer, StreamReader};
+use no_std_io::{Error, StreamContainer, StreamReader};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 pub const DATASTORE_PROTOCOL_ID: u8 = 0x73;
@@ -32,8 +40,22 @@ pub enum DataStoreMethod {
     SearchPokemonV2 = 0x37,
 }
 
+/// Copies the persistence init param out of a post param.
+/// A [NexStruct] only gives up its value by being consumed, so the field is re-encoded and read back.
+fn read_persistence_init_param(
+    param: &DataStorePreparePostParam,
+) -> Result<DataStorePersistenceInitParam, Error> {
+    let mut data = vec![];
+    write_field(&mut data, &param.persistence_init_param)?;
+
+    let mut stream = StreamContainer::new(data.as_slice());
+    Ok(stream
+        .read_stream_le::<NexStruct<DataStorePersistenceInitParam>>()?
+        .into_raw())
+}
+
 #[async_trait(?Send)]
-pub trait DataStoreProtocol: Server {
+pub trait DataStoreProtocol: Server + ClientContextProvider {
     async fn get_metas(
         &self,
         client: &mut ClientConnection,
@@ -97,53 +119,176 @@ pub trait DataStoreProtocol: Server {
         param: GlobalTradeStationSearchPokemonParam,
     ) -> Result<Vec<u8>, ResultCode>;
 
-    async fn handle_get_metas(
+    /// Tracks posted objects by persistence slot when set, so GetMetas can address them
+    /// by their persistence target and PostMetaBinary replaces the slot's previous object.
+    fn get_persistence_manager(&self) -> Option<&DataStorePersistenceManager> {
+        None
+    }
+
+    /// Called when a post replaced the object in a persistence slot with `delete_last_object` set.
+    /// Does nothing by default.
+    async fn delete_replaced_object(
+        &self,
+        _client: &mut ClientConnection,
+        _data_id: u64,
+    ) -> Result<(), ResultCode> {
+        Ok(())
+    }
+
+    /// Assigns a posted object to the persistence slot requested by the post,
+    /// reading its data id from the PostMetaBinary response.
+    async fn assign_persistence_slot(
+        &self,
+        client: &mut ClientConnection,
+        response: &[u8],
+        param: &DataStorePersistenceInitParam,
+    ) -> Result<(), ResultCode> {
+        let manager = match self.get_persistence_manager() {
+            Some(manager) => manager,
+            None => return Ok(()),
+        };
+
+        let data_id = StreamContainer::new(response)
+            .read_stream_le::<u64>()
+            .map_err(|_| ResultCode::Core_SystemError)?;
+
+        if let PersistenceAssignment::Delete(previous) =
+            manager.assign(client.get_pid(), data_id, param)?
+        {
+            self.delete_replaced_object(client, previous).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Dispatches a request to its method's handler.
+    async fn handle_request(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
     ) -> Result<(), &'static str> {
+        let rmc_request = packet.get_rmc_request();
+        let method = DataStoreMethod::try_from(rmc_request.method_id);
+
+        #[cfg(feature = "tracing")]
+        let span = request_span(
+            "DataStore",
+            method.as_ref().ok(),
+            rmc_request.method_id,
+            rmc_request.call_id,
+            client.get_pid(),
+        );
+
+        let dispatch = async move {
+            let request = match self.begin_request(client, packet, true) {
+                Ok(request) => request,
+                Err(error_code) => {
+                    return self.send_response(client, packet, Err(error_code)).await
+                }
+            };
+
+            let result = match method {
+                Ok(DataStoreMethod::GetMetas) => self.handle_get_metas(client, packet).await,
+                Ok(DataStoreMethod::RateObject) => self.handle_rate_object(client, packet).await,
+                Ok(DataStoreMethod::PostMetaBinary) => {
+                    self.handle_post_meta_binary(client, packet).await
+                }
+                Ok(DataStoreMethod::ChangeMetas) => self.handle_change_metas(client, packet).await,
+                Ok(DataStoreMethod::PrepareUploadPokemon) => {
+                    self.handle_prepare_upload_pokemon(client, packet).await
+                }
+                Ok(DataStoreMethod::UploadPokemon) => {
+                    self.handle_upload_pokemon(client, packet).await
+                }
+                Ok(DataStoreMethod::PrepareTradePokemon) => {
+                    self.handle_prepare_trade_pokemon(client, packet).await
+                }
+                Ok(DataStoreMethod::TradePokemon) => {
+                    self.handle_trade_pokemon(client, packet).await
+                }
+                Ok(DataStoreMethod::DownloadOtherPokemon) => {
+                    self.handle_download_other_pokemon(client, packet).await
+                }
+                Ok(DataStoreMethod::DownloadMyPokemon) => {
+                    self.handle_download_my_pokemon(client, packet).await
+                }
+                Ok(DataStoreMethod::DeletePokemon) => {
+                    self.handle_delete_pokemon(client, packet).await
+                }
+                Ok(DataStoreMethod::SearchPokemonV2) => {
+                    self.handle_search_pokemon_v2(client, packet).await
+                }
+                Err(_) => self.handle_unknown_method(client, packet).await,
+            };
+
+            self.finish_request(client, request, result).await
+        };
+
+        #[cfg(feature = "tracing")]
+        let dispatch = tracing::Instrument::instrument(dispatch, span);
+
+        dispatch.await
+    }
+
+    /// Called for method ids without a handler, e.g. to prototype undocumented methods.
+    /// Responds with `Core_NotImplemented` by default.
+    async fn handle_unknown(
+        &self,
+        _client: &mut ClientConnection,
+        _method_id: u32,
+        _parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn handle_unknown_method(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+
+        let result = self
+            .handle_unknown(client, request.method_id, parameters)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_get_metas(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
 
         let mut parameters_stream = StreamContainer::new(parameters);
 
-        let data_ids = parameters_stream
-            .read_stream_le::<NexList<u64>>()
-            .map_err(|_| "Can not read data ids list")?;
-        let param = parameters_stream
+        let data_ids =
+            read_list::<u64>(&mut parameters_stream).map_err(|_| "Can not read data ids list")?;
+        let mut param = parameters_stream
             .read_stream_le::<DataStoreGetMetaParam>()
             .map_err(|_| "Can not read DataStoreGetMetaParam")?;
 
-        match self.get_metas(client, data_ids, param).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
+        trace_parameters!(data_ids, param);
+
+        if let Some(manager) = self.get_persistence_manager() {
+            match manager.resolve_get_meta_param(&param) {
+                Ok(data_id) => param.data_id = data_id,
+                Err(error_code) => return Ok(Err(error_code)),
             }
         }
-        Ok(())
+
+        let result = self.get_metas(client, data_ids, param).await;
+        Ok(result)
     }
 
     async fn handle_rate_object(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
 
@@ -159,75 +304,52 @@ pub trait DataStoreProtocol: Server {
             .read_stream_le::<bool>()
             .map_err(|_| "Can not read fetch ratings bool")?;
 
-        match self.rate_object(client, target, param, fetch_ratings).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        trace_parameters!(target, param, fetch_ratings);
+        let result = self.rate_object(client, target, param, fetch_ratings).await;
+        Ok(result)
     }
 
     async fn handle_post_meta_binary(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
 
         let mut parameters_stream = StreamContainer::new(parameters);
+        let datastore_version = self.get_title_profile(client).datastore_version;
 
-        let param = parameters_stream
-            .read_stream_le::<NexStruct<DataStorePreparePostParam>>()
-            .map_err(|_| "Can not read DataStorePreparePostParam")?;
-
-        match self.post_meta_binary(client, param.into_raw()).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
+        let param =
+            read_nex_struct::<DataStorePreparePostParam>(&mut parameters_stream, datastore_version)
+                .map_err(|_| "Can not read DataStorePreparePostParam")?;
+        let persistence_init_param = read_persistence_init_param(&param)
+            .map_err(|_| "Can not read DataStorePersistenceInitParam")?;
+
+        trace_parameters!(param);
+
+        // Checked before posting, so an invalid slot doesn't leave an object behind
+        if let Some(manager) = self.get_persistence_manager() {
+            if let Err(error_code) = manager.check_init_param(&persistence_init_param) {
+                return Ok(Err(error_code));
             }
         }
-        Ok(())
+
+        let result = match self.post_meta_binary(client, param).await {
+            Ok(response) => self
+                .assign_persistence_slot(client, &response, &persistence_init_param)
+                .await
+                .map(|_| response),
+            Err(error_code) => Err(error_code),
+        };
+        Ok(result)
     }
 
     async fn handle_change_metas(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
 
@@ -237,332 +359,171 @@ pub trait DataStoreProtocol: Server {
             .read_stream_le::<ChangeMetasRequest>()
             .map_err(|_| "Can not read ChangeMetasRequest")?;
 
-        match self.change_metas(client, param).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        trace_parameters!(param);
+        let result = self.change_metas(client, param).await;
+        Ok(result)
     }
 
     async fn handle_prepare_upload_pokemon(
         &self,
         client: &mut ClientConnection,
-        packet: &PacketV1,
-    ) -> Result<(), &'static str> {
-        let request = packet.get_rmc_request();
-        match self.prepare_upload_pokemon(client).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        _packet: &PacketV1,
+    ) -> HandlerResult {
+        let result = self.prepare_upload_pokemon(client).await;
+        Ok(result)
     }
 
     async fn handle_upload_pokemon(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
 
         let mut parameters_stream = StreamContainer::new(parameters);
+        let datastore_version = self.get_title_profile(client).datastore_version;
 
-        let param = parameters_stream
-            .read_stream_le::<NexStruct<GlobalTradeStationUploadPokemonParam>>()
-            .map_err(|_| "Can not read GlobalTradeStationUploadPokemonParam")?;
-
-        match self.upload_pokemon(client, param.into_raw()).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        let param = read_nex_struct::<GlobalTradeStationUploadPokemonParam>(
+            &mut parameters_stream,
+            datastore_version,
+        )
+        .map_err(|_| "Can not read GlobalTradeStationUploadPokemonParam")?;
+
+        trace_parameters!(param);
+        let result = self.upload_pokemon(client, param).await;
+        Ok(result)
     }
 
     async fn handle_prepare_trade_pokemon(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
 
         let mut parameters_stream = StreamContainer::new(parameters);
+        let datastore_version = self.get_title_profile(client).datastore_version;
 
-        let param = parameters_stream
-            .read_stream_le::<NexStruct<GlobalTradeStationPrepareTradePokemonParam>>()
-            .map_err(|_| "Can not read GlobalTradeStationPrepareTradePokemonParam")?;
-
-        match self.prepare_trade_pokemon(client, param.into_raw()).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        let param = read_nex_struct::<GlobalTradeStationPrepareTradePokemonParam>(
+            &mut parameters_stream,
+            datastore_version,
+        )
+        .map_err(|_| "Can not read GlobalTradeStationPrepareTradePokemonParam")?;
+
+        trace_parameters!(param);
+        let result = self.prepare_trade_pokemon(client, param).await;
+        Ok(result)
     }
 
     async fn handle_trade_pokemon(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
 
         let mut parameters_stream = StreamContainer::new(parameters);
+        let datastore_version = self.get_title_profile(client).datastore_version;
 
-        let param = parameters_stream
-            .read_stream_le::<NexStruct<GlobalTradeStationTradePokemonParam>>()
-            .map_err(|_| "Can not read GlobalTradeStationTradePokemonParam")?;
-
-        match self.trade_pokemon(client, param.into_raw()).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        let param = read_nex_struct::<GlobalTradeStationTradePokemonParam>(
+            &mut parameters_stream,
+            datastore_version,
+        )
+        .map_err(|_| "Can not read GlobalTradeStationTradePokemonParam")?;
+
+        trace_parameters!(param);
+        let result = self.trade_pokemon(client, param).await;
+        Ok(result)
     }
 
     async fn handle_download_other_pokemon(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
 
         let mut parameters_stream = StreamContainer::new(parameters);
+        let datastore_version = self.get_title_profile(client).datastore_version;
 
-        let param = parameters_stream
-            .read_stream_le::<NexStruct<GlobalTradeStationDownloadOtherPokemonParam>>()
-            .map_err(|_| "Can not read GlobalTradeStationDownloadOtherPokemonParam")?;
-
-        match self.download_other_pokemon(client, param.into_raw()).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        let param = read_nex_struct::<GlobalTradeStationDownloadOtherPokemonParam>(
+            &mut parameters_stream,
+            datastore_version,
+        )
+        .map_err(|_| "Can not read GlobalTradeStationDownloadOtherPokemonParam")?;
+
+        trace_parameters!(param);
+        let result = self.download_other_pokemon(client, param).await;
+        Ok(result)
     }
 
     async fn handle_download_my_pokemon(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
 
         let mut parameters_stream = StreamContainer::new(parameters);
+        let datastore_version = self.get_title_profile(client).datastore_version;
 
-        let param = parameters_stream
-            .read_stream_le::<NexStruct<GlobalTradeStationDownloadMyPokemonParam>>()
-            .map_err(|_| "Can not read GlobalTradeStationDownloadMyPokemonParam")?;
-
-        match self.download_my_pokemon(client, param.into_raw()).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        let param = read_nex_struct::<GlobalTradeStationDownloadMyPokemonParam>(
+            &mut parameters_stream,
+            datastore_version,
+        )
+        .map_err(|_| "Can not read GlobalTradeStationDownloadMyPokemonParam")?;
+
+        trace_parameters!(param);
+        let result = self.download_my_pokemon(client, param).await;
+        Ok(result)
     }
 
     async fn handle_delete_pokemon(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
 
         let mut parameters_stream = StreamContainer::new(parameters);
+        let datastore_version = self.get_title_profile(client).datastore_version;
 
-        let param = parameters_stream
-            .read_stream_le::<NexStruct<GlobalTradeStationDeletePokemonParam>>()
-            .map_err(|_| "Can not read GlobalTradeStationDeletePokemonParam")?;
-
-        match self.delete_pokemon(client, param.into_raw()).await {
-            Ok(_) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    vec![],
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        let param = read_nex_struct::<GlobalTradeStationDeletePokemonParam>(
+            &mut parameters_stream,
+            datastore_version,
+        )
+        .map_err(|_| "Can not read GlobalTradeStationDeletePokemonParam")?;
+
+        trace_parameters!(param);
+        let result = self.delete_pokemon(client, param).await.map(|_| vec![]);
+        Ok(result)
     }
 
     async fn handle_search_pokemon_v2(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
 
         let mut parameters_stream = StreamContainer::new(parameters);
+        let datastore_version = self.get_title_profile(client).datastore_version;
 
-        let param = parameters_stream
-            .read_stream_le::<NexStruct<GlobalTradeStationSearchPokemonParam>>()
-            .map_err(|_| "Can not read GlobalTradeStationSearchPokemonParam")?;
-
-        match self.search_pokemon_v2(client, param.into_raw()).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        let param = read_nex_struct::<GlobalTradeStationSearchPokemonParam>(
+            &mut parameters_stream,
+            datastore_version,
+        )
+        .map_err(|_| "Can not read GlobalTradeStationSearchPokemonParam")?;
+
+        trace_parameters!(param);
+        let result = self.search_pokemon_v2(client, param).await;
+        Ok(result)
     }
 }
@@ -1,14 +1,20 @@
+use crate::structure::{impl_bounded_endian_read, read_list, read_qbuffer};
 use nex_rs::nex_types::{
     DateTime, NexList, NexQBuffer, NexString, NexStruct, ResultCode, ResultRange,
 };
 use no_std_io::{EndianRead, EndianWrite};
 
-#[derive(Debug, EndianRead, EndianWrite)]
+#[derive(Debug, EndianWrite)]
 pub struct GetMetasRequest {
     pub data_ids: NexList<u64>,
     pub param: NexStruct<DataStoreGetMetaParam>,
 }
 
+impl_bounded_endian_read!(GetMetasRequest {
+    data_ids: read_list,
+    param,
+});
+
 #[derive(Debug, EndianRead, EndianWrite)]
 pub struct GetMetasResponse {
     pub p_meta_info: NexList<NexStruct<DataStoreMetaInfo>>,
@@ -63,7 +69,7 @@ pub struct DataStoreRatingInfo {
     pub initial_value: i64,
 }
 
-#[derive(Debug, EndianRead, EndianWrite)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EndianRead, EndianWrite)]
 pub struct DataStorePersistenceTarget {
     pub owner_id: u32,
     pub persistence_slot_id: u16,
@@ -101,25 +107,30 @@ pub struct DataStoreRatingInitParam {
     pub period_duration: i16,
 }
 
-#[derive(Debug, EndianRead, EndianWrite)]
+#[derive(Debug, EndianWrite)]
 pub struct DataStorePermission {
     pub permission: u8,
     pub recipient_ids: NexList<u32>,
 }
 
+impl_bounded_endian_read!(DataStorePermission {
+    permission,
+    recipient_ids: read_list,
+});
+
 #[derive(Debug, EndianRead, EndianWrite)]
 pub struct DataStoreRatingInitParamWithSlot {
     pub slot: i8,
     pub param: NexStruct<DataStoreRatingInitParam>,
 }
 
-#[derive(Debug, EndianRead, EndianWrite)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EndianRead, EndianWrite)]
 pub struct DataStorePersistenceInitParam {
     pub persistence_slot_id: u16,
     pub delete_last_object: bool,
 }
 
-#[derive(Debug, EndianRead, EndianWrite)]
+#[derive(Debug, EndianWrite)]
 pub struct DataStorePreparePostParam {
     pub size: u32,
     pub name: NexString,
@@ -136,6 +147,22 @@ pub struct DataStorePreparePostParam {
     pub extra_data: NexList<NexString>,
 }
 
+impl_bounded_endian_read!(DataStorePreparePostParam {
+    size,
+    name,
+    data_type,
+    meta_binary: read_qbuffer,
+    permission,
+    del_permission,
+    flag,
+    period,
+    refer_data_id,
+    tags: read_list,
+    rating_init_params: read_list,
+    persistence_init_param,
+    extra_data: read_list,
+});
+
 #[derive(Debug, EndianRead, EndianWrite)]
 pub struct PostMetaBinaryRequest {
     pub param: NexStruct<DataStorePreparePostParam>,
@@ -146,7 +173,7 @@ pub struct PostMetaBinaryResponse {
     pub data_id: u64,
 }
 
-#[derive(Debug, EndianRead, EndianWrite)]
+#[derive(Debug, EndianWrite)]
 pub struct DataStoreChangeMetaCompareParam {
     pub comparison_flag: u32,
     pub name: NexString,
@@ -160,7 +187,20 @@ pub struct DataStoreChangeMetaCompareParam {
     pub status: u8,
 }
 
-#[derive(Debug, EndianRead, EndianWrite)]
+impl_bounded_endian_read!(DataStoreChangeMetaCompareParam {
+    comparison_flag,
+    name,
+    permission,
+    del_permission,
+    period,
+    meta_binary: read_qbuffer,
+    tags: read_list,
+    referred_cnt,
+    data_type,
+    status,
+});
+
+#[derive(Debug, EndianWrite)]
 pub struct DataStoreChangeMetaParam {
     pub data_id: u64,
     pub modifies_flag: u32,
@@ -178,13 +218,36 @@ pub struct DataStoreChangeMetaParam {
     pub persistence_target: NexStruct<DataStorePersistenceTarget>,
 }
 
-#[derive(Debug, EndianRead, EndianWrite)]
+impl_bounded_endian_read!(DataStoreChangeMetaParam {
+    data_id,
+    modifies_flag,
+    name,
+    permission,
+    del_permission,
+    period,
+    meta_binary: read_qbuffer,
+    tags: read_list,
+    update_password,
+    referred_cnt,
+    data_type,
+    status,
+    compare_param,
+    persistence_target,
+});
+
+#[derive(Debug, EndianWrite)]
 pub struct ChangeMetasRequest {
     pub data_ids: NexList<u64>,
     pub params: NexList<NexStruct<DataStoreChangeMetaParam>>,
     pub transactional: bool,
 }
 
+impl_bounded_endian_read!(ChangeMetasRequest {
+    data_ids: read_list,
+    params: read_list,
+    transactional,
+});
+
 #[derive(Debug, EndianRead, EndianWrite)]
 pub struct ChangeMetasResponse {
     pub p_results: NexList<ResultCode>,
@@ -201,7 +264,7 @@ pub struct PrepareUploadPokemonResponse {
     pub p_record_key: NexStruct<GlobalTradeStationRecordKey>,
 }
 
-#[derive(Debug, EndianRead, EndianWrite)]
+#[derive(Debug, EndianWrite)]
 pub struct GlobalTradeStationUploadPokemonParam {
     pub prepare_upload_key: NexStruct<GlobalTradeStationRecordKey>,
     pub period: u16,
@@ -210,6 +273,14 @@ pub struct GlobalTradeStationUploadPokemonParam {
     pub signature: NexQBuffer,
 }
 
+impl_bounded_endian_read!(GlobalTradeStationUploadPokemonParam {
+    prepare_upload_key,
+    period,
+    index_data: read_qbuffer,
+    pokemon_data: read_qbuffer,
+    signature: read_qbuffer,
+});
+
 #[derive(Debug, EndianRead, EndianWrite)]
 pub struct UploadPokemonRequest {
     pub param: NexStruct<GlobalTradeStationUploadPokemonParam>,
@@ -250,7 +321,7 @@ pub struct GlobalTradeStationPrepareTradePokemonResult {
     pub prepare_trade_key: NexStruct<GlobalTradeStationRecordKey>,
 }
 
-#[derive(Debug, EndianRead, EndianWrite)]
+#[derive(Debug, EndianWrite)]
 pub struct GlobalTradeStationTradePokemonParam {
     pub trade_key: NexStruct<GlobalTradeStationTradeKey>,
     pub prepare_trade_key: NexStruct<GlobalTradeStationRecordKey>,
@@ -262,6 +333,17 @@ pub struct GlobalTradeStationTradePokemonParam {
     pub need_data: bool,
 }
 
+impl_bounded_endian_read!(GlobalTradeStationTradePokemonParam {
+    trade_key,
+    prepare_trade_key,
+    prepare_upload_key,
+    period,
+    index_data: read_qbuffer,
+    pokemon_data: read_qbuffer,
+    signature: read_qbuffer,
+    need_data,
+});
+
 #[derive(Debug, EndianRead, EndianWrite)]
 pub struct TradePokemonRequest {
     pub param: NexStruct<GlobalTradeStationTradePokemonParam>,
@@ -325,7 +407,7 @@ pub struct DeletePokemonRequest {
     pub param: NexStruct<GlobalTradeStationDeletePokemonParam>,
 }
 
-#[derive(Debug, EndianRead, EndianWrite)]
+#[derive(Debug, EndianWrite)]
 pub struct GlobalTradeStationSearchPokemonParam {
     pub prepare_upload_key: NexStruct<GlobalTradeStationRecordKey>,
     pub conditions: NexList<u32>,
@@ -336,6 +418,16 @@ pub struct GlobalTradeStationSearchPokemonParam {
     pub result_range: NexStruct<ResultRange>,
 }
 
+impl_bounded_endian_read!(GlobalTradeStationSearchPokemonParam {
+    prepare_upload_key,
+    conditions: read_list,
+    result_order_column,
+    result_order,
+    uploaded_after,
+    uploaded_before,
+    result_range,
+});
+
 #[derive(Debug, EndianRead, EndianWrite)]
 pub struct SearchPokemonV2Request {
     pub param: NexStruct<GlobalTradeStationSearchPokemonParam>,
@@ -1,2 +1,8 @@
+mod probe;
+pub use probe::*;
+
 mod protocol;
 pub use protocol::*;
+
+mod server;
+pub use server::*;
@@ -0,0 +1,312 @@
+use crate::matchmake_extension::MatchmakeRegistry;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthProbeKind {
+    Database,
+    Storage,
+    Service,
+}
+
+/// A named check of one dependency of the server.
+pub trait HealthProbe {
+    fn get_name(&self) -> &str;
+
+    fn get_kind(&self) -> HealthProbeKind {
+        HealthProbeKind::Service
+    }
+
+    /// Returns a description of the problem when unhealthy.
+    fn check(&self) -> Result<(), String>;
+}
+
+impl<T: HealthProbe + ?Sized> HealthProbe for Rc<T> {
+    fn get_name(&self) -> &str {
+        (**self).get_name()
+    }
+
+    fn get_kind(&self) -> HealthProbeKind {
+        (**self).get_kind()
+    }
+
+    fn check(&self) -> Result<(), String> {
+        (**self).check()
+    }
+}
+
+impl HealthProbe for MatchmakeRegistry {
+    fn get_name(&self) -> &str {
+        "matchmake_registry"
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let gids = self.find_inconsistent_session_ids();
+
+        if gids.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Inconsistent matchmake sessions: {:?}", gids))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SanityCheckEntry {
+    pub name: String,
+    pub kind: HealthProbeKind,
+    pub error: Option<String>,
+}
+
+impl SanityCheckEntry {
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SanityReport {
+    pub entries: Vec<SanityCheckEntry>,
+}
+
+impl SanityReport {
+    pub fn is_healthy(&self) -> bool {
+        self.entries.iter().all(SanityCheckEntry::is_healthy)
+    }
+
+    pub fn get_failures(&self) -> impl Iterator<Item = &SanityCheckEntry> {
+        self.entries.iter().filter(|entry| !entry.is_healthy())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairResult {
+    pub name: String,
+    pub error: Option<String>,
+}
+
+type RepairAction = Box<dyn Fn() -> Result<(), String>>;
+
+/// The probes and repair actions backing the default [HealthProtocol](crate::health::HealthProtocol).
+#[derive(Default)]
+pub struct HealthProbeRegistry {
+    probes: Vec<Box<dyn HealthProbe>>,
+    repair_actions: Vec<(String, RepairAction)>,
+    last_sanity_report: RefCell<Option<SanityReport>>,
+}
+
+impl HealthProbeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register_probe(&mut self, probe: impl HealthProbe + 'static) {
+        self.probes.push(Box::new(probe));
+    }
+
+    pub fn register_repair_action(
+        &mut self,
+        name: &str,
+        action: impl Fn() -> Result<(), String> + 'static,
+    ) {
+        self.repair_actions
+            .push((name.to_string(), Box::new(action)));
+    }
+
+    /// Registers a [MatchmakeRegistry] probe along with a repair action for its sessions.
+    pub fn register_matchmake_registry(&mut self, registry: Rc<MatchmakeRegistry>) {
+        let repair_registry = Rc::clone(&registry);
+        self.register_probe(registry);
+        self.register_repair_action("matchmake_registry", move || {
+            repair_registry.repair_sessions();
+            Ok(())
+        });
+    }
+
+    pub fn check_kind(&self, kind: HealthProbeKind) -> SanityReport {
+        self.check_probes(|probe| probe.get_kind() == kind)
+    }
+
+    /// Checks every probe, keeping the report for [Self::get_last_sanity_report].
+    pub fn run_sanity_check(&self) -> SanityReport {
+        let report = self.check_probes(|_| true);
+        *self.last_sanity_report.borrow_mut() = Some(report.clone());
+        report
+    }
+
+    /// Returns the report of the latest [Self::run_sanity_check], since RunSanityCheck
+    /// only tells clients whether it passed.
+    pub fn get_last_sanity_report(&self) -> Option<SanityReport> {
+        self.last_sanity_report.borrow().clone()
+    }
+
+    fn check_probes(&self, filter: impl Fn(&dyn HealthProbe) -> bool) -> SanityReport {
+        SanityReport {
+            entries: self
+                .probes
+                .iter()
+                .filter(|probe| filter(probe.as_ref()))
+                .map(|probe| SanityCheckEntry {
+                    name: probe.get_name().to_string(),
+                    kind: probe.get_kind(),
+                    error: probe.check().err(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn run_repair_actions(&self) -> Vec<RepairResult> {
+        self.repair_actions
+            .iter()
+            .map(|(name, action)| RepairResult {
+                name: name.clone(),
+                error: action().err(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matchmake_extension::MatchmakeSession;
+    use std::cell::Cell;
+
+    struct StaticProbe {
+        name: &'static str,
+        kind: HealthProbeKind,
+        healthy: bool,
+    }
+
+    impl HealthProbe for StaticProbe {
+        fn get_name(&self) -> &str {
+            self.name
+        }
+
+        fn get_kind(&self) -> HealthProbeKind {
+            self.kind
+        }
+
+        fn check(&self) -> Result<(), String> {
+            if self.healthy {
+                Ok(())
+            } else {
+                Err(format!("{} is down", self.name))
+            }
+        }
+    }
+
+    fn probe(name: &'static str, kind: HealthProbeKind, healthy: bool) -> StaticProbe {
+        StaticProbe {
+            name,
+            kind,
+            healthy,
+        }
+    }
+
+    fn session() -> MatchmakeSession {
+        let mut session = MatchmakeSession {
+            open_participation: true,
+            ..Default::default()
+        };
+        session.gathering.min_participants = 1;
+        session.gathering.max_participants = 4;
+        session
+    }
+
+    #[test]
+    fn checks_probes_of_a_kind() {
+        let mut registry = HealthProbeRegistry::new();
+        registry.register_probe(probe("database", HealthProbeKind::Database, true));
+        registry.register_probe(probe("storage", HealthProbeKind::Storage, false));
+
+        let report = registry.check_kind(HealthProbeKind::Database);
+        assert!(report.is_healthy());
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].name, "database");
+
+        assert!(!registry.check_kind(HealthProbeKind::Storage).is_healthy());
+        assert!(registry
+            .check_kind(HealthProbeKind::Service)
+            .entries
+            .is_empty());
+    }
+
+    #[test]
+    fn keeps_the_last_sanity_report() {
+        let mut registry = HealthProbeRegistry::new();
+        registry.register_probe(probe("database", HealthProbeKind::Database, true));
+        registry.register_probe(probe("storage", HealthProbeKind::Storage, false));
+        assert_eq!(registry.get_last_sanity_report(), None);
+
+        registry.check_kind(HealthProbeKind::Database);
+        assert_eq!(registry.get_last_sanity_report(), None);
+
+        let report = registry.run_sanity_check();
+        assert!(!report.is_healthy());
+        assert_eq!(
+            report
+                .get_failures()
+                .map(|entry| (entry.name.as_str(), entry.error.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![("storage", Some("storage is down"))]
+        );
+        assert_eq!(registry.get_last_sanity_report(), Some(report));
+    }
+
+    #[test]
+    fn runs_every_repair_action() {
+        let runs = Rc::new(Cell::new(0));
+        let mut registry = HealthProbeRegistry::new();
+
+        let counter = Rc::clone(&runs);
+        registry.register_repair_action("cache", move || {
+            counter.set(counter.get() + 1);
+            Ok(())
+        });
+        registry.register_repair_action("storage", || Err("read only".to_string()));
+
+        assert_eq!(
+            registry.run_repair_actions(),
+            vec![
+                RepairResult {
+                    name: "cache".to_string(),
+                    error: None,
+                },
+                RepairResult {
+                    name: "storage".to_string(),
+                    error: Some("read only".to_string()),
+                },
+            ]
+        );
+        assert_eq!(runs.get(), 1);
+    }
+
+    #[test]
+    fn checks_and_repairs_matchmake_registries() {
+        let matchmake_registry = Rc::new(MatchmakeRegistry::new());
+        let gid = matchmake_registry.create_session(1, session(), 1).unwrap();
+        matchmake_registry.join_session(2, gid, None, 1).unwrap();
+
+        let mut registry = HealthProbeRegistry::new();
+        registry.register_matchmake_registry(Rc::clone(&matchmake_registry));
+        assert!(registry.run_sanity_check().is_healthy());
+
+        matchmake_registry.set_host_pid_unchecked(gid, 3);
+        let report = registry.run_sanity_check();
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].name, "matchmake_registry");
+        assert_eq!(report.entries[0].kind, HealthProbeKind::Service);
+        assert!(!report.is_healthy());
+
+        assert!(registry
+            .run_repair_actions()
+            .iter()
+            .all(|result| result.error.is_none()));
+        assert!(registry.run_sanity_check().is_healthy());
+        assert_eq!(
+            matchmake_registry.with_session(gid, |session| session.gathering.host_pid),
+            Some(1)
+        );
+    }
+}
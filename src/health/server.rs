@@ -0,0 +1,56 @@
+use crate::client_context::ClientContextProvider;
+use crate::health::{HealthProbeKind, HealthProbeRegistry, HealthProtocol};
+use async_trait::async_trait;
+use nex_rs::client::ClientConnection;
+use nex_rs::nex_types::ResultCode;
+use nex_rs::server::Server;
+
+/// Implemented by servers that want [HealthProtocol] backed by a [HealthProbeRegistry].
+/// Every method responds with a bool telling whether the checks or repairs succeeded.
+/// The full report of the latest RunSanityCheck is kept by
+/// [HealthProbeRegistry::get_last_sanity_report].
+pub trait HealthProbeServer: Server + ClientContextProvider {
+    fn get_health_probes(&self) -> &HealthProbeRegistry;
+}
+
+fn write_bool(value: bool) -> Vec<u8> {
+    vec![value as u8]
+}
+
+#[async_trait(?Send)]
+impl<T: HealthProbeServer> HealthProtocol for T {
+    async fn ping_daemon(&self, _client: &mut ClientConnection) -> Result<Vec<u8>, ResultCode> {
+        Ok(write_bool(true))
+    }
+
+    async fn ping_database(&self, _client: &mut ClientConnection) -> Result<Vec<u8>, ResultCode> {
+        let report = self
+            .get_health_probes()
+            .check_kind(HealthProbeKind::Database);
+        Ok(write_bool(report.is_healthy()))
+    }
+
+    async fn run_sanity_check(
+        &self,
+        _client: &mut ClientConnection,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let report = self.get_health_probes().run_sanity_check();
+
+        #[cfg(feature = "tracing")]
+        for entry in report.get_failures() {
+            tracing::warn!(probe = %entry.name, error = ?entry.error, "sanity check failed");
+        }
+
+        Ok(write_bool(report.is_healthy()))
+    }
+
+    async fn fix_sanity_errors(
+        &self,
+        _client: &mut ClientConnection,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let results = self.get_health_probes().run_repair_actions();
+        Ok(write_bool(
+            results.iter().all(|result| result.error.is_none()),
+        ))
+    }
+}
@@ -1,7 +1,20 @@
+pub mod any_data_holder;
+pub mod authorization;
+pub mod client_context;
 pub mod datastore_usum;
 pub mod health;
+pub mod match_making;
 pub mod matchmake_extension;
+pub mod metrics;
+pub mod middleware;
 pub mod monitoring;
+pub mod rate_limit;
+pub mod request_trace;
 pub mod secure_connection;
+pub mod structure;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod ticket_granting;
+pub mod title_profile;
+pub mod token;
 pub mod usum_117;
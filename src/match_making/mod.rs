@@ -0,0 +1,5 @@
+mod protocol;
+pub use protocol::*;
+
+mod types;
+pub use types::*;
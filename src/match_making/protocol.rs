@@ -0,0 +1,366 @@
+use crate::any_data_holder::AnyDataHolder;
+use crate::client_context::ClientContextProvider;
+use crate::middleware::{HandlerResult, RequestPipeline};
+#[cfg(feature = "tracing")]
+use crate::request_trace::request_span;
+use crate::request_trace::trace_parameters;
+use crate::structure::read_list;
+use async_trait::async_trait;
+use nex_rs::client::ClientConnection;
+use nex_rs::nex_types::{NexList, NexString, ResultCode};
+use nex_rs::packet::{Packet, PacketV1};
+use nex_rs::server::Server;
+use no_std_io::{StreamContainer, StreamReader};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+pub const MATCH_MAKING_PROTOCOL_ID: u8 = 0x15;
+
+#[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive, IntoPrimitive)]
+#[repr(u32)]
+pub enum MatchMakingMethod {
+    RegisterGathering = 0x1,
+    UnregisterGathering = 0x2,
+    UpdateGathering = 0x4,
+    Participate = 0xB,
+    CancelParticipation = 0xC,
+    FindByID = 0x14,
+    FindBySingleID = 0x15,
+    GetSessionURLs = 0x29,
+    MigrateGatheringOwnership = 0x2C,
+}
+
+#[async_trait(?Send)]
+pub trait MatchMakingProtocol: Server + ClientContextProvider {
+    /// `gathering` is a Gathering or a subclass, usually a MatchmakeSession.
+    /// Subclasses registered with [DataHolderRegistry::register_gathering](crate::any_data_holder::DataHolderRegistry::register_gathering)
+    /// are passed as [AnyDataHolder::Custom], other classes are rejected with `Core_InvalidArgument`.
+    async fn register_gathering(
+        &self,
+        client: &mut ClientConnection,
+        gathering: AnyDataHolder,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn unregister_gathering(
+        &self,
+        client: &mut ClientConnection,
+        gid: u32,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn update_gathering(
+        &self,
+        client: &mut ClientConnection,
+        gathering: AnyDataHolder,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn participate(
+        &self,
+        client: &mut ClientConnection,
+        gid: u32,
+        message: String,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn cancel_participation(
+        &self,
+        client: &mut ClientConnection,
+        gid: u32,
+        message: String,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn find_by_id(
+        &self,
+        client: &mut ClientConnection,
+        gids: NexList<u32>,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn find_by_single_id(
+        &self,
+        client: &mut ClientConnection,
+        gid: u32,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn get_session_urls(
+        &self,
+        client: &mut ClientConnection,
+        gid: u32,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn migrate_gathering_ownership(
+        &self,
+        client: &mut ClientConnection,
+        gid: u32,
+        potential_new_owners: NexList<u32>,
+        participants_only: bool,
+    ) -> Result<Vec<u8>, ResultCode>;
+
+    /// Dispatches a request to its method's handler.
+    async fn handle_request(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> Result<(), &'static str> {
+        let rmc_request = packet.get_rmc_request();
+        let method = MatchMakingMethod::try_from(rmc_request.method_id);
+
+        #[cfg(feature = "tracing")]
+        let span = request_span(
+            "MatchMaking",
+            method.as_ref().ok(),
+            rmc_request.method_id,
+            rmc_request.call_id,
+            client.get_pid(),
+        );
+
+        let dispatch = async move {
+            let request = match self.begin_request(client, packet, true) {
+                Ok(request) => request,
+                Err(error_code) => {
+                    return self.send_response(client, packet, Err(error_code)).await
+                }
+            };
+
+            let result = match method {
+                Ok(MatchMakingMethod::RegisterGathering) => {
+                    self.handle_register_gathering(client, packet).await
+                }
+                Ok(MatchMakingMethod::UnregisterGathering) => {
+                    self.handle_unregister_gathering(client, packet).await
+                }
+                Ok(MatchMakingMethod::UpdateGathering) => {
+                    self.handle_update_gathering(client, packet).await
+                }
+                Ok(MatchMakingMethod::Participate) => self.handle_participate(client, packet).await,
+                Ok(MatchMakingMethod::CancelParticipation) => {
+                    self.handle_cancel_participation(client, packet).await
+                }
+                Ok(MatchMakingMethod::FindByID) => self.handle_find_by_id(client, packet).await,
+                Ok(MatchMakingMethod::FindBySingleID) => {
+                    self.handle_find_by_single_id(client, packet).await
+                }
+                Ok(MatchMakingMethod::GetSessionURLs) => {
+                    self.handle_get_session_urls(client, packet).await
+                }
+                Ok(MatchMakingMethod::MigrateGatheringOwnership) => {
+                    self.handle_migrate_gathering_ownership(client, packet)
+                        .await
+                }
+                Err(_) => self.handle_unknown_method(client, packet).await,
+            };
+
+            self.finish_request(client, request, result).await
+        };
+
+        #[cfg(feature = "tracing")]
+        let dispatch = tracing::Instrument::instrument(dispatch, span);
+
+        dispatch.await
+    }
+
+    /// Called for method ids without a handler, e.g. to prototype undocumented methods.
+    /// Responds with `Core_NotImplemented` by default.
+    async fn handle_unknown(
+        &self,
+        _client: &mut ClientConnection,
+        _method_id: u32,
+        _parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn handle_unknown_method(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+
+        let result = self
+            .handle_unknown(client, request.method_id, parameters)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_register_gathering(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let match_making_version = self.get_title_profile(client).match_making_version;
+        let data_holders = self.get_client_context_table().get_data_holders();
+        let gathering = data_holders
+            .read(&mut parameters_stream, match_making_version)
+            .map_err(|_| "Can not read gathering data holder")?;
+
+        if !data_holders.is_gathering(&gathering) {
+            return Ok(Err(ResultCode::Core_InvalidArgument));
+        }
+
+        trace_parameters!(gathering);
+        let result = self.register_gathering(client, gathering).await;
+        Ok(result)
+    }
+
+    async fn handle_unregister_gathering(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let gid = parameters_stream
+            .read_stream_le::<u32>()
+            .map_err(|_| "Can not read gathering id")?;
+
+        trace_parameters!(gid);
+        let result = self.unregister_gathering(client, gid).await;
+        Ok(result)
+    }
+
+    async fn handle_update_gathering(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let match_making_version = self.get_title_profile(client).match_making_version;
+        let data_holders = self.get_client_context_table().get_data_holders();
+        let gathering = data_holders
+            .read(&mut parameters_stream, match_making_version)
+            .map_err(|_| "Can not read gathering data holder")?;
+
+        if !data_holders.is_gathering(&gathering) {
+            return Ok(Err(ResultCode::Core_InvalidArgument));
+        }
+
+        trace_parameters!(gathering);
+        let result = self.update_gathering(client, gathering).await;
+        Ok(result)
+    }
+
+    async fn handle_participate(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let gid = parameters_stream
+            .read_stream_le::<u32>()
+            .map_err(|_| "Can not read gathering id")?;
+
+        let message: String = parameters_stream
+            .read_stream_le::<NexString>()
+            .map_err(|_| "Can not read message")?
+            .into();
+
+        trace_parameters!(gid, message);
+        let result = self.participate(client, gid, message).await;
+        Ok(result)
+    }
+
+    async fn handle_cancel_participation(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let gid = parameters_stream
+            .read_stream_le::<u32>()
+            .map_err(|_| "Can not read gathering id")?;
+
+        let message: String = parameters_stream
+            .read_stream_le::<NexString>()
+            .map_err(|_| "Can not read message")?
+            .into();
+
+        trace_parameters!(gid, message);
+        let result = self.cancel_participation(client, gid, message).await;
+        Ok(result)
+    }
+
+    async fn handle_find_by_id(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let gids =
+            read_list::<u32>(&mut parameters_stream).map_err(|_| "Can not read gathering ids")?;
+
+        trace_parameters!(gids);
+        let result = self.find_by_id(client, gids).await;
+        Ok(result)
+    }
+
+    async fn handle_find_by_single_id(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let gid = parameters_stream
+            .read_stream_le::<u32>()
+            .map_err(|_| "Can not read gathering id")?;
+
+        trace_parameters!(gid);
+        let result = self.find_by_single_id(client, gid).await;
+        Ok(result)
+    }
+
+    async fn handle_get_session_urls(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let gid = parameters_stream
+            .read_stream_le::<u32>()
+            .map_err(|_| "Can not read gathering id")?;
+
+        trace_parameters!(gid);
+        let result = self.get_session_urls(client, gid).await;
+        Ok(result)
+    }
+
+    async fn handle_migrate_gathering_ownership(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let gid = parameters_stream
+            .read_stream_le::<u32>()
+            .map_err(|_| "Can not read gathering id")?;
+
+        let potential_new_owners = read_list::<u32>(&mut parameters_stream)
+            .map_err(|_| "Can not read potential new owners")?;
+
+        let participants_only = parameters_stream
+            .read_stream_le::<bool>()
+            .map_err(|_| "Can not read participants only bool")?;
+
+        trace_parameters!(gid, potential_new_owners, participants_only);
+        let result = self
+            .migrate_gathering_ownership(client, gid, potential_new_owners, participants_only)
+            .await;
+        Ok(result)
+    }
+}
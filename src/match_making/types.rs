@@ -0,0 +1,102 @@
+use crate::matchmake_extension::Gathering;
+use crate::structure::{impl_bounded_endian_read, read_list};
+use nex_rs::nex_types::{DataHolder, NexList, NexString};
+use no_std_io::{EndianRead, EndianWrite};
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct RegisterGatheringRequest {
+    pub gathering: DataHolder<Gathering>,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct RegisterGatheringResponse {
+    pub gid: u32,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct UnregisterGatheringRequest {
+    pub gid: u32,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct UnregisterGatheringResponse {
+    pub result: bool,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct UpdateGatheringRequest {
+    pub gathering: DataHolder<Gathering>,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct UpdateGatheringResponse {
+    pub result: bool,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct ParticipateRequest {
+    pub gid: u32,
+    pub message: NexString,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct ParticipateResponse {
+    pub result: bool,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct CancelParticipationRequest {
+    pub gid: u32,
+    pub message: NexString,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct CancelParticipationResponse {
+    pub result: bool,
+}
+
+#[derive(Debug, EndianWrite)]
+pub struct FindByIDRequest {
+    pub gids: NexList<u32>,
+}
+
+impl_bounded_endian_read!(FindByIDRequest { gids: read_list });
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct FindByIDResponse {
+    pub gatherings: NexList<DataHolder<Gathering>>,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct FindBySingleIDRequest {
+    pub gid: u32,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct FindBySingleIDResponse {
+    pub result: bool,
+    pub gathering: DataHolder<Gathering>,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct GetSessionURLsRequest {
+    pub gid: u32,
+}
+
+#[derive(Debug, EndianRead, EndianWrite)]
+pub struct GetSessionURLsResponse {
+    pub station_urls: NexList<NexString>,
+}
+
+#[derive(Debug, EndianWrite)]
+pub struct MigrateGatheringOwnershipRequest {
+    pub gid: u32,
+    pub potential_new_owners: NexList<u32>,
+    pub participants_only: bool,
+}
+
+impl_bounded_endian_read!(MigrateGatheringOwnershipRequest {
+    gid,
+    potential_new_owners: read_list,
+    participants_only,
+});
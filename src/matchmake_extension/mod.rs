@@ -1,5 +1,11 @@
 mod protocol;
 pub use protocol::*;
 
+mod registry;
+pub use registry::*;
+
+mod search_criteria;
+pub use search_criteria::*;
+
 mod types;
 pub use types::*;
@@ -1,7 +1,16 @@
-use crate::matchmake_extension::MatchmakeSessionSearchCriteria;
+use crate::client_context::ClientContextProvider;
+use crate::matchmake_extension::{
+    AutoMatchmakeParam, CreateMatchmakeSessionParam, JoinMatchmakeSessionParam,
+    MatchmakeSessionSearchCriteria, PersistentGathering, UpdateMatchmakeSessionParam,
+};
+use crate::middleware::{HandlerResult, RequestPipeline};
+#[cfg(feature = "tracing")]
+use crate::request_trace::request_span;
+use crate::request_trace::trace_parameters;
+use crate::structure::{read_list, NexStructure};
 use async_trait::async_trait;
 use nex_rs::client::ClientConnection;
-use nex_rs::nex_types::{ResultCode, ResultRange};
+use nex_rs::nex_types::{NexList, NexString, ResultCode, ResultRange};
 use nex_rs::packet::{Packet, PacketV1};
 use nex_rs::server::Server;
 use no_std_io::{StreamContainer, StreamReader};
@@ -16,12 +25,23 @@ pub enum MatchmakeExtensionMethod {
     OpenParticipation = 0x2,
     BrowseMatchmakeSession = 0x4,
     BrowseMatchmakeSessionWithHostUrls = 0x5,
+    CreateCommunity = 0x11,
+    FindCommunityByGatheringID = 0x14,
+    FindOfficialCommunity = 0x15,
+    FindCommunityByParticipant = 0x16,
+    JoinMatchmakeSessionEx = 0x1E,
+    GetSimplePlayingSession = 0x1F,
+    GetSimpleCommunity = 0x20,
+    CreateMatchmakeSessionWithParam = 0x26,
+    JoinMatchmakeSessionWithParam = 0x27,
+    AutoMatchmakeWithParamPostpone = 0x28,
+    UpdateMatchmakeSessionPart = 0x2C,
     GetAttractionStatus = 0x31,
     SimpleMatchmake = 0x33,
 }
 
 #[async_trait(?Send)]
-pub trait MatchmakeExtensionProtocol: Server {
+pub trait MatchmakeExtensionProtocol: Server + ClientContextProvider {
     async fn close_participation(
         &self,
         client: &mut ClientConnection,
@@ -44,6 +64,92 @@ pub trait MatchmakeExtensionProtocol: Server {
         matchmake_session_search_criteria: MatchmakeSessionSearchCriteria,
         result_range: ResultRange,
     ) -> Result<Vec<u8>, ResultCode>;
+    // Methods added after the protocol was first published default to Core_NotImplemented,
+    // so existing implementations keep compiling.
+    async fn create_community(
+        &self,
+        _client: &mut ClientConnection,
+        _community: PersistentGathering,
+        _message: String,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+    async fn find_community_by_gathering_id(
+        &self,
+        _client: &mut ClientConnection,
+        _gids: NexList<u32>,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+    async fn find_official_community(
+        &self,
+        _client: &mut ClientConnection,
+        _is_available_only: bool,
+        _result_range: ResultRange,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+    async fn find_community_by_participant(
+        &self,
+        _client: &mut ClientConnection,
+        _pid: u32,
+        _result_range: ResultRange,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+    async fn join_matchmake_session_ex(
+        &self,
+        _client: &mut ClientConnection,
+        _gid: u32,
+        _message: String,
+        _dont_care_my_block_list: bool,
+        _participation_count: u16,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+    async fn get_simple_playing_session(
+        &self,
+        _client: &mut ClientConnection,
+        _pids: NexList<u32>,
+        _include_login_user: bool,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+    async fn get_simple_community(
+        &self,
+        _client: &mut ClientConnection,
+        _gids: NexList<u32>,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+    async fn create_matchmake_session_with_param(
+        &self,
+        _client: &mut ClientConnection,
+        _create_matchmake_session_param: CreateMatchmakeSessionParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+    async fn join_matchmake_session_with_param(
+        &self,
+        _client: &mut ClientConnection,
+        _join_matchmake_session_param: JoinMatchmakeSessionParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+    async fn auto_matchmake_with_param_postpone(
+        &self,
+        _client: &mut ClientConnection,
+        _auto_matchmake_param: AutoMatchmakeParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+    async fn update_matchmake_session_part(
+        &self,
+        _client: &mut ClientConnection,
+        _update_matchmake_session_param: UpdateMatchmakeSessionParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
     async fn get_attraction_status(
         &self,
         client: &mut ClientConnection,
@@ -54,11 +160,133 @@ pub trait MatchmakeExtensionProtocol: Server {
         group_id: u32,
     ) -> Result<Vec<u8>, ResultCode>;
 
-    async fn handle_close_participation(
+    /// Dispatches a request to its method's handler.
+    async fn handle_request(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
     ) -> Result<(), &'static str> {
+        let rmc_request = packet.get_rmc_request();
+        let method = MatchmakeExtensionMethod::try_from(rmc_request.method_id);
+
+        #[cfg(feature = "tracing")]
+        let span = request_span(
+            "MatchmakeExtension",
+            method.as_ref().ok(),
+            rmc_request.method_id,
+            rmc_request.call_id,
+            client.get_pid(),
+        );
+
+        let dispatch = async move {
+            let request = match self.begin_request(client, packet, true) {
+                Ok(request) => request,
+                Err(error_code) => {
+                    return self.send_response(client, packet, Err(error_code)).await
+                }
+            };
+
+            let result = match method {
+                Ok(MatchmakeExtensionMethod::CloseParticipation) => {
+                    self.handle_close_participation(client, packet).await
+                }
+                Ok(MatchmakeExtensionMethod::OpenParticipation) => {
+                    self.handle_open_participation(client, packet).await
+                }
+                Ok(MatchmakeExtensionMethod::BrowseMatchmakeSession) => {
+                    self.handle_browse_matchmake_session(client, packet).await
+                }
+                Ok(MatchmakeExtensionMethod::BrowseMatchmakeSessionWithHostUrls) => {
+                    self.handle_browse_matchmake_session_with_host_urls(client, packet)
+                        .await
+                }
+                Ok(MatchmakeExtensionMethod::CreateCommunity) => {
+                    self.handle_create_community(client, packet).await
+                }
+                Ok(MatchmakeExtensionMethod::FindCommunityByGatheringID) => {
+                    self.handle_find_community_by_gathering_id(client, packet)
+                        .await
+                }
+                Ok(MatchmakeExtensionMethod::FindOfficialCommunity) => {
+                    self.handle_find_official_community(client, packet).await
+                }
+                Ok(MatchmakeExtensionMethod::FindCommunityByParticipant) => {
+                    self.handle_find_community_by_participant(client, packet)
+                        .await
+                }
+                Ok(MatchmakeExtensionMethod::JoinMatchmakeSessionEx) => {
+                    self.handle_join_matchmake_session_ex(client, packet).await
+                }
+                Ok(MatchmakeExtensionMethod::GetSimplePlayingSession) => {
+                    self.handle_get_simple_playing_session(client, packet).await
+                }
+                Ok(MatchmakeExtensionMethod::GetSimpleCommunity) => {
+                    self.handle_get_simple_community(client, packet).await
+                }
+                Ok(MatchmakeExtensionMethod::CreateMatchmakeSessionWithParam) => {
+                    self.handle_create_matchmake_session_with_param(client, packet)
+                        .await
+                }
+                Ok(MatchmakeExtensionMethod::JoinMatchmakeSessionWithParam) => {
+                    self.handle_join_matchmake_session_with_param(client, packet)
+                        .await
+                }
+                Ok(MatchmakeExtensionMethod::AutoMatchmakeWithParamPostpone) => {
+                    self.handle_auto_matchmake_with_param_postpone(client, packet)
+                        .await
+                }
+                Ok(MatchmakeExtensionMethod::UpdateMatchmakeSessionPart) => {
+                    self.handle_update_matchmake_session_part(client, packet)
+                        .await
+                }
+                Ok(MatchmakeExtensionMethod::GetAttractionStatus) => {
+                    self.handle_get_attraction_status(client, packet).await
+                }
+                Ok(MatchmakeExtensionMethod::SimpleMatchmake) => {
+                    self.handle_simple_matchmake(client, packet).await
+                }
+                Err(_) => self.handle_unknown_method(client, packet).await,
+            };
+
+            self.finish_request(client, request, result).await
+        };
+
+        #[cfg(feature = "tracing")]
+        let dispatch = tracing::Instrument::instrument(dispatch, span);
+
+        dispatch.await
+    }
+
+    /// Called for method ids without a handler, e.g. to prototype undocumented methods.
+    /// Responds with `Core_NotImplemented` by default.
+    async fn handle_unknown(
+        &self,
+        _client: &mut ClientConnection,
+        _method_id: u32,
+        _parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn handle_unknown_method(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+
+        let result = self
+            .handle_unknown(client, request.method_id, parameters)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_close_participation(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
         let mut parameters_stream = StreamContainer::new(parameters);
@@ -67,36 +295,16 @@ pub trait MatchmakeExtensionProtocol: Server {
             .read_stream_le::<u32>()
             .map_err(|_| "Can not read group id")?;
 
-        match self.close_participation(client, gid).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        trace_parameters!(gid);
+        let result = self.close_participation(client, gid).await;
+        Ok(result)
     }
 
     async fn handle_open_participation(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
         let mut parameters_stream = StreamContainer::new(parameters);
@@ -105,161 +313,338 @@ pub trait MatchmakeExtensionProtocol: Server {
             .read_stream_le::<u32>()
             .map_err(|_| "Can not read group id")?;
 
-        match self.open_participation(client, gid).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        trace_parameters!(gid);
+        let result = self.open_participation(client, gid).await;
+        Ok(result)
     }
 
     async fn handle_browse_matchmake_session(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
         let mut parameters_stream = StreamContainer::new(parameters);
+        let match_making_version = self.get_title_profile(client).match_making_version;
 
-        let matchmake_session_search_criteria = parameters_stream
-            .read_stream_le::<MatchmakeSessionSearchCriteria>()
-            .map_err(|_| "Can not read matchmake session search criteria")?;
+        let matchmake_session_search_criteria = MatchmakeSessionSearchCriteria::read_structure(
+            &mut parameters_stream,
+            match_making_version,
+        )
+        .map_err(|_| "Can not read matchmake session search criteria")?;
 
         let result_range = parameters_stream
             .read_stream_le::<ResultRange>()
             .map_err(|_| "Can not read result range")?;
 
-        match self
+        trace_parameters!(matchmake_session_search_criteria, result_range);
+        let result = self
             .browse_matchmake_session(client, matchmake_session_search_criteria, result_range)
-            .await
-        {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+            .await;
+        Ok(result)
     }
 
     async fn handle_browse_matchmake_session_with_host_urls(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
         let mut parameters_stream = StreamContainer::new(parameters);
+        let match_making_version = self.get_title_profile(client).match_making_version;
 
-        let matchmake_session_search_criteria = parameters_stream
-            .read_stream_le::<MatchmakeSessionSearchCriteria>()
-            .map_err(|_| "Can not read matchmake session search criteria")?;
+        let matchmake_session_search_criteria = MatchmakeSessionSearchCriteria::read_structure(
+            &mut parameters_stream,
+            match_making_version,
+        )
+        .map_err(|_| "Can not read matchmake session search criteria")?;
 
         let result_range = parameters_stream
             .read_stream_le::<ResultRange>()
             .map_err(|_| "Can not read result range")?;
 
-        match self
+        trace_parameters!(matchmake_session_search_criteria, result_range);
+        let result = self
             .browse_matchmake_session_with_host_urls(
                 client,
                 matchmake_session_search_criteria,
                 result_range,
             )
-            .await
-        {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+            .await;
+        Ok(result)
     }
 
-    async fn handle_get_attraction_status(
+    async fn handle_create_community(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+        let match_making_version = self.get_title_profile(client).match_making_version;
+
+        let community =
+            PersistentGathering::read_structure(&mut parameters_stream, match_making_version)
+                .map_err(|_| "Can not read community")?;
+
+        let message: String = parameters_stream
+            .read_stream_le::<NexString>()
+            .map_err(|_| "Can not read message")?
+            .into();
+
+        trace_parameters!(community, message);
+        let result = self.create_community(client, community, message).await;
+        Ok(result)
+    }
+
+    async fn handle_find_community_by_gathering_id(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let gids =
+            read_list::<u32>(&mut parameters_stream).map_err(|_| "Can not read group ids")?;
+
+        trace_parameters!(gids);
+        let result = self.find_community_by_gathering_id(client, gids).await;
+        Ok(result)
+    }
+
+    async fn handle_find_official_community(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let is_available_only = parameters_stream
+            .read_stream_le::<bool>()
+            .map_err(|_| "Can not read is available only bool")?;
+
+        let result_range = parameters_stream
+            .read_stream_le::<ResultRange>()
+            .map_err(|_| "Can not read result range")?;
+
+        trace_parameters!(is_available_only, result_range);
+        let result = self
+            .find_official_community(client, is_available_only, result_range)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_find_community_by_participant(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let pid = parameters_stream
+            .read_stream_le::<u32>()
+            .map_err(|_| "Can not read pid")?;
+
+        let result_range = parameters_stream
+            .read_stream_le::<ResultRange>()
+            .map_err(|_| "Can not read result range")?;
+
+        trace_parameters!(pid, result_range);
+        let result = self
+            .find_community_by_participant(client, pid, result_range)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_join_matchmake_session_ex(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let gid = parameters_stream
+            .read_stream_le::<u32>()
+            .map_err(|_| "Can not read group id")?;
+
+        let message: String = parameters_stream
+            .read_stream_le::<NexString>()
+            .map_err(|_| "Can not read message")?
+            .into();
+
+        let dont_care_my_block_list = parameters_stream
+            .read_stream_le::<bool>()
+            .map_err(|_| "Can not read dont care my block list bool")?;
+
+        let participation_count = parameters_stream
+            .read_stream_le::<u16>()
+            .map_err(|_| "Can not read participation count")?;
+
+        trace_parameters!(gid, message, dont_care_my_block_list, participation_count);
+        let result = self
+            .join_matchmake_session_ex(
+                client,
+                gid,
+                message,
+                dont_care_my_block_list,
+                participation_count,
+            )
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_get_simple_playing_session(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let pids = read_list::<u32>(&mut parameters_stream).map_err(|_| "Can not read pids")?;
+
+        let include_login_user = parameters_stream
+            .read_stream_le::<bool>()
+            .map_err(|_| "Can not read include login user bool")?;
+
+        trace_parameters!(pids, include_login_user);
+        let result = self
+            .get_simple_playing_session(client, pids, include_login_user)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_get_simple_community(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let gids =
+            read_list::<u32>(&mut parameters_stream).map_err(|_| "Can not read group ids")?;
+
+        trace_parameters!(gids);
+        let result = self.get_simple_community(client, gids).await;
+        Ok(result)
+    }
+
+    async fn handle_create_matchmake_session_with_param(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
-        match self.get_attraction_status(client).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+        let match_making_version = self.get_title_profile(client).match_making_version;
+
+        let create_matchmake_session_param = CreateMatchmakeSessionParam::read_structure(
+            &mut parameters_stream,
+            match_making_version,
+        )
+        .map_err(|_| "Can not read create matchmake session param")?;
+
+        trace_parameters!(create_matchmake_session_param);
+        let result = self
+            .create_matchmake_session_with_param(client, create_matchmake_session_param)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_join_matchmake_session_with_param(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+        let match_making_version = self.get_title_profile(client).match_making_version;
+
+        let join_matchmake_session_param =
+            JoinMatchmakeSessionParam::read_structure(&mut parameters_stream, match_making_version)
+                .map_err(|_| "Can not read join matchmake session param")?;
+
+        trace_parameters!(join_matchmake_session_param);
+        let result = self
+            .join_matchmake_session_with_param(client, join_matchmake_session_param)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_auto_matchmake_with_param_postpone(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+        let match_making_version = self.get_title_profile(client).match_making_version;
+
+        let auto_matchmake_param =
+            AutoMatchmakeParam::read_structure(&mut parameters_stream, match_making_version)
+                .map_err(|_| "Can not read auto matchmake param")?;
+
+        trace_parameters!(auto_matchmake_param);
+        let result = self
+            .auto_matchmake_with_param_postpone(client, auto_matchmake_param)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_update_matchmake_session_part(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+        let match_making_version = self.get_title_profile(client).match_making_version;
+
+        let update_matchmake_session_param = UpdateMatchmakeSessionParam::read_structure(
+            &mut parameters_stream,
+            match_making_version,
+        )
+        .map_err(|_| "Can not read update matchmake session param")?;
+
+        trace_parameters!(update_matchmake_session_param);
+        let result = self
+            .update_matchmake_session_part(client, update_matchmake_session_param)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_get_attraction_status(
+        &self,
+        client: &mut ClientConnection,
+        _packet: &PacketV1,
+    ) -> HandlerResult {
+        let result = self.get_attraction_status(client).await;
+        Ok(result)
     }
 
     async fn handle_simple_matchmake(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
         let mut parameters_stream = StreamContainer::new(parameters);
@@ -268,28 +653,8 @@ pub trait MatchmakeExtensionProtocol: Server {
             .read_stream_le::<u32>()
             .map_err(|_| "Can not read group id")?;
 
-        match self.simple_matchmake(client, group_id).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        trace_parameters!(group_id);
+        let result = self.simple_matchmake(client, group_id).await;
+        Ok(result)
     }
 }
@@ -0,0 +1,1026 @@
+use crate::client_context::ClientContextProvider;
+use crate::matchmake_extension::{
+    AutoMatchmakeParam, CreateMatchmakeSessionParam, GatheringURLs, JoinMatchmakeSessionParam,
+    MatchmakeExtensionProtocol, MatchmakeSession, MatchmakeSessionFilter,
+    MatchmakeSessionSearchCriteria, SimplePlayingSession, UpdateMatchmakeSessionParam,
+};
+use crate::structure::{write_data_holder_structure, NexStructure, NexVersion};
+use async_trait::async_trait;
+use nex_rs::client::ClientConnection;
+use nex_rs::nex_types::{NexList, NexString, ResultCode, ResultRange};
+use nex_rs::server::Server;
+use no_std_io::{EndianWrite, StreamContainer, StreamWriter};
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hasher};
+
+pub const SESSION_KEY_SIZE: usize = 32;
+
+pub const MODIFICATION_FLAG_ATTRIBUTES: u32 = 0x1;
+pub const MODIFICATION_FLAG_OPEN_PARTICIPATION: u32 = 0x2;
+pub const MODIFICATION_FLAG_APPLICATION_BUFFER: u32 = 0x4;
+pub const MODIFICATION_FLAG_PROGRESS_SCORE: u32 = 0x8;
+pub const MODIFICATION_FLAG_MATCHMAKE_PARAM: u32 = 0x10;
+pub const MODIFICATION_FLAG_STARTED_TIME: u32 = 0x20;
+pub const MODIFICATION_FLAG_USER_PASSWORD: u32 = 0x40;
+pub const MODIFICATION_FLAG_GAME_MODE: u32 = 0x80;
+pub const MODIFICATION_FLAG_DESCRIPTION: u32 = 0x100;
+pub const MODIFICATION_FLAG_MIN_PARTICIPANTS: u32 = 0x200;
+pub const MODIFICATION_FLAG_MAX_PARTICIPANTS: u32 = 0x400;
+pub const MODIFICATION_FLAG_MATCHMAKE_SYSTEM_TYPE: u32 = 0x800;
+pub const MODIFICATION_FLAG_PARTICIPATION_POLICY: u32 = 0x1000;
+pub const MODIFICATION_FLAG_POLICY_ARGUMENT: u32 = 0x2000;
+
+/// Not suitable for secrets, only for picking between sessions.
+pub(crate) fn generate_random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Session keys secure the peer to peer connections, so they come from the OS RNG.
+fn generate_session_key() -> Result<Vec<u8>, ResultCode> {
+    let mut session_key = vec![0; SESSION_KEY_SIZE];
+    getrandom::getrandom(&mut session_key).map_err(|_| ResultCode::Core_SystemError)?;
+    Ok(session_key)
+}
+
+fn write_response<T: EndianWrite>(value: &T) -> Result<Vec<u8>, ResultCode> {
+    let mut stream = StreamContainer::new(vec![]);
+    stream
+        .write_stream_le(value)
+        .map_err(|_| ResultCode::Core_SystemError)?;
+    Ok(stream.into_raw())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Participant {
+    pid: u32,
+    participation_count: u16,
+}
+
+struct MatchmakeSessionEntry {
+    session: MatchmakeSession,
+    user_password: String,
+    participants: Vec<Participant>,
+}
+
+impl MatchmakeSessionEntry {
+    fn get_participation_count(&self) -> u32 {
+        self.participants
+            .iter()
+            .map(|participant| u32::from(participant.participation_count))
+            .sum()
+    }
+
+    fn is_participant(&self, pid: u32) -> bool {
+        self.participants
+            .iter()
+            .any(|participant| participant.pid == pid)
+    }
+
+    fn is_joinable(&self, participation_count: u16) -> bool {
+        let max_participants = u32::from(self.session.gathering.max_participants);
+        self.session.open_participation
+            && self.get_participation_count() + u32::from(participation_count) <= max_participants
+    }
+
+    fn update_participation_count(&mut self) {
+        self.session.participation_count = self.get_participation_count();
+    }
+}
+
+#[derive(Default)]
+struct MatchmakeRegistryState {
+    last_gid: u32,
+    sessions: BTreeMap<u32, MatchmakeSessionEntry>,
+}
+
+impl MatchmakeRegistryState {
+    /// Returns the next gid without a live session, skipping gids still in use after a wrap.
+    fn next_gid(&mut self) -> Result<u32, ResultCode> {
+        for _ in 0..=self.sessions.len() {
+            self.last_gid = self.last_gid.wrapping_add(1).max(1);
+
+            if !self.sessions.contains_key(&self.last_gid) {
+                return Ok(self.last_gid);
+            }
+        }
+
+        Err(ResultCode::Core_SystemError)
+    }
+}
+
+/// An in-memory gathering registry for matchmake sessions.
+///
+/// Session owners and hosts are migrated to the next participant when they leave,
+/// and sessions are removed once their last participant leaves.
+#[derive(Default)]
+pub struct MatchmakeRegistry {
+    state: RefCell<MatchmakeRegistryState>,
+}
+
+impl MatchmakeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create_session(
+        &self,
+        pid: u32,
+        mut session: MatchmakeSession,
+        participation_count: u16,
+    ) -> Result<u32, ResultCode> {
+        let min_participants = session.gathering.min_participants;
+        let max_participants = session.gathering.max_participants;
+        let participation_count = participation_count.max(1);
+
+        if max_participants == 0 || min_participants > max_participants {
+            return Err(ResultCode::Core_InvalidArgument);
+        }
+
+        if participation_count > max_participants {
+            return Err(ResultCode::RendezVous_SessionFull);
+        }
+
+        let session_key = generate_session_key()?;
+        let mut state = self.state.borrow_mut();
+        let gid = state.next_gid()?;
+
+        session.gathering.id = gid;
+        session.gathering.owner_pid = pid;
+        session.gathering.host_pid = pid;
+        session.session_key = session_key.into();
+
+        let user_password: String = session.user_password.clone().into();
+        let mut entry = MatchmakeSessionEntry {
+            session,
+            user_password,
+            participants: vec![Participant {
+                pid,
+                participation_count,
+            }],
+        };
+        entry.update_participation_count();
+
+        state.sessions.insert(gid, entry);
+        Ok(gid)
+    }
+
+    /// Joins a session. `user_password` is `None` for requests that can't carry a password,
+    /// which can't join sessions with a user password.
+    pub fn join_session(
+        &self,
+        pid: u32,
+        gid: u32,
+        user_password: Option<&str>,
+        participation_count: u16,
+    ) -> Result<(), ResultCode> {
+        let mut state = self.state.borrow_mut();
+        let entry = state
+            .sessions
+            .get_mut(&gid)
+            .ok_or(ResultCode::RendezVous_SessionVoid)?;
+
+        if entry.is_participant(pid) {
+            return Ok(());
+        }
+
+        if !entry.session.open_participation {
+            return Err(ResultCode::RendezVous_SessionClosed);
+        }
+
+        if entry.session.user_password_enabled && user_password != Some(&entry.user_password) {
+            return Err(ResultCode::RendezVous_InvalidPassword);
+        }
+
+        let participation_count = participation_count.max(1);
+
+        if !entry.is_joinable(participation_count) {
+            return Err(ResultCode::RendezVous_SessionFull);
+        }
+
+        entry.participants.push(Participant {
+            pid,
+            participation_count,
+        });
+        entry.update_participation_count();
+        Ok(())
+    }
+
+    pub fn leave_session(&self, pid: u32, gid: u32) -> Result<(), ResultCode> {
+        let mut state = self.state.borrow_mut();
+        let entry = state
+            .sessions
+            .get_mut(&gid)
+            .ok_or(ResultCode::RendezVous_SessionVoid)?;
+
+        if !entry.is_participant(pid) {
+            return Err(ResultCode::RendezVous_NotParticipatedGathering);
+        }
+
+        entry
+            .participants
+            .retain(|participant| participant.pid != pid);
+
+        let next_pid = match entry.participants.first() {
+            Some(participant) => participant.pid,
+            None => {
+                state.sessions.remove(&gid);
+                return Ok(());
+            }
+        };
+
+        if entry.session.gathering.owner_pid == pid {
+            entry.session.gathering.owner_pid = next_pid;
+        }
+
+        if entry.session.gathering.host_pid == pid {
+            entry.session.gathering.host_pid = next_pid;
+        }
+
+        entry.update_participation_count();
+        Ok(())
+    }
+
+    /// Removes a participant from every session, e.g. when their connection drops.
+    pub fn leave_all_sessions(&self, pid: u32) {
+        for gid in self.get_participating_session_ids(pid) {
+            // The gid was just looked up, so the session can only be missing if it was already removed
+            let _ = self.leave_session(pid, gid);
+        }
+    }
+
+    /// Removes every participant `is_connected` rejects from their sessions,
+    /// returning the removed pids.
+    pub fn leave_disconnected(&self, mut is_connected: impl FnMut(u32) -> bool) -> Vec<u32> {
+        let mut pids: Vec<u32> = self
+            .state
+            .borrow()
+            .sessions
+            .values()
+            .flat_map(|entry| entry.participants.iter().map(|participant| participant.pid))
+            .collect();
+        pids.sort_unstable();
+        pids.dedup();
+        pids.retain(|pid| !is_connected(*pid));
+
+        for pid in pids.iter() {
+            self.leave_all_sessions(*pid);
+        }
+
+        pids
+    }
+
+    /// Finds sessions without participants, with a stale participation count,
+    /// or with an owner or host that no longer participates.
+    pub fn find_inconsistent_session_ids(&self) -> Vec<u32> {
+        self.state
+            .borrow()
+            .sessions
+            .iter()
+            .filter(|(_, entry)| {
+                let gathering = &entry.session.gathering;
+                entry.participants.is_empty()
+                    || entry.session.participation_count != entry.get_participation_count()
+                    || !entry.is_participant(gathering.owner_pid)
+                    || !entry.is_participant(gathering.host_pid)
+            })
+            .map(|(gid, _)| *gid)
+            .collect()
+    }
+
+    /// Fixes the sessions [Self::find_inconsistent_session_ids] reports,
+    /// returning how many were fixed or removed.
+    pub fn repair_sessions(&self) -> usize {
+        let gids = self.find_inconsistent_session_ids();
+        let mut state = self.state.borrow_mut();
+
+        for gid in gids.iter() {
+            let entry = match state.sessions.get_mut(gid) {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            let first_pid = match entry.participants.first() {
+                Some(participant) => participant.pid,
+                None => {
+                    state.sessions.remove(gid);
+                    continue;
+                }
+            };
+
+            if !entry.is_participant(entry.session.gathering.owner_pid) {
+                entry.session.gathering.owner_pid = first_pid;
+            }
+
+            if !entry.is_participant(entry.session.gathering.host_pid) {
+                entry.session.gathering.host_pid = first_pid;
+            }
+
+            entry.update_participation_count();
+        }
+
+        gids.len()
+    }
+
+    /// Sets a host without checking it participates, to test consistency checks.
+    #[cfg(test)]
+    pub(crate) fn set_host_pid_unchecked(&self, gid: u32, host_pid: u32) {
+        if let Some(entry) = self.state.borrow_mut().sessions.get_mut(&gid) {
+            entry.session.gathering.host_pid = host_pid;
+        }
+    }
+
+    pub fn set_open_participation(
+        &self,
+        pid: u32,
+        gid: u32,
+        open_participation: bool,
+    ) -> Result<(), ResultCode> {
+        let mut state = self.state.borrow_mut();
+        let entry = state
+            .sessions
+            .get_mut(&gid)
+            .ok_or(ResultCode::RendezVous_SessionVoid)?;
+
+        if entry.session.gathering.owner_pid != pid {
+            return Err(ResultCode::RendezVous_PermissionDenied);
+        }
+
+        entry.session.open_participation = open_participation;
+        Ok(())
+    }
+
+    pub fn update_session(
+        &self,
+        pid: u32,
+        param: UpdateMatchmakeSessionParam,
+    ) -> Result<(), ResultCode> {
+        let mut state = self.state.borrow_mut();
+        let entry = state
+            .sessions
+            .get_mut(&param.gid)
+            .ok_or(ResultCode::RendezVous_SessionVoid)?;
+
+        if entry.session.gathering.owner_pid != pid {
+            return Err(ResultCode::RendezVous_PermissionDenied);
+        }
+
+        let flag = param.modification_flag;
+        let min_participants = if flag & MODIFICATION_FLAG_MIN_PARTICIPANTS != 0 {
+            param.min_participants
+        } else {
+            entry.session.gathering.min_participants
+        };
+        let max_participants = if flag & MODIFICATION_FLAG_MAX_PARTICIPANTS != 0 {
+            param.max_participants
+        } else {
+            entry.session.gathering.max_participants
+        };
+
+        if max_participants == 0 || min_participants > max_participants {
+            return Err(ResultCode::Core_InvalidArgument);
+        }
+
+        if u32::from(max_participants) < entry.get_participation_count() {
+            return Err(ResultCode::RendezVous_SessionFull);
+        }
+
+        let session = &mut entry.session;
+
+        if flag & MODIFICATION_FLAG_ATTRIBUTES != 0 {
+            session.attributes = param.attributes;
+        }
+
+        if flag & MODIFICATION_FLAG_OPEN_PARTICIPATION != 0 {
+            session.open_participation = param.open_participation;
+        }
+
+        if flag & MODIFICATION_FLAG_APPLICATION_BUFFER != 0 {
+            session.application_buffer = param.application_buffer;
+        }
+
+        if flag & MODIFICATION_FLAG_PROGRESS_SCORE != 0 {
+            session.progress_score = param.progress_score;
+        }
+
+        if flag & MODIFICATION_FLAG_MATCHMAKE_PARAM != 0 {
+            session.matchmake_param = param.matchmake_param;
+        }
+
+        if flag & MODIFICATION_FLAG_STARTED_TIME != 0 {
+            session.started_time = param.started_time;
+        }
+
+        if flag & MODIFICATION_FLAG_USER_PASSWORD != 0 {
+            entry.user_password = param.user_password.clone().into();
+            session.user_password = param.user_password;
+        }
+
+        if flag & MODIFICATION_FLAG_GAME_MODE != 0 {
+            session.game_mode = param.game_mode;
+        }
+
+        if flag & MODIFICATION_FLAG_DESCRIPTION != 0 {
+            session.gathering.description = param.description;
+        }
+
+        if flag & MODIFICATION_FLAG_MIN_PARTICIPANTS != 0 {
+            session.gathering.min_participants = param.min_participants;
+        }
+
+        if flag & MODIFICATION_FLAG_MAX_PARTICIPANTS != 0 {
+            session.gathering.max_participants = param.max_participants;
+        }
+
+        if flag & MODIFICATION_FLAG_MATCHMAKE_SYSTEM_TYPE != 0 {
+            session.matchmake_system_type = param.matchmake_system_type;
+        }
+
+        if flag & MODIFICATION_FLAG_PARTICIPATION_POLICY != 0 {
+            session.gathering.participation_policy = param.participation_policy;
+        }
+
+        if flag & MODIFICATION_FLAG_POLICY_ARGUMENT != 0 {
+            session.gathering.policy_argument = param.policy_argument;
+        }
+
+        Ok(())
+    }
+
+    pub fn with_session<T>(&self, gid: u32, f: impl FnOnce(&MatchmakeSession) -> T) -> Option<T> {
+        let state = self.state.borrow();
+        state.sessions.get(&gid).map(|entry| f(&entry.session))
+    }
+
+    pub fn is_owner(&self, gid: u32, pid: u32) -> bool {
+        self.with_session(gid, |session| session.gathering.owner_pid == pid)
+            .unwrap_or(false)
+    }
+
+    pub fn get_participants(&self, gid: u32) -> Option<Vec<u32>> {
+        let state = self.state.borrow();
+        let entry = state.sessions.get(&gid)?;
+        Some(
+            entry
+                .participants
+                .iter()
+                .map(|participant| participant.pid)
+                .collect(),
+        )
+    }
+
+    pub fn get_participating_session_ids(&self, pid: u32) -> Vec<u32> {
+        let state = self.state.borrow();
+        state
+            .sessions
+            .iter()
+            .filter(|(_, entry)| entry.is_participant(pid))
+            .map(|(gid, _)| *gid)
+            .collect()
+    }
+
+    /// Returns the ids of sessions that can take the given number of participants,
+    /// ordered by gid and filtered by `filter`.
+    pub fn find_joinable_session_ids(
+        &self,
+        participation_count: u16,
+        mut filter: impl FnMut(&MatchmakeSession) -> bool,
+    ) -> Vec<u32> {
+        let state = self.state.borrow();
+        state
+            .sessions
+            .iter()
+            .filter(|(_, entry)| entry.is_joinable(participation_count.max(1)))
+            .filter(|(_, entry)| filter(&entry.session))
+            .map(|(gid, _)| *gid)
+            .collect()
+    }
+
+    /// Returns the ids of joinable sessions matching `filter`, ordered by its selection method.
+    pub fn search_session_ids(
+        &self,
+        filter: &MatchmakeSessionFilter,
+        participation_count: u16,
+        progress_score: u8,
+    ) -> Vec<u32> {
+        let state = self.state.borrow();
+        let sessions = state
+            .sessions
+            .values()
+            .filter(|entry| entry.is_joinable(participation_count.max(1)))
+            .map(|entry| &entry.session);
+
+        filter.select(sessions, progress_score)
+    }
+
+    fn write_session(&self, gid: u32, nex_version: NexVersion) -> Result<Vec<u8>, ResultCode> {
+        self.with_session(gid, |session| session.write_structure(nex_version))
+            .ok_or(ResultCode::RendezVous_SessionVoid)?
+            .map_err(|_| ResultCode::Core_SystemError)
+    }
+
+    fn write_session_list(
+        &self,
+        gids: &[u32],
+        nex_version: NexVersion,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let mut data = (gids.len() as u32).to_le_bytes().to_vec();
+
+        for gid in gids {
+            let session = self
+                .with_session(*gid, |session| {
+                    write_data_holder_structure("MatchmakeSession", session, nex_version)
+                })
+                .ok_or(ResultCode::RendezVous_SessionVoid)?
+                .map_err(|_| ResultCode::Core_SystemError)?;
+            data.extend(session);
+        }
+
+        Ok(data)
+    }
+
+    /// Joins the first session a filter finds, trying the filters in order,
+    /// or creates `session` if none can be joined. Returns the gid.
+    pub fn auto_matchmake(
+        &self,
+        pid: u32,
+        filters: &[MatchmakeSessionFilter],
+        session: MatchmakeSession,
+        participation_count: u16,
+    ) -> Result<u32, ResultCode> {
+        let progress_score = session.progress_score;
+
+        for filter in filters {
+            for gid in self.search_session_ids(filter, participation_count, progress_score) {
+                if self
+                    .join_session(pid, gid, None, participation_count)
+                    .is_ok()
+                {
+                    return Ok(gid);
+                }
+            }
+        }
+
+        self.create_session(pid, session, participation_count)
+    }
+
+    fn browse_session_ids(
+        &self,
+        matchmake_session_search_criteria: MatchmakeSessionSearchCriteria,
+        result_range: &ResultRange,
+    ) -> Result<Vec<u32>, ResultCode> {
+        let filter = MatchmakeSessionFilter::try_from(matchmake_session_search_criteria)?;
+
+        Ok(self
+            .search_session_ids(&filter, 1, 0)
+            .into_iter()
+            .skip(result_range.offset as usize)
+            .take(result_range.length as usize)
+            .collect())
+    }
+}
+
+/// Implemented by servers that want [MatchmakeExtensionProtocol] backed by a [MatchmakeRegistry].
+/// Community and title specific methods respond with `Core_NotImplemented`; implement
+/// [MatchmakeExtensionProtocol] directly and call into the registry to customize them.
+///
+/// Host urls are the station urls the host registered with. Call
+/// [Self::remove_disconnected_participants] after disconnecting clients, or from a timer,
+/// so participants that left without a call don't keep their seats.
+pub trait MatchmakeRegistryServer: Server + ClientContextProvider {
+    fn get_matchmake_registry(&self) -> &MatchmakeRegistry;
+
+    /// Removes participants without a session in the
+    /// [ClientContextTable](crate::client_context::ClientContextTable) from their sessions,
+    /// returning the removed pids.
+    fn remove_disconnected_participants(&self) -> Vec<u32> {
+        let session_pids = self.get_client_context_table().get_session_pids();
+        self.get_matchmake_registry()
+            .leave_disconnected(|pid| session_pids.contains(&pid))
+    }
+}
+
+#[async_trait(?Send)]
+impl<T: MatchmakeRegistryServer> MatchmakeExtensionProtocol for T {
+    async fn close_participation(
+        &self,
+        client: &mut ClientConnection,
+        gid: u32,
+    ) -> Result<Vec<u8>, ResultCode> {
+        self.get_matchmake_registry()
+            .set_open_participation(client.get_pid(), gid, false)?;
+        Ok(vec![])
+    }
+
+    async fn open_participation(
+        &self,
+        client: &mut ClientConnection,
+        gid: u32,
+    ) -> Result<Vec<u8>, ResultCode> {
+        self.get_matchmake_registry()
+            .set_open_participation(client.get_pid(), gid, true)?;
+        Ok(vec![])
+    }
+
+    async fn browse_matchmake_session(
+        &self,
+        client: &mut ClientConnection,
+        matchmake_session_search_criteria: MatchmakeSessionSearchCriteria,
+        result_range: ResultRange,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let registry = self.get_matchmake_registry();
+        let match_making_version = self.get_title_profile(client).match_making_version;
+        let gids = registry.browse_session_ids(matchmake_session_search_criteria, &result_range)?;
+        registry.write_session_list(&gids, match_making_version)
+    }
+
+    async fn browse_matchmake_session_with_host_urls(
+        &self,
+        client: &mut ClientConnection,
+        matchmake_session_search_criteria: MatchmakeSessionSearchCriteria,
+        result_range: ResultRange,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let registry = self.get_matchmake_registry();
+        let match_making_version = self.get_title_profile(client).match_making_version;
+        let gids = registry.browse_session_ids(matchmake_session_search_criteria, &result_range)?;
+        let mut data = registry.write_session_list(&gids, match_making_version)?;
+
+        let gathering_urls: Vec<GatheringURLs> = gids
+            .iter()
+            .filter_map(|gid| {
+                let host_pid = registry.with_session(*gid, |session| session.gathering.host_pid)?;
+                let station_urls: Vec<NexString> = self
+                    .get_client_context_table()
+                    .find_session(host_pid)
+                    .map(|session| session.station_urls)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(NexString::from)
+                    .collect();
+
+                Some(GatheringURLs {
+                    gid: *gid,
+                    station_urls: NexList::from(station_urls),
+                })
+            })
+            .collect();
+
+        data.extend(write_response(&NexList::from(gathering_urls))?);
+        Ok(data)
+    }
+
+    async fn join_matchmake_session_ex(
+        &self,
+        client: &mut ClientConnection,
+        gid: u32,
+        _message: String,
+        _dont_care_my_block_list: bool,
+        participation_count: u16,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let registry = self.get_matchmake_registry();
+        // JoinMatchmakeSessionEx has no password, so protected sessions need the WithParam variant
+        registry.join_session(client.get_pid(), gid, None, participation_count)?;
+        registry
+            .with_session(gid, |session| write_response(&session.session_key))
+            .ok_or(ResultCode::RendezVous_SessionVoid)?
+    }
+
+    async fn get_simple_playing_session(
+        &self,
+        client: &mut ClientConnection,
+        pids: NexList<u32>,
+        include_login_user: bool,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let registry = self.get_matchmake_registry();
+        let mut pids: Vec<u32> = pids.into();
+
+        if include_login_user {
+            pids.push(client.get_pid());
+        }
+
+        let playing_sessions: Vec<SimplePlayingSession> = pids
+            .into_iter()
+            .flat_map(|pid| {
+                registry
+                    .get_participating_session_ids(pid)
+                    .into_iter()
+                    .filter_map(move |gid| {
+                        registry.with_session(gid, |session| SimplePlayingSession {
+                            principal_id: pid,
+                            gathering_id: gid,
+                            game_mode: session.game_mode,
+                            attribute_0: session.attributes.iter().next().copied().unwrap_or(0),
+                        })
+                    })
+            })
+            .collect();
+
+        write_response(&NexList::from(playing_sessions))
+    }
+
+    async fn create_matchmake_session_with_param(
+        &self,
+        client: &mut ClientConnection,
+        create_matchmake_session_param: CreateMatchmakeSessionParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let registry = self.get_matchmake_registry();
+        let match_making_version = self.get_title_profile(client).match_making_version;
+        let gid = registry.create_session(
+            client.get_pid(),
+            create_matchmake_session_param.source_matchmake_session,
+            create_matchmake_session_param.participation_count,
+        )?;
+        registry.write_session(gid, match_making_version)
+    }
+
+    async fn join_matchmake_session_with_param(
+        &self,
+        client: &mut ClientConnection,
+        join_matchmake_session_param: JoinMatchmakeSessionParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let registry = self.get_matchmake_registry();
+        let match_making_version = self.get_title_profile(client).match_making_version;
+        let gid = join_matchmake_session_param.gid;
+        let user_password: String = join_matchmake_session_param.user_password.into();
+
+        registry.join_session(
+            client.get_pid(),
+            gid,
+            Some(&user_password),
+            join_matchmake_session_param.participation_count,
+        )?;
+        registry.write_session(gid, match_making_version)
+    }
+
+    async fn auto_matchmake_with_param_postpone(
+        &self,
+        client: &mut ClientConnection,
+        auto_matchmake_param: AutoMatchmakeParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let registry = self.get_matchmake_registry();
+        let match_making_version = self.get_title_profile(client).match_making_version;
+        let search_criteria: Vec<MatchmakeSessionSearchCriteria> =
+            auto_matchmake_param.search_criteria.into();
+        let filters = search_criteria
+            .into_iter()
+            .map(MatchmakeSessionFilter::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let gid = registry.auto_matchmake(
+            client.get_pid(),
+            &filters,
+            auto_matchmake_param.source_matchmake_session,
+            auto_matchmake_param.participation_count,
+        )?;
+        registry.write_session(gid, match_making_version)
+    }
+
+    async fn update_matchmake_session_part(
+        &self,
+        client: &mut ClientConnection,
+        update_matchmake_session_param: UpdateMatchmakeSessionParam,
+    ) -> Result<Vec<u8>, ResultCode> {
+        self.get_matchmake_registry()
+            .update_session(client.get_pid(), update_matchmake_session_param)?;
+        Ok(vec![])
+    }
+
+    async fn get_attraction_status(
+        &self,
+        _client: &mut ClientConnection,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn simple_matchmake(
+        &self,
+        _client: &mut ClientConnection,
+        _group_id: u32,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matchmake_extension::MatchmakeSelectionMethod;
+
+    fn session(max_participants: u16) -> MatchmakeSession {
+        let mut session = MatchmakeSession {
+            open_participation: true,
+            ..Default::default()
+        };
+        session.gathering.min_participants = 1;
+        session.gathering.max_participants = max_participants;
+        session
+    }
+
+    fn owner_and_host(registry: &MatchmakeRegistry, gid: u32) -> Option<(u32, u32)> {
+        registry.with_session(gid, |session| {
+            (session.gathering.owner_pid, session.gathering.host_pid)
+        })
+    }
+
+    #[test]
+    fn creates_sessions_owned_by_the_creator() {
+        let registry = MatchmakeRegistry::new();
+        let gid = registry.create_session(1, session(4), 2).unwrap();
+
+        assert_eq!(gid, 1);
+        assert_eq!(owner_and_host(&registry, gid), Some((1, 1)));
+        assert_eq!(
+            registry.with_session(gid, |session| session.participation_count),
+            Some(2)
+        );
+        // A Buffer is written with a u32 length prefix
+        let session_key = registry
+            .with_session(gid, |session| write_response(&session.session_key))
+            .unwrap()
+            .unwrap();
+        assert_eq!(session_key.len(), 4 + SESSION_KEY_SIZE);
+        assert_eq!(registry.get_participants(gid), Some(vec![1]));
+    }
+
+    #[test]
+    fn rejects_invalid_capacities() {
+        let registry = MatchmakeRegistry::new();
+
+        assert_eq!(
+            registry.create_session(1, session(0), 1),
+            Err(ResultCode::Core_InvalidArgument)
+        );
+        assert_eq!(
+            registry.create_session(1, session(2), 3),
+            Err(ResultCode::RendezVous_SessionFull)
+        );
+    }
+
+    #[test]
+    fn joins_sessions_until_they_are_full() {
+        let registry = MatchmakeRegistry::new();
+        let gid = registry.create_session(1, session(3), 1).unwrap();
+
+        assert_eq!(registry.join_session(2, gid, None, 1), Ok(()));
+        assert_eq!(
+            registry.join_session(3, gid, None, 2),
+            Err(ResultCode::RendezVous_SessionFull)
+        );
+        assert_eq!(registry.join_session(3, gid, None, 1), Ok(()));
+        // Joining again doesn't take another seat
+        assert_eq!(registry.join_session(3, gid, None, 1), Ok(()));
+        assert_eq!(
+            registry.join_session(4, gid, None, 1),
+            Err(ResultCode::RendezVous_SessionFull)
+        );
+        assert_eq!(
+            registry.join_session(4, gid + 1, None, 1),
+            Err(ResultCode::RendezVous_SessionVoid)
+        );
+        assert_eq!(registry.get_participants(gid), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn rejects_joins_to_closed_sessions() {
+        let registry = MatchmakeRegistry::new();
+        let gid = registry.create_session(1, session(4), 1).unwrap();
+
+        assert_eq!(
+            registry.set_open_participation(2, gid, false),
+            Err(ResultCode::RendezVous_PermissionDenied)
+        );
+        registry.set_open_participation(1, gid, false).unwrap();
+        assert_eq!(
+            registry.join_session(2, gid, None, 1),
+            Err(ResultCode::RendezVous_SessionClosed)
+        );
+    }
+
+    #[test]
+    fn checks_user_passwords() {
+        let registry = MatchmakeRegistry::new();
+        let mut protected = session(4);
+        protected.user_password = NexString::from("1234".to_string());
+        protected.user_password_enabled = true;
+        let gid = registry.create_session(1, protected, 1).unwrap();
+
+        assert_eq!(
+            registry.join_session(2, gid, None, 1),
+            Err(ResultCode::RendezVous_InvalidPassword)
+        );
+        assert_eq!(
+            registry.join_session(2, gid, Some(""), 1),
+            Err(ResultCode::RendezVous_InvalidPassword)
+        );
+        assert_eq!(registry.join_session(2, gid, Some("1234"), 1), Ok(()));
+    }
+
+    #[test]
+    fn migrates_the_owner_and_host_when_they_leave() {
+        let registry = MatchmakeRegistry::new();
+        let gid = registry.create_session(1, session(4), 1).unwrap();
+        registry.join_session(2, gid, None, 1).unwrap();
+        registry.join_session(3, gid, None, 1).unwrap();
+
+        assert_eq!(registry.leave_session(1, gid), Ok(()));
+        assert_eq!(owner_and_host(&registry, gid), Some((2, 2)));
+        assert_eq!(
+            registry.with_session(gid, |session| session.participation_count),
+            Some(2)
+        );
+        assert_eq!(
+            registry.leave_session(1, gid),
+            Err(ResultCode::RendezVous_NotParticipatedGathering)
+        );
+
+        registry.leave_session(3, gid).unwrap();
+        assert_eq!(owner_and_host(&registry, gid), Some((2, 2)));
+
+        registry.leave_session(2, gid).unwrap();
+        assert_eq!(owner_and_host(&registry, gid), None);
+        assert!(registry.find_inconsistent_session_ids().is_empty());
+    }
+
+    #[test]
+    fn removes_disconnected_participants() {
+        let registry = MatchmakeRegistry::new();
+        let first = registry.create_session(1, session(4), 1).unwrap();
+        let second = registry.create_session(2, session(4), 1).unwrap();
+        registry.join_session(3, first, None, 1).unwrap();
+
+        assert_eq!(registry.leave_disconnected(|pid| pid == 3), vec![1, 2]);
+        assert_eq!(owner_and_host(&registry, first), Some((3, 3)));
+        assert_eq!(owner_and_host(&registry, second), None);
+    }
+
+    #[test]
+    fn browses_joinable_sessions_in_range() {
+        let registry = MatchmakeRegistry::new();
+        let full = registry.create_session(1, session(1), 1).unwrap();
+        let first = registry.create_session(2, session(4), 1).unwrap();
+        let second = registry.create_session(3, session(4), 1).unwrap();
+        let third = registry.create_session(4, session(4), 1).unwrap();
+        let criteria = || MatchmakeSessionSearchCriteria {
+            selection_method: MatchmakeSelectionMethod::NearestNeighbor.into(),
+            ..Default::default()
+        };
+
+        let all = ResultRange {
+            offset: 0,
+            length: 10,
+        };
+        assert_eq!(
+            registry.browse_session_ids(criteria(), &all),
+            Ok(vec![first, second, third])
+        );
+        assert!(!registry
+            .browse_session_ids(criteria(), &all)
+            .unwrap()
+            .contains(&full));
+
+        let page = ResultRange {
+            offset: 1,
+            length: 1,
+        };
+        assert_eq!(
+            registry.browse_session_ids(criteria(), &page),
+            Ok(vec![second])
+        );
+    }
+
+    #[test]
+    fn auto_matchmakes_into_open_sessions_or_creates_one() {
+        let registry = MatchmakeRegistry::new();
+        let mut protected = session(4);
+        protected.user_password_enabled = true;
+        let protected = registry.create_session(1, protected, 1).unwrap();
+        let open = registry.create_session(2, session(2), 1).unwrap();
+        let filters = [
+            MatchmakeSessionFilter::try_from(MatchmakeSessionSearchCriteria::default()).unwrap(),
+        ];
+
+        assert_eq!(
+            registry.auto_matchmake(3, &filters, session(2), 1),
+            Ok(open)
+        );
+        assert_eq!(registry.get_participants(open), Some(vec![2, 3]));
+
+        let created = registry.auto_matchmake(4, &filters, session(2), 1).unwrap();
+        assert_ne!(created, protected);
+        assert_ne!(created, open);
+        assert_eq!(owner_and_host(&registry, created), Some((4, 4)));
+    }
+
+    #[test]
+    fn skips_gids_in_use_after_wrapping() {
+        let registry = MatchmakeRegistry::new();
+        let first = registry.create_session(1, session(4), 1).unwrap();
+        registry.state.borrow_mut().last_gid = u32::MAX;
+
+        let second = registry.create_session(2, session(4), 1).unwrap();
+        assert_eq!(first, 1);
+        assert_eq!(second, 2);
+        assert_eq!(owner_and_host(&registry, first), Some((1, 1)));
+    }
+}
@@ -0,0 +1,330 @@
+use crate::matchmake_extension::{
+    generate_random_u64, MatchmakeSession, MatchmakeSessionSearchCriteria,
+};
+use nex_rs::nex_types::ResultCode;
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, TryFromPrimitive, IntoPrimitive)]
+#[repr(u32)]
+pub enum MatchmakeSelectionMethod {
+    #[default]
+    Random = 0x0,
+    NearestNeighbor = 0x1,
+    Broadening = 0x2,
+}
+
+/// A single textual search criterion.
+/// An empty string matches anything, "a" matches exactly a, and "a,b" matches a through b.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchCriterion {
+    Any,
+    Exact(u32),
+    Range(u32, u32),
+}
+
+impl SearchCriterion {
+    pub fn parse(criterion: &str) -> Result<Self, ResultCode> {
+        let criterion = criterion.trim();
+
+        if criterion.is_empty() {
+            return Ok(Self::Any);
+        }
+
+        let parse_value = |value: &str| {
+            value
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| ResultCode::Core_InvalidArgument)
+        };
+
+        match criterion.split_once(',') {
+            Some((min, max)) => {
+                let min = parse_value(min)?;
+                let max = parse_value(max)?;
+
+                if min > max {
+                    return Err(ResultCode::Core_InvalidArgument);
+                }
+
+                Ok(Self::Range(min, max))
+            }
+            None => Ok(Self::Exact(parse_value(criterion)?)),
+        }
+    }
+
+    pub fn matches(&self, value: u32) -> bool {
+        match *self {
+            Self::Any => true,
+            Self::Exact(expected) => value == expected,
+            Self::Range(min, max) => min <= value && value <= max,
+        }
+    }
+}
+
+/// A parsed [MatchmakeSessionSearchCriteria] that can be evaluated against sessions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchmakeSessionFilter {
+    pub attributes: Vec<SearchCriterion>,
+    pub game_mode: SearchCriterion,
+    pub min_participants: SearchCriterion,
+    pub max_participants: SearchCriterion,
+    pub matchmake_system_type: SearchCriterion,
+    pub vacant_only: bool,
+    pub vacant_participants: u32,
+    pub exclude_locked: bool,
+    pub exclude_non_host_pid: bool,
+    pub exclude_user_password_set: bool,
+    pub exclude_system_password_set: bool,
+    pub refer_gid: u32,
+    pub selection_method: MatchmakeSelectionMethod,
+}
+
+impl TryFrom<MatchmakeSessionSearchCriteria> for MatchmakeSessionFilter {
+    type Error = ResultCode;
+
+    fn try_from(criteria: MatchmakeSessionSearchCriteria) -> Result<Self, Self::Error> {
+        let parse = |criterion: String| SearchCriterion::parse(&criterion);
+
+        Ok(Self {
+            attributes: Vec::from(criteria.attributes)
+                .into_iter()
+                .map(|attribute| parse(attribute.into()))
+                .collect::<Result<_, _>>()?,
+            game_mode: parse(criteria.game_mode.into())?,
+            min_participants: parse(criteria.min_participants.into())?,
+            max_participants: parse(criteria.max_participants.into())?,
+            matchmake_system_type: parse(criteria.matchmake_system_type.into())?,
+            vacant_only: criteria.vacant_only,
+            vacant_participants: criteria.vacant_participants,
+            exclude_locked: criteria.exclude_locked,
+            exclude_non_host_pid: criteria.exclude_non_host_pid,
+            exclude_user_password_set: criteria.exclude_user_password_set,
+            exclude_system_password_set: criteria.exclude_system_password_set,
+            refer_gid: criteria.refer_gid,
+            // Newer titles send methods this filter doesn't know, which still expect a session
+            selection_method: MatchmakeSelectionMethod::try_from(criteria.selection_method)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl MatchmakeSessionFilter {
+    /// Checks everything except attributes, which broadening is allowed to relax.
+    fn is_candidate(&self, session: &MatchmakeSession) -> bool {
+        let gathering = &session.gathering;
+
+        if !self.game_mode.matches(session.game_mode)
+            || !self
+                .min_participants
+                .matches(gathering.min_participants.into())
+            || !self
+                .max_participants
+                .matches(gathering.max_participants.into())
+            || !self
+                .matchmake_system_type
+                .matches(session.matchmake_system_type)
+        {
+            return false;
+        }
+
+        if self.vacant_only {
+            let vacant_participants = self.vacant_participants.max(1);
+            let max_participants = u32::from(gathering.max_participants);
+
+            match session.participation_count.checked_add(vacant_participants) {
+                Some(participation_count) if participation_count <= max_participants => {}
+                _ => return false,
+            }
+        }
+
+        !((self.exclude_locked && !session.open_participation)
+            || (self.exclude_non_host_pid && gathering.host_pid == 0)
+            || (self.exclude_user_password_set && session.user_password_enabled)
+            || (self.exclude_system_password_set && session.system_password_enabled)
+            || (self.refer_gid != 0 && session.refer_gid != self.refer_gid))
+    }
+
+    fn count_attribute_mismatches(&self, session: &MatchmakeSession) -> usize {
+        let mut attributes = session.attributes.iter();
+
+        self.attributes
+            .iter()
+            .filter(|criterion| match attributes.next() {
+                Some(attribute) => !criterion.matches(*attribute),
+                None => **criterion != SearchCriterion::Any,
+            })
+            .count()
+    }
+
+    pub fn matches(&self, session: &MatchmakeSession) -> bool {
+        self.is_candidate(session) && self.count_attribute_mismatches(session) == 0
+    }
+
+    /// Filters sessions and orders them by the selection method, returning their gathering ids.
+    /// `progress_score` is the searcher's score used for nearest neighbor selection.
+    pub fn select<'a>(
+        &self,
+        sessions: impl IntoIterator<Item = &'a MatchmakeSession>,
+        progress_score: u8,
+    ) -> Vec<u32> {
+        let candidates = sessions
+            .into_iter()
+            .filter(|session| self.is_candidate(session))
+            .map(|session| (session, self.count_attribute_mismatches(session)));
+
+        let mut candidates: Vec<(&MatchmakeSession, usize)> = match self.selection_method {
+            MatchmakeSelectionMethod::Broadening => candidates.collect(),
+            _ => candidates
+                .filter(|(_, mismatches)| *mismatches == 0)
+                .collect(),
+        };
+
+        match self.selection_method {
+            MatchmakeSelectionMethod::Random => {
+                for i in (1..candidates.len()).rev() {
+                    let j = (generate_random_u64() % (i as u64 + 1)) as usize;
+                    candidates.swap(i, j);
+                }
+            }
+            MatchmakeSelectionMethod::NearestNeighbor => {
+                candidates
+                    .sort_by_key(|(session, _)| session.progress_score.abs_diff(progress_score));
+            }
+            MatchmakeSelectionMethod::Broadening => {
+                candidates.sort_by_key(|(_, mismatches)| *mismatches);
+            }
+        }
+
+        candidates
+            .into_iter()
+            .map(|(session, _)| session.gathering.id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nex_rs::nex_types::{NexList, NexString};
+
+    fn criteria(attributes: &[&str], selection_method: u32) -> MatchmakeSessionSearchCriteria {
+        let attributes: Vec<NexString> = attributes
+            .iter()
+            .map(|attribute| NexString::from(attribute.to_string()))
+            .collect();
+
+        MatchmakeSessionSearchCriteria {
+            attributes: NexList::from(attributes),
+            selection_method,
+            ..Default::default()
+        }
+    }
+
+    fn session(gid: u32, attributes: Vec<u32>, participation_count: u32) -> MatchmakeSession {
+        let mut session = MatchmakeSession {
+            attributes: NexList::from(attributes),
+            participation_count,
+            open_participation: true,
+            ..Default::default()
+        };
+        session.gathering.id = gid;
+        session.gathering.host_pid = 1;
+        session.gathering.max_participants = 4;
+        session
+    }
+
+    #[test]
+    fn parse_criterion() {
+        assert_eq!(SearchCriterion::parse(""), Ok(SearchCriterion::Any));
+        assert_eq!(SearchCriterion::parse(" 5 "), Ok(SearchCriterion::Exact(5)));
+        assert_eq!(
+            SearchCriterion::parse("2,8"),
+            Ok(SearchCriterion::Range(2, 8))
+        );
+        assert_eq!(
+            SearchCriterion::parse("8,2"),
+            Err(ResultCode::Core_InvalidArgument)
+        );
+        assert_eq!(
+            SearchCriterion::parse("a"),
+            Err(ResultCode::Core_InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn unknown_selection_method_falls_back_to_random() {
+        let filter = MatchmakeSessionFilter::try_from(criteria(&[], 0xFF)).unwrap();
+        assert_eq!(filter.selection_method, MatchmakeSelectionMethod::Random);
+    }
+
+    #[test]
+    fn matches_attributes() {
+        let filter = MatchmakeSessionFilter::try_from(criteria(&["1", "", "3,5"], 0)).unwrap();
+
+        assert!(filter.matches(&session(1, vec![1, 9, 4], 1)));
+        assert!(!filter.matches(&session(2, vec![2, 9, 4], 1)));
+        assert!(!filter.matches(&session(3, vec![1, 9, 6], 1)));
+    }
+
+    #[test]
+    fn vacant_only_rejects_full_sessions() {
+        let mut filter = MatchmakeSessionFilter::try_from(criteria(&[], 0)).unwrap();
+        filter.vacant_only = true;
+        filter.vacant_participants = 2;
+
+        assert!(filter.matches(&session(1, vec![], 2)));
+        assert!(!filter.matches(&session(2, vec![], 3)));
+    }
+
+    #[test]
+    fn vacant_only_rejects_overflowing_counts() {
+        let mut filter = MatchmakeSessionFilter::try_from(criteria(&[], 0)).unwrap();
+        filter.vacant_only = true;
+        filter.vacant_participants = u32::MAX;
+
+        assert!(!filter.matches(&session(1, vec![], 1)));
+    }
+
+    #[test]
+    fn excludes_locked_and_password_sessions() {
+        let mut filter = MatchmakeSessionFilter::try_from(criteria(&[], 0)).unwrap();
+        filter.exclude_locked = true;
+        filter.exclude_user_password_set = true;
+
+        let mut locked = session(1, vec![], 1);
+        locked.open_participation = false;
+        let mut password = session(2, vec![], 1);
+        password.user_password_enabled = true;
+
+        assert!(!filter.matches(&locked));
+        assert!(!filter.matches(&password));
+        assert!(filter.matches(&session(3, vec![], 1)));
+    }
+
+    #[test]
+    fn broadening_orders_by_mismatches() {
+        let filter = MatchmakeSessionFilter::try_from(criteria(&["1", "2"], 2)).unwrap();
+        let sessions = [
+            session(1, vec![0, 0], 1),
+            session(2, vec![1, 2], 1),
+            session(3, vec![1, 0], 1),
+        ];
+
+        assert_eq!(filter.select(sessions.iter(), 0), vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn nearest_neighbor_orders_by_progress_score() {
+        let filter = MatchmakeSessionFilter::try_from(criteria(&[], 1)).unwrap();
+        let mut sessions = [
+            session(1, vec![], 1),
+            session(2, vec![], 1),
+            session(3, vec![], 1),
+        ];
+        sessions[0].progress_score = 90;
+        sessions[1].progress_score = 40;
+        sessions[2].progress_score = 55;
+
+        assert_eq!(filter.select(sessions.iter(), 50), vec![3, 2, 1]);
+    }
+}
@@ -1,160 +1,691 @@
+use crate::request_trace::Redacted;
+use crate::structure::{
+    impl_endian_for_nex_structure, read_buffer, read_list, read_structure_content,
+    read_structure_list, write_field, write_structure_content, write_structure_list, Data,
+    NexStructure, NexVersion,
+};
 use nex_rs::nex_types::{DateTime, NexBuffer, NexList, NexMap, NexString, NexVariant};
-use no_std_io::{EndianRead, EndianWrite};
+use no_std_io::{EndianRead, EndianWrite, Error, StreamContainer, StreamReader};
+use std::fmt;
 
-#[derive(Default, EndianRead, EndianWrite)]
+#[derive(Debug, Default)]
 pub struct Gathering {
-    id: u32,
-    owner_pid: u32,
-    host_pid: u32,
-    min_participants: u16,
-    max_participants: u16,
-    participation_policy: u32,
-    policy_argument: u32,
-    flags: u32,
-    state: u32,
-    description: NexString,
-}
-
-#[derive(Default, EndianRead, EndianWrite)]
+    pub id: u32,
+    pub owner_pid: u32,
+    pub host_pid: u32,
+    pub min_participants: u16,
+    pub max_participants: u16,
+    pub participation_policy: u32,
+    pub policy_argument: u32,
+    pub flags: u32,
+    pub state: u32,
+    pub description: NexString,
+}
+
+impl NexStructure for Gathering {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        Data::read_structure(stream, nex_version)?;
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                id: stream.read_stream_le()?,
+                owner_pid: stream.read_stream_le()?,
+                host_pid: stream.read_stream_le()?,
+                min_participants: stream.read_stream_le()?,
+                max_participants: stream.read_stream_le()?,
+                participation_policy: stream.read_stream_le()?,
+                policy_argument: stream.read_stream_le()?,
+                flags: stream.read_stream_le()?,
+                state: stream.read_stream_le()?,
+                description: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.id)?;
+        write_field(&mut content, &self.owner_pid)?;
+        write_field(&mut content, &self.host_pid)?;
+        write_field(&mut content, &self.min_participants)?;
+        write_field(&mut content, &self.max_participants)?;
+        write_field(&mut content, &self.participation_policy)?;
+        write_field(&mut content, &self.policy_argument)?;
+        write_field(&mut content, &self.flags)?;
+        write_field(&mut content, &self.state)?;
+        write_field(&mut content, &self.description)?;
+
+        let mut data = Data {}.write_structure(nex_version)?;
+        data.extend(write_structure_content(
+            Self::STRUCTURE_VERSION,
+            nex_version,
+            content,
+        )?);
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct MatchmakeParam {
-    parameters: NexMap<NexString, NexVariant>,
+    pub parameters: NexMap<NexString, NexVariant>,
 }
 
-#[derive(Default, EndianRead, EndianWrite)]
+impl NexStructure for MatchmakeParam {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                parameters: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.parameters)?;
+        write_structure_content(Self::STRUCTURE_VERSION, nex_version, content)
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct MatchmakeSession {
-    gathering: Gathering,
-    game_mode: u32,
-    attributes: NexList<u32>,
-    open_participation: bool,
-    matchmake_system_type: u32,
-    application_buffer: NexBuffer,
-    participation_count: u32,
-    progress_score: u8,
-    session_key: NexBuffer,
-    option_0: u32,
-    matchmake_param: MatchmakeParam,
-    started_time: DateTime,
-    user_password: NexString,
-    refer_gid: u32,
-    user_password_enabled: bool,
-    system_password_enabled: bool,
-}
-
-#[derive(Default, EndianRead, EndianWrite)]
+    pub gathering: Gathering,
+    pub game_mode: u32,
+    pub attributes: NexList<u32>,
+    pub open_participation: bool,
+    pub matchmake_system_type: u32,
+    pub application_buffer: NexBuffer,
+    pub participation_count: u32,
+    /// NEX 3.4+
+    pub progress_score: u8,
+    /// NEX 3.0+
+    pub session_key: NexBuffer,
+    /// NEX 3.5+
+    pub option_0: u32,
+    /// NEX 3.6+
+    pub matchmake_param: MatchmakeParam,
+    /// NEX 3.6+
+    pub started_time: DateTime,
+    /// NEX 3.7+
+    pub user_password: NexString,
+    /// NEX 3.8+
+    pub refer_gid: u32,
+    /// NEX 3.8+
+    pub user_password_enabled: bool,
+    /// NEX 3.8+
+    pub system_password_enabled: bool,
+    /// NEX 4.0+
+    pub codeword: NexString,
+}
+
+impl NexStructure for MatchmakeSession {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        let gathering = Gathering::read_structure(stream, nex_version)?;
+        read_structure_content(stream, nex_version, |stream| {
+            let mut session = Self {
+                gathering,
+                game_mode: stream.read_stream_le()?,
+                attributes: read_list(stream)?,
+                open_participation: stream.read_stream_le()?,
+                matchmake_system_type: stream.read_stream_le()?,
+                application_buffer: read_buffer(stream)?,
+                participation_count: stream.read_stream_le()?,
+                ..Default::default()
+            };
+
+            if nex_version >= NexVersion::new(3, 4, 0) {
+                session.progress_score = stream.read_stream_le()?;
+            }
+
+            if nex_version >= NexVersion::new(3, 0, 0) {
+                session.session_key = read_buffer(stream)?;
+            }
+
+            if nex_version >= NexVersion::new(3, 5, 0) {
+                session.option_0 = stream.read_stream_le()?;
+            }
+
+            if nex_version >= NexVersion::new(3, 6, 0) {
+                session.matchmake_param = MatchmakeParam::read_structure(stream, nex_version)?;
+                session.started_time = stream.read_stream_le()?;
+            }
+
+            if nex_version >= NexVersion::new(3, 7, 0) {
+                session.user_password = stream.read_stream_le()?;
+            }
+
+            if nex_version >= NexVersion::new(3, 8, 0) {
+                session.refer_gid = stream.read_stream_le()?;
+                session.user_password_enabled = stream.read_stream_le()?;
+                session.system_password_enabled = stream.read_stream_le()?;
+            }
+
+            if nex_version >= NexVersion::new(4, 0, 0) {
+                session.codeword = stream.read_stream_le()?;
+            }
+
+            Ok(session)
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.game_mode)?;
+        write_field(&mut content, &self.attributes)?;
+        write_field(&mut content, &self.open_participation)?;
+        write_field(&mut content, &self.matchmake_system_type)?;
+        write_field(&mut content, &self.application_buffer)?;
+        write_field(&mut content, &self.participation_count)?;
+
+        if nex_version >= NexVersion::new(3, 4, 0) {
+            write_field(&mut content, &self.progress_score)?;
+        }
+
+        if nex_version >= NexVersion::new(3, 0, 0) {
+            write_field(&mut content, &self.session_key)?;
+        }
+
+        if nex_version >= NexVersion::new(3, 5, 0) {
+            write_field(&mut content, &self.option_0)?;
+        }
+
+        if nex_version >= NexVersion::new(3, 6, 0) {
+            content.extend(self.matchmake_param.write_structure(nex_version)?);
+            write_field(&mut content, &self.started_time)?;
+        }
+
+        if nex_version >= NexVersion::new(3, 7, 0) {
+            write_field(&mut content, &self.user_password)?;
+        }
+
+        if nex_version >= NexVersion::new(3, 8, 0) {
+            write_field(&mut content, &self.refer_gid)?;
+            write_field(&mut content, &self.user_password_enabled)?;
+            write_field(&mut content, &self.system_password_enabled)?;
+        }
+
+        if nex_version >= NexVersion::new(4, 0, 0) {
+            write_field(&mut content, &self.codeword)?;
+        }
+
+        let mut data = self.gathering.write_structure(nex_version)?;
+        data.extend(write_structure_content(
+            Self::STRUCTURE_VERSION,
+            nex_version,
+            content,
+        )?);
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct MatchmakeSessionSearchCriteria {
-    attributes: NexList<NexString>,
-    game_mode: NexString,
-    min_participants: NexString,
-    max_participants: NexString,
-    matchmake_system_type: NexString,
-    vacant_only: bool,
-    exclude_locked: bool,
-    exclude_non_host_pid: bool,
-    selection_method: u32,
-    vacant_participants: u32,
-    matchmake_param: MatchmakeParam,
-    exclude_user_password_set: bool,
-    exclude_system_password_set: bool,
-    refer_gid: u32,
-}
-
-#[derive(Default, EndianRead, EndianWrite)]
+    pub attributes: NexList<NexString>,
+    pub game_mode: NexString,
+    pub min_participants: NexString,
+    pub max_participants: NexString,
+    pub matchmake_system_type: NexString,
+    pub vacant_only: bool,
+    pub exclude_locked: bool,
+    pub exclude_non_host_pid: bool,
+    /// NEX 3.0+
+    pub selection_method: u32,
+    /// NEX 3.4+
+    pub vacant_participants: u32,
+    /// NEX 3.6+
+    pub matchmake_param: MatchmakeParam,
+    /// NEX 3.7+
+    pub exclude_user_password_set: bool,
+    /// NEX 3.7+
+    pub exclude_system_password_set: bool,
+    /// NEX 3.8+
+    pub refer_gid: u32,
+}
+
+impl NexStructure for MatchmakeSessionSearchCriteria {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        read_structure_content(stream, nex_version, |stream| {
+            let mut criteria = Self {
+                attributes: read_list(stream)?,
+                game_mode: stream.read_stream_le()?,
+                min_participants: stream.read_stream_le()?,
+                max_participants: stream.read_stream_le()?,
+                matchmake_system_type: stream.read_stream_le()?,
+                vacant_only: stream.read_stream_le()?,
+                exclude_locked: stream.read_stream_le()?,
+                exclude_non_host_pid: stream.read_stream_le()?,
+                ..Default::default()
+            };
+
+            if nex_version >= NexVersion::new(3, 0, 0) {
+                criteria.selection_method = stream.read_stream_le()?;
+            }
+
+            if nex_version >= NexVersion::new(3, 4, 0) {
+                criteria.vacant_participants = stream.read_stream_le()?;
+            }
+
+            if nex_version >= NexVersion::new(3, 6, 0) {
+                criteria.matchmake_param = MatchmakeParam::read_structure(stream, nex_version)?;
+            }
+
+            if nex_version >= NexVersion::new(3, 7, 0) {
+                criteria.exclude_user_password_set = stream.read_stream_le()?;
+                criteria.exclude_system_password_set = stream.read_stream_le()?;
+            }
+
+            if nex_version >= NexVersion::new(3, 8, 0) {
+                criteria.refer_gid = stream.read_stream_le()?;
+            }
+
+            Ok(criteria)
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.attributes)?;
+        write_field(&mut content, &self.game_mode)?;
+        write_field(&mut content, &self.min_participants)?;
+        write_field(&mut content, &self.max_participants)?;
+        write_field(&mut content, &self.matchmake_system_type)?;
+        write_field(&mut content, &self.vacant_only)?;
+        write_field(&mut content, &self.exclude_locked)?;
+        write_field(&mut content, &self.exclude_non_host_pid)?;
+
+        if nex_version >= NexVersion::new(3, 0, 0) {
+            write_field(&mut content, &self.selection_method)?;
+        }
+
+        if nex_version >= NexVersion::new(3, 4, 0) {
+            write_field(&mut content, &self.vacant_participants)?;
+        }
+
+        if nex_version >= NexVersion::new(3, 6, 0) {
+            content.extend(self.matchmake_param.write_structure(nex_version)?);
+        }
+
+        if nex_version >= NexVersion::new(3, 7, 0) {
+            write_field(&mut content, &self.exclude_user_password_set)?;
+            write_field(&mut content, &self.exclude_system_password_set)?;
+        }
+
+        if nex_version >= NexVersion::new(3, 8, 0) {
+            write_field(&mut content, &self.refer_gid)?;
+        }
+
+        write_structure_content(Self::STRUCTURE_VERSION, nex_version, content)
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct CreateMatchmakeSessionParam {
-    source_matchmake_session: MatchmakeSession,
-    additional_participants: NexList<u32>,
-    gid_for_participation_check: u32,
-    create_matchmake_session_option: u32,
-    join_message: NexString,
-    participation_count: u16,
+    pub source_matchmake_session: MatchmakeSession,
+    pub additional_participants: NexList<u32>,
+    pub gid_for_participation_check: u32,
+    pub create_matchmake_session_option: u32,
+    pub join_message: NexString,
+    pub participation_count: u16,
+}
+
+impl NexStructure for CreateMatchmakeSessionParam {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                source_matchmake_session: MatchmakeSession::read_structure(stream, nex_version)?,
+                additional_participants: read_list(stream)?,
+                gid_for_participation_check: stream.read_stream_le()?,
+                create_matchmake_session_option: stream.read_stream_le()?,
+                join_message: stream.read_stream_le()?,
+                participation_count: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        content.extend(self.source_matchmake_session.write_structure(nex_version)?);
+        write_field(&mut content, &self.additional_participants)?;
+        write_field(&mut content, &self.gid_for_participation_check)?;
+        write_field(&mut content, &self.create_matchmake_session_option)?;
+        write_field(&mut content, &self.join_message)?;
+        write_field(&mut content, &self.participation_count)?;
+        write_structure_content(Self::STRUCTURE_VERSION, nex_version, content)
+    }
 }
 
-#[derive(Default, EndianRead, EndianWrite)]
+#[derive(Default)]
 pub struct JoinMatchmakeSessionParam {
-    gid: u32,
-    additional_participants: NexList<u32>,
-    gid_for_participation_check: u32,
-    join_matchmake_session_option: u32,
-    join_matchmake_session_behaviour: u8,
-    user_password: NexString,
-    system_password: NexString,
-    join_message: NexString,
-    participation_count: u16,
-    extra_participants: u16,
-}
-
-#[derive(Default, EndianRead, EndianWrite)]
+    pub gid: u32,
+    pub additional_participants: NexList<u32>,
+    pub gid_for_participation_check: u32,
+    pub join_matchmake_session_option: u32,
+    pub join_matchmake_session_behaviour: u8,
+    pub user_password: NexString,
+    pub system_password: NexString,
+    pub join_message: NexString,
+    pub participation_count: u16,
+    pub extra_participants: u16,
+}
+
+impl fmt::Debug for JoinMatchmakeSessionParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinMatchmakeSessionParam")
+            .field("gid", &self.gid)
+            .field("additional_participants", &self.additional_participants)
+            .field(
+                "gid_for_participation_check",
+                &self.gid_for_participation_check,
+            )
+            .field(
+                "join_matchmake_session_option",
+                &self.join_matchmake_session_option,
+            )
+            .field(
+                "join_matchmake_session_behaviour",
+                &self.join_matchmake_session_behaviour,
+            )
+            .field("user_password", &Redacted)
+            .field("system_password", &Redacted)
+            .field("join_message", &self.join_message)
+            .field("participation_count", &self.participation_count)
+            .field("extra_participants", &self.extra_participants)
+            .finish()
+    }
+}
+
+impl NexStructure for JoinMatchmakeSessionParam {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                gid: stream.read_stream_le()?,
+                additional_participants: read_list(stream)?,
+                gid_for_participation_check: stream.read_stream_le()?,
+                join_matchmake_session_option: stream.read_stream_le()?,
+                join_matchmake_session_behaviour: stream.read_stream_le()?,
+                user_password: stream.read_stream_le()?,
+                system_password: stream.read_stream_le()?,
+                join_message: stream.read_stream_le()?,
+                participation_count: stream.read_stream_le()?,
+                extra_participants: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.gid)?;
+        write_field(&mut content, &self.additional_participants)?;
+        write_field(&mut content, &self.gid_for_participation_check)?;
+        write_field(&mut content, &self.join_matchmake_session_option)?;
+        write_field(&mut content, &self.join_matchmake_session_behaviour)?;
+        write_field(&mut content, &self.user_password)?;
+        write_field(&mut content, &self.system_password)?;
+        write_field(&mut content, &self.join_message)?;
+        write_field(&mut content, &self.participation_count)?;
+        write_field(&mut content, &self.extra_participants)?;
+        write_structure_content(Self::STRUCTURE_VERSION, nex_version, content)
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct AutoMatchmakeParam {
-    source_matchmake_session: MatchmakeSession,
-    additional_participants: NexList<u32>,
-    gid_for_participation_check: u32,
-    auto_matchmake_option: u32,
-    join_message: NexString,
-    participation_count: u16,
-    search_criteria: NexList<MatchmakeSessionSearchCriteria>,
-    target_gids: NexList<u32>,
+    pub source_matchmake_session: MatchmakeSession,
+    pub additional_participants: NexList<u32>,
+    pub gid_for_participation_check: u32,
+    pub auto_matchmake_option: u32,
+    pub join_message: NexString,
+    pub participation_count: u16,
+    pub search_criteria: NexList<MatchmakeSessionSearchCriteria>,
+    pub target_gids: NexList<u32>,
 }
 
-#[derive(Default, EndianRead, EndianWrite)]
+impl NexStructure for AutoMatchmakeParam {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                source_matchmake_session: MatchmakeSession::read_structure(stream, nex_version)?,
+                additional_participants: read_list(stream)?,
+                gid_for_participation_check: stream.read_stream_le()?,
+                auto_matchmake_option: stream.read_stream_le()?,
+                join_message: stream.read_stream_le()?,
+                participation_count: stream.read_stream_le()?,
+                search_criteria: read_structure_list(stream, nex_version)?,
+                target_gids: read_list(stream)?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        content.extend(self.source_matchmake_session.write_structure(nex_version)?);
+        write_field(&mut content, &self.additional_participants)?;
+        write_field(&mut content, &self.gid_for_participation_check)?;
+        write_field(&mut content, &self.auto_matchmake_option)?;
+        write_field(&mut content, &self.join_message)?;
+        write_field(&mut content, &self.participation_count)?;
+        content.extend(write_structure_list(
+            self.search_criteria.iter(),
+            nex_version,
+        )?);
+        write_field(&mut content, &self.target_gids)?;
+        write_structure_content(Self::STRUCTURE_VERSION, nex_version, content)
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct UpdateMatchmakeSessionParam {
-    gid: u32,
-    modification_flag: u32,
-    attributes: NexList<u32>,
-    open_participation: bool,
-    application_buffer: NexBuffer,
-    progress_score: u8,
-    matchmake_param: MatchmakeParam,
-    started_time: DateTime,
-    user_password: NexString,
-    game_mode: u32,
-    description: NexString,
-    min_participants: u16,
-    max_participants: u16,
-    matchmake_system_type: u32,
-    participation_policy: u32,
-    policy_argument: u32,
-}
-
-#[derive(Default, EndianRead, EndianWrite)]
+    pub gid: u32,
+    pub modification_flag: u32,
+    pub attributes: NexList<u32>,
+    pub open_participation: bool,
+    pub application_buffer: NexBuffer,
+    pub progress_score: u8,
+    pub matchmake_param: MatchmakeParam,
+    pub started_time: DateTime,
+    pub user_password: NexString,
+    pub game_mode: u32,
+    pub description: NexString,
+    pub min_participants: u16,
+    pub max_participants: u16,
+    pub matchmake_system_type: u32,
+    pub participation_policy: u32,
+    pub policy_argument: u32,
+}
+
+impl NexStructure for UpdateMatchmakeSessionParam {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                gid: stream.read_stream_le()?,
+                modification_flag: stream.read_stream_le()?,
+                attributes: read_list(stream)?,
+                open_participation: stream.read_stream_le()?,
+                application_buffer: read_buffer(stream)?,
+                progress_score: stream.read_stream_le()?,
+                matchmake_param: MatchmakeParam::read_structure(stream, nex_version)?,
+                started_time: stream.read_stream_le()?,
+                user_password: stream.read_stream_le()?,
+                game_mode: stream.read_stream_le()?,
+                description: stream.read_stream_le()?,
+                min_participants: stream.read_stream_le()?,
+                max_participants: stream.read_stream_le()?,
+                matchmake_system_type: stream.read_stream_le()?,
+                participation_policy: stream.read_stream_le()?,
+                policy_argument: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.gid)?;
+        write_field(&mut content, &self.modification_flag)?;
+        write_field(&mut content, &self.attributes)?;
+        write_field(&mut content, &self.open_participation)?;
+        write_field(&mut content, &self.application_buffer)?;
+        write_field(&mut content, &self.progress_score)?;
+        content.extend(self.matchmake_param.write_structure(nex_version)?);
+        write_field(&mut content, &self.started_time)?;
+        write_field(&mut content, &self.user_password)?;
+        write_field(&mut content, &self.game_mode)?;
+        write_field(&mut content, &self.description)?;
+        write_field(&mut content, &self.min_participants)?;
+        write_field(&mut content, &self.max_participants)?;
+        write_field(&mut content, &self.matchmake_system_type)?;
+        write_field(&mut content, &self.participation_policy)?;
+        write_field(&mut content, &self.policy_argument)?;
+        write_structure_content(Self::STRUCTURE_VERSION, nex_version, content)
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct Community {
-    gathering: Gathering,
-    community_type: u32,
-    password: NexString,
-    attributes: NexList<u32>,
-    application_buffer: NexBuffer,
-    participation_start_date: DateTime,
-    participation_end_date: DateTime,
-    matchmake_session_count: u32,
-    participation_count: u32,
-}
-
-#[derive(Default, EndianRead, EndianWrite)]
+    pub gathering: Gathering,
+    pub community_type: u32,
+    pub password: NexString,
+    pub attributes: NexList<u32>,
+    pub application_buffer: NexBuffer,
+    pub participation_start_date: DateTime,
+    pub participation_end_date: DateTime,
+    pub matchmake_session_count: u32,
+    pub participation_count: u32,
+}
+
+impl NexStructure for Community {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        let gathering = Gathering::read_structure(stream, nex_version)?;
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                gathering,
+                community_type: stream.read_stream_le()?,
+                password: stream.read_stream_le()?,
+                attributes: read_list(stream)?,
+                application_buffer: read_buffer(stream)?,
+                participation_start_date: stream.read_stream_le()?,
+                participation_end_date: stream.read_stream_le()?,
+                matchmake_session_count: stream.read_stream_le()?,
+                participation_count: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.community_type)?;
+        write_field(&mut content, &self.password)?;
+        write_field(&mut content, &self.attributes)?;
+        write_field(&mut content, &self.application_buffer)?;
+        write_field(&mut content, &self.participation_start_date)?;
+        write_field(&mut content, &self.participation_end_date)?;
+        write_field(&mut content, &self.matchmake_session_count)?;
+        write_field(&mut content, &self.participation_count)?;
+
+        let mut data = self.gathering.write_structure(nex_version)?;
+        data.extend(write_structure_content(
+            Self::STRUCTURE_VERSION,
+            nex_version,
+            content,
+        )?);
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct PersistentGathering {
-    gathering: Gathering,
-    community_type: u32,
-    password: NexString,
-    attributes: NexList<u32>,
-    application_buffer: NexBuffer,
-    participation_start_date: DateTime,
-    participation_end_date: DateTime,
-    matchmake_session_count: u32,
-    participation_count: u32,
-}
-
-#[derive(Default, EndianRead, EndianWrite)]
+    pub gathering: Gathering,
+    pub community_type: u32,
+    pub password: NexString,
+    pub attributes: NexList<u32>,
+    pub application_buffer: NexBuffer,
+    pub participation_start_date: DateTime,
+    pub participation_end_date: DateTime,
+    pub matchmake_session_count: u32,
+    pub participation_count: u32,
+}
+
+impl NexStructure for PersistentGathering {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        let gathering = Gathering::read_structure(stream, nex_version)?;
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                gathering,
+                community_type: stream.read_stream_le()?,
+                password: stream.read_stream_le()?,
+                attributes: read_list(stream)?,
+                application_buffer: read_buffer(stream)?,
+                participation_start_date: stream.read_stream_le()?,
+                participation_end_date: stream.read_stream_le()?,
+                matchmake_session_count: stream.read_stream_le()?,
+                participation_count: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.community_type)?;
+        write_field(&mut content, &self.password)?;
+        write_field(&mut content, &self.attributes)?;
+        write_field(&mut content, &self.application_buffer)?;
+        write_field(&mut content, &self.participation_start_date)?;
+        write_field(&mut content, &self.participation_end_date)?;
+        write_field(&mut content, &self.matchmake_session_count)?;
+        write_field(&mut content, &self.participation_count)?;
+
+        let mut data = self.gathering.write_structure(nex_version)?;
+        data.extend(write_structure_content(
+            Self::STRUCTURE_VERSION,
+            nex_version,
+            content,
+        )?);
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Default, EndianRead, EndianWrite)]
 pub struct SimplePlayingSession {
-    principal_id: u32,
-    gathering_id: u32,
-    game_mode: u32,
-    attribute_0: u32,
+    pub principal_id: u32,
+    pub gathering_id: u32,
+    pub game_mode: u32,
+    pub attribute_0: u32,
 }
 
-#[derive(Default, EndianRead, EndianWrite)]
+#[derive(Debug, Default, EndianRead, EndianWrite)]
 pub struct SimpleCommunity {
-    gathering_id: u32,
-    matchmake_session_count: u32,
+    pub gathering_id: u32,
+    pub matchmake_session_count: u32,
 }
 
-#[derive(Default, EndianRead, EndianWrite)]
+#[derive(Debug, Default, EndianRead, EndianWrite)]
 pub struct AttractionStatus {
     pub message_interval: u16,
     pub operation_flag: u8,
@@ -163,9 +694,229 @@ pub struct AttractionStatus {
     pub extra_params: NexList<u32>,
 }
 
-#[derive(Default, EndianRead, EndianWrite)]
+#[derive(Debug, Default, EndianRead, EndianWrite)]
 pub struct SimpleMatchmakeHostInfo {
-    pid: u32,
-    session_key: NexBuffer,
-    station_urls: NexList<NexString>,
+    pub pid: u32,
+    pub session_key: NexBuffer,
+    pub station_urls: NexList<NexString>,
+}
+
+#[derive(Debug, Default, EndianRead, EndianWrite)]
+pub struct GatheringURLs {
+    pub gid: u32,
+    pub station_urls: NexList<NexString>,
+}
+
+impl_endian_for_nex_structure!(
+    Gathering,
+    MatchmakeParam,
+    MatchmakeSession,
+    MatchmakeSessionSearchCriteria,
+    CreateMatchmakeSessionParam,
+    JoinMatchmakeSessionParam,
+    AutoMatchmakeParam,
+    UpdateMatchmakeSessionParam,
+    Community,
+    PersistentGathering,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::DEFAULT_NEX_VERSION;
+
+    const VERSIONS: [NexVersion; 8] = [
+        NexVersion::new(2, 0, 0),
+        NexVersion::new(3, 0, 0),
+        NexVersion::new(3, 4, 0),
+        NexVersion::new(3, 5, 0),
+        NexVersion::new(3, 6, 0),
+        NexVersion::new(3, 7, 0),
+        NexVersion::new(3, 8, 0),
+        NexVersion::new(4, 0, 0),
+    ];
+
+    /// Writes and reads the value back, checking every byte is read and rewritten the same way.
+    fn round_trip<T: NexStructure>(value: &T, nex_version: NexVersion) -> (T, Vec<u8>) {
+        let bytes = value.write_structure(nex_version).unwrap();
+        let mut stream = StreamContainer::new(bytes.as_slice());
+        let read = T::read_structure(&mut stream, nex_version).unwrap();
+
+        assert_eq!(stream.get_index(), bytes.len(), "{:?}", nex_version);
+        assert_eq!(
+            read.write_structure(nex_version).unwrap(),
+            bytes,
+            "{:?}",
+            nex_version
+        );
+        (read, bytes)
+    }
+
+    fn session() -> MatchmakeSession {
+        let mut session = MatchmakeSession {
+            game_mode: 1,
+            attributes: NexList::from(vec![2, 3]),
+            open_participation: true,
+            participation_count: 4,
+            progress_score: 5,
+            session_key: vec![6; 4].into(),
+            option_0: 7,
+            user_password: NexString::from("password".to_string()),
+            refer_gid: 8,
+            user_password_enabled: true,
+            system_password_enabled: true,
+            codeword: NexString::from("codeword".to_string()),
+            ..Default::default()
+        };
+        session.gathering.id = 9;
+        session.gathering.max_participants = 10;
+        session
+    }
+
+    #[test]
+    fn round_trips_matchmake_sessions_at_each_version() {
+        let mut previous_length = 0;
+
+        for nex_version in VERSIONS {
+            let (read, bytes) = round_trip(&session(), nex_version);
+            let user_password: String = read.user_password.into();
+            let codeword: String = read.codeword.into();
+
+            assert_eq!(read.gathering.id, 9);
+            assert_eq!(Vec::from(read.attributes), vec![2, 3]);
+            assert_eq!(read.participation_count, 4);
+            assert_eq!(
+                read.progress_score,
+                if nex_version >= NexVersion::new(3, 4, 0) {
+                    5
+                } else {
+                    0
+                }
+            );
+            assert_eq!(
+                read.option_0,
+                if nex_version >= NexVersion::new(3, 5, 0) {
+                    7
+                } else {
+                    0
+                }
+            );
+            assert_eq!(
+                user_password,
+                if nex_version >= NexVersion::new(3, 7, 0) {
+                    "password"
+                } else {
+                    ""
+                }
+            );
+            assert_eq!(
+                (read.refer_gid, read.user_password_enabled),
+                if nex_version >= NexVersion::new(3, 8, 0) {
+                    (8, true)
+                } else {
+                    (0, false)
+                }
+            );
+            assert_eq!(
+                codeword,
+                if nex_version >= NexVersion::new(4, 0, 0) {
+                    "codeword"
+                } else {
+                    ""
+                }
+            );
+
+            // Each version gate adds fields, so no version is shorter than the one before
+            assert!(bytes.len() > previous_length, "{:?}", nex_version);
+            previous_length = bytes.len();
+        }
+    }
+
+    #[test]
+    fn writes_session_keys_from_nex_3_0() {
+        let (_, without_key) = round_trip(&session(), NexVersion::new(2, 0, 0));
+        let (_, with_key) = round_trip(&session(), NexVersion::new(3, 0, 0));
+
+        // A u32 length prefix and the 4 key bytes
+        assert_eq!(with_key.len(), without_key.len() + 8);
+    }
+
+    #[test]
+    fn writes_structure_headers_from_nex_3_5() {
+        let (_, headered) = round_trip(&session(), NexVersion::new(3, 5, 0));
+        let (_, headerless) = round_trip(
+            &session(),
+            NexVersion::new(3, 5, 0).without_structure_headers(),
+        );
+
+        // Data has no content, so its header is version 0 with length 0
+        assert_eq!(headered[..5], [0, 0, 0, 0, 0]);
+        // Gathering is next, with its own header before the gathering id
+        assert_eq!(headered[10..14], 9u32.to_le_bytes());
+        assert_eq!(headerless[..4], 9u32.to_le_bytes());
+        // Headers for Data, Gathering and MatchmakeSession
+        assert_eq!(headered.len(), headerless.len() + 15);
+    }
+
+    #[test]
+    fn redacts_join_passwords() {
+        let param = JoinMatchmakeSessionParam {
+            gid: 1,
+            user_password: NexString::from("user secret".to_string()),
+            system_password: NexString::from("system secret".to_string()),
+            ..Default::default()
+        };
+        let debug = format!("{:?}", param);
+
+        assert!(debug.contains("gid: 1"), "{}", debug);
+        assert!(!debug.contains("secret"), "{}", debug);
+        assert!(debug.contains("user_password: <redacted>"), "{}", debug);
+    }
+
+    #[test]
+    fn round_trips_search_criteria_at_each_version() {
+        let criteria = MatchmakeSessionSearchCriteria {
+            attributes: NexList::from(vec![NexString::from("1".to_string())]),
+            selection_method: 1,
+            vacant_participants: 2,
+            exclude_user_password_set: true,
+            exclude_system_password_set: true,
+            refer_gid: 3,
+            ..Default::default()
+        };
+
+        for nex_version in VERSIONS.into_iter().chain([DEFAULT_NEX_VERSION]) {
+            let (read, _) = round_trip(&criteria, nex_version);
+            let expect = |version: NexVersion| nex_version >= version;
+
+            assert_eq!(
+                read.selection_method,
+                if expect(NexVersion::new(3, 0, 0)) {
+                    1
+                } else {
+                    0
+                }
+            );
+            assert_eq!(
+                read.vacant_participants,
+                if expect(NexVersion::new(3, 4, 0)) {
+                    2
+                } else {
+                    0
+                }
+            );
+            assert_eq!(
+                read.exclude_user_password_set && read.exclude_system_password_set,
+                expect(NexVersion::new(3, 7, 0))
+            );
+            assert_eq!(
+                read.refer_gid,
+                if expect(NexVersion::new(3, 8, 0)) {
+                    3
+                } else {
+                    0
+                }
+            );
+        }
+    }
 }
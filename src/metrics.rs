@@ -0,0 +1,398 @@
+use crate::middleware::{Middleware, RmcCall, RmcResponse};
+use nex_rs::client::ClientConnection;
+use nex_rs::nex_types::ResultCode;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// Upper bounds in seconds of the handler latency histogram buckets.
+pub const LATENCY_BUCKETS: [f64; 9] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0, 5.0];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MethodMetrics {
+    pub calls: u64,
+    pub errors: BTreeMap<String, u64>,
+    /// Cumulative counts for each of [LATENCY_BUCKETS].
+    pub latency_buckets: [u64; LATENCY_BUCKETS.len()],
+    pub latency_sum: f64,
+    pub latency_count: u64,
+}
+
+impl MethodMetrics {
+    fn observe_latency(&mut self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+
+        for (bucket, upper_bound) in self.latency_buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= upper_bound {
+                *bucket += 1;
+            }
+        }
+
+        self.latency_sum += seconds;
+        self.latency_count += 1;
+    }
+}
+
+/// Call, error and latency metrics per (protocol id, method id).
+/// Every dispatched request is recorded when returned from
+/// [ClientContextProvider::get_metrics](crate::client_context::ClientContextProvider::get_metrics).
+#[derive(Debug, Default)]
+pub struct RmcMetrics {
+    methods: RefCell<BTreeMap<(u8, u32), MethodMetrics>>,
+}
+
+impl RmcMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_call(&self, protocol_id: u8, method_id: u32) {
+        self.methods
+            .borrow_mut()
+            .entry((protocol_id, method_id))
+            .or_default()
+            .calls += 1;
+    }
+
+    pub fn record_error(&self, protocol_id: u8, method_id: u32, error_code: ResultCode) {
+        self.record_error_label(protocol_id, method_id, &format!("{:?}", error_code));
+    }
+
+    /// Records a call that failed without a result code, e.g. because its parameters couldn't be decoded.
+    pub fn record_error_label(&self, protocol_id: u8, method_id: u32, label: &str) {
+        *self
+            .methods
+            .borrow_mut()
+            .entry((protocol_id, method_id))
+            .or_default()
+            .errors
+            .entry(label.to_string())
+            .or_insert(0) += 1;
+    }
+
+    pub fn record_latency(&self, protocol_id: u8, method_id: u32, latency: Duration) {
+        self.methods
+            .borrow_mut()
+            .entry((protocol_id, method_id))
+            .or_default()
+            .observe_latency(latency);
+    }
+
+    pub fn get_method_metrics(&self, protocol_id: u8, method_id: u32) -> Option<MethodMetrics> {
+        self.methods
+            .borrow()
+            .get(&(protocol_id, method_id))
+            .cloned()
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let methods = self.methods.borrow();
+        let mut output = String::new();
+
+        let _ = writeln!(output, "# HELP nex_rmc_calls_total RMC calls received.");
+        let _ = writeln!(output, "# TYPE nex_rmc_calls_total counter");
+        for ((protocol_id, method_id), metrics) in methods.iter() {
+            let _ = writeln!(
+                output,
+                "nex_rmc_calls_total{{protocol_id=\"{}\",method_id=\"{}\"}} {}",
+                protocol_id, method_id, metrics.calls
+            );
+        }
+
+        let _ = writeln!(
+            output,
+            "# HELP nex_rmc_errors_total RMC calls answered with an error."
+        );
+        let _ = writeln!(output, "# TYPE nex_rmc_errors_total counter");
+        for ((protocol_id, method_id), metrics) in methods.iter() {
+            for (result_code, count) in metrics.errors.iter() {
+                let _ = writeln!(
+                    output,
+                    "nex_rmc_errors_total{{protocol_id=\"{}\",method_id=\"{}\",result_code=\"{}\"}} {}",
+                    protocol_id, method_id, result_code, count
+                );
+            }
+        }
+
+        let _ = writeln!(
+            output,
+            "# HELP nex_rmc_handler_duration_seconds Time from dispatch to response."
+        );
+        let _ = writeln!(output, "# TYPE nex_rmc_handler_duration_seconds histogram");
+        for ((protocol_id, method_id), metrics) in methods.iter() {
+            let labels = format!(
+                "protocol_id=\"{}\",method_id=\"{}\"",
+                protocol_id, method_id
+            );
+
+            for (count, upper_bound) in metrics.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(
+                    output,
+                    "nex_rmc_handler_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, upper_bound, count
+                );
+            }
+
+            let _ = writeln!(
+                output,
+                "nex_rmc_handler_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, metrics.latency_count
+            );
+            let _ = writeln!(
+                output,
+                "nex_rmc_handler_duration_seconds_sum{{{}}} {}",
+                labels, metrics.latency_sum
+            );
+            let _ = writeln!(
+                output,
+                "nex_rmc_handler_duration_seconds_count{{{}}} {}",
+                labels, metrics.latency_count
+            );
+        }
+
+        output
+    }
+}
+
+/// The error label of calls whose handler failed without sending a response.
+pub const HANDLER_FAILURE_LABEL: &str = "HandlerFailure";
+
+/// Records every call, its errors and its latency from receiving it to responding.
+/// Run first by the protocol dispatchers, so calls rejected by other middleware are recorded too.
+impl Middleware for RmcMetrics {
+    fn before(&self, _client: &ClientConnection, call: &RmcCall) -> Result<(), ResultCode> {
+        self.record_call(call.protocol_id, call.method_id);
+        Ok(())
+    }
+
+    fn after(&self, _client: &ClientConnection, call: &RmcCall, response: &RmcResponse) {
+        match response {
+            RmcResponse::Success(_) => {}
+            RmcResponse::Error(error_code) => {
+                self.record_error(call.protocol_id, call.method_id, *error_code)
+            }
+            RmcResponse::Failure(_) => {
+                self.record_error_label(call.protocol_id, call.method_id, HANDLER_FAILURE_LABEL)
+            }
+        }
+
+        self.record_latency(call.protocol_id, call.method_id, call.received_at.elapsed());
+    }
+}
+
+/// How long a scrape may take before its connection is dropped.
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_SCRAPE_REQUEST_SIZE: usize = 8192;
+
+#[derive(Debug)]
+struct Scrape {
+    stream: TcpStream,
+    request: Vec<u8>,
+    response: Option<Vec<u8>>,
+    written: usize,
+    accepted_at: Instant,
+}
+
+impl Scrape {
+    fn has_request(&self) -> bool {
+        self.request.len() >= MAX_SCRAPE_REQUEST_SIZE
+            || self.request.windows(4).any(|window| window == b"\r\n\r\n")
+    }
+
+    /// Advances the scrape without blocking, returning whether it's finished.
+    fn advance(&mut self, metrics: &RmcMetrics) -> io::Result<bool> {
+        while self.response.is_none() {
+            let mut buffer = [0; 1024];
+
+            match self.stream.read(&mut buffer) {
+                // The request itself doesn't matter, every path returns the metrics.
+                Ok(0) => self.response = Some(render_response(metrics)),
+                Ok(read) => {
+                    self.request.extend_from_slice(&buffer[..read]);
+
+                    if self.has_request() {
+                        self.response = Some(render_response(metrics));
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        let response = self.response.as_deref().unwrap_or_default();
+
+        while self.written < response.len() {
+            match self.stream.write(&response[self.written..]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => self.written += written,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+fn render_response(metrics: &RmcMetrics) -> Vec<u8> {
+    let body = metrics.render();
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+    .into_bytes()
+}
+
+/// Serves [RmcMetrics::render] over HTTP without blocking,
+/// so it can be polled from any event loop.
+#[derive(Debug)]
+pub struct MetricsExporter {
+    listener: TcpListener,
+    scrapes: RefCell<Vec<Scrape>>,
+}
+
+impl MetricsExporter {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            scrapes: RefCell::new(vec![]),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts new scrapes and advances pending ones, returning how many were answered.
+    /// Scrapes that fail or take longer than a second are dropped without affecting the others.
+    pub fn poll(&self, metrics: &RmcMetrics) -> usize {
+        let mut scrapes = self.scrapes.borrow_mut();
+
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(true).is_ok() {
+                        scrapes.push(Scrape {
+                            stream,
+                            request: vec![],
+                            response: None,
+                            written: 0,
+                            accepted_at: Instant::now(),
+                        });
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                // WouldBlock means every pending connection was accepted,
+                // other errors belong to a connection that's already gone.
+                Err(_) => break,
+            }
+        }
+
+        let mut answered = 0;
+
+        scrapes.retain_mut(|scrape| match scrape.advance(metrics) {
+            Ok(true) => {
+                answered += 1;
+                false
+            }
+            Ok(false) => scrape.accepted_at.elapsed() < SCRAPE_TIMEOUT,
+            Err(_) => false,
+        });
+
+        answered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+
+    #[test]
+    fn renders_calls_errors_and_latency() {
+        let metrics = RmcMetrics::new();
+        metrics.record_call(115, 8);
+        metrics.record_error(115, 8, ResultCode::Core_InvalidArgument);
+        metrics.record_latency(115, 8, Duration::from_millis(20));
+
+        let output = metrics.render();
+        assert!(output.contains("nex_rmc_calls_total{protocol_id=\"115\",method_id=\"8\"} 1"));
+        assert!(output.contains("result_code=\"Core_InvalidArgument\"} 1"));
+        assert!(output.contains(
+            "nex_rmc_handler_duration_seconds_bucket{protocol_id=\"115\",method_id=\"8\",le=\"0.025\"} 1"
+        ));
+    }
+
+    #[test]
+    fn records_rejected_and_failed_calls_as_middleware() {
+        let metrics = RmcMetrics::new();
+        let client =
+            ClientConnection::new(SocketAddr::from(([127, 0, 0, 1], 1)), Default::default());
+        let call = RmcCall {
+            protocol_id: 115,
+            method_id: 8,
+            call_id: 1,
+            parameters: &[],
+            session: None,
+            requires_session: true,
+            received_at: Instant::now(),
+        };
+
+        for response in [
+            RmcResponse::Success(&[]),
+            RmcResponse::Error(ResultCode::RendezVous_NotAuthenticated),
+            RmcResponse::Failure("Can not read data ids list"),
+        ] {
+            metrics.before(&client, &call).unwrap();
+            metrics.after(&client, &call, &response);
+        }
+
+        let method_metrics = metrics.get_method_metrics(115, 8).unwrap();
+        assert_eq!(method_metrics.calls, 3);
+        assert_eq!(method_metrics.latency_count, 3);
+        assert_eq!(
+            method_metrics.errors,
+            BTreeMap::from([
+                (HANDLER_FAILURE_LABEL.to_string(), 1),
+                ("RendezVous_NotAuthenticated".to_string(), 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn silent_scrapes_do_not_block_others() {
+        let metrics = RmcMetrics::new();
+        let exporter = MetricsExporter::bind("127.0.0.1:0").unwrap();
+        let address = exporter.local_addr().unwrap();
+
+        let _silent = TcpStream::connect(address).unwrap();
+        let mut scrape = TcpStream::connect(address).unwrap();
+        scrape.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+
+        let mut answered = 0;
+        for _ in 0..100 {
+            answered += exporter.poll(&metrics);
+
+            if answered > 0 {
+                break;
+            }
+
+            sleep(Duration::from_millis(10));
+        }
+        assert_eq!(answered, 1);
+
+        let mut response = String::new();
+        scrape.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert_eq!(exporter.scrapes.borrow().len(), 1);
+    }
+}
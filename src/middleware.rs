@@ -0,0 +1,436 @@
+use crate::authorization::SessionAuthorization;
+use crate::client_context::{ClientContextProvider, SessionContext};
+use async_trait::async_trait;
+use nex_rs::client::ClientConnection;
+use nex_rs::nex_types::ResultCode;
+use nex_rs::packet::{Packet, PacketV1};
+use nex_rs::server::Server;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// What a handler produced for a call: the response to send, or an error for calls that
+/// couldn't be handled at all, e.g. because their parameters couldn't be decoded.
+/// Those get no response.
+pub type HandlerResult = Result<Result<Vec<u8>, ResultCode>, &'static str>;
+
+/// A decoded RMC request as seen by middleware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RmcCall<'a> {
+    pub protocol_id: u8,
+    pub method_id: u32,
+    pub call_id: u32,
+    pub parameters: &'a [u8],
+    pub session: Option<SessionContext>,
+    /// Whether the method can only be called with a session, i.e. after SecureConnection
+    /// Register or RegisterEx.
+    pub requires_session: bool,
+    pub received_at: Instant,
+}
+
+impl<'a> RmcCall<'a> {
+    pub fn new<T: ClientContextProvider + ?Sized>(
+        provider: &T,
+        client: &ClientConnection,
+        packet: &'a PacketV1,
+        requires_session: bool,
+    ) -> Self {
+        let rmc_request = packet.get_rmc_request();
+
+        Self {
+            protocol_id: rmc_request.protocol_id,
+            method_id: rmc_request.method_id,
+            call_id: rmc_request.call_id,
+            parameters: rmc_request.parameters.as_slice(),
+            session: provider.get_session(client),
+            requires_session,
+            received_at: Instant::now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RmcResponse<'a> {
+    Success(&'a [u8]),
+    Error(ResultCode),
+    /// The handler failed without a response, see [HandlerResult].
+    Failure(&'static str),
+}
+
+/// Runs around every request dispatched by a protocol's `handle_request`.
+pub trait Middleware {
+    /// Runs before the handler. Returning an error skips the handler and sends the error instead.
+    fn before(&self, _client: &ClientConnection, _call: &RmcCall) -> Result<(), ResultCode> {
+        Ok(())
+    }
+
+    /// Runs with the call's response, just before it is sent. Only runs if this
+    /// middleware's `before` accepted the call, but also runs when a later middleware
+    /// rejected it or the handler failed.
+    fn after(&self, _client: &ClientConnection, _call: &RmcCall, _response: &RmcResponse) {}
+}
+
+/// Runs `before` of each middleware in order. If one rejects the call, `after` runs with the
+/// rejection for the middleware that already accepted it, in reverse order.
+fn run_before(
+    middlewares: &[&dyn Middleware],
+    client: &ClientConnection,
+    call: &RmcCall,
+) -> Result<(), ResultCode> {
+    for (index, middleware) in middlewares.iter().enumerate() {
+        if let Err(error_code) = middleware.before(client, call) {
+            run_after(
+                &middlewares[..index],
+                client,
+                call,
+                &RmcResponse::Error(error_code),
+            );
+            return Err(error_code);
+        }
+    }
+
+    Ok(())
+}
+
+fn run_after(
+    middlewares: &[&dyn Middleware],
+    client: &ClientConnection,
+    call: &RmcCall,
+    response: &RmcResponse,
+) {
+    for middleware in middlewares.iter().rev() {
+        middleware.after(client, call, response);
+    }
+}
+
+/// Middleware run in insertion order before handlers and in reverse order after them.
+#[derive(Default)]
+pub struct MiddlewareChain {
+    middlewares: Vec<Box<dyn Middleware>>,
+}
+
+impl MiddlewareChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.push(middleware);
+        self
+    }
+
+    pub fn push(&mut self, middleware: impl Middleware + 'static) {
+        self.middlewares.push(Box::new(middleware));
+    }
+
+    pub fn len(&self) -> usize {
+        self.middlewares.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.middlewares.is_empty()
+    }
+
+    fn as_refs(&self) -> Vec<&dyn Middleware> {
+        self.middlewares
+            .iter()
+            .map(|middleware| middleware.as_ref())
+            .collect()
+    }
+}
+
+impl Middleware for MiddlewareChain {
+    fn before(&self, client: &ClientConnection, call: &RmcCall) -> Result<(), ResultCode> {
+        run_before(&self.as_refs(), client, call)
+    }
+
+    fn after(&self, client: &ClientConnection, call: &RmcCall, response: &RmcResponse) {
+        run_after(&self.as_refs(), client, call, response)
+    }
+}
+
+impl<T: Middleware + ?Sized> Middleware for &T {
+    fn before(&self, client: &ClientConnection, call: &RmcCall) -> Result<(), ResultCode> {
+        (**self).before(client, call)
+    }
+
+    fn after(&self, client: &ClientConnection, call: &RmcCall, response: &RmcResponse) {
+        (**self).after(client, call, response)
+    }
+}
+
+impl<T: Middleware + ?Sized> Middleware for Rc<T> {
+    fn before(&self, client: &ClientConnection, call: &RmcCall) -> Result<(), ResultCode> {
+        (**self).before(client, call)
+    }
+
+    fn after(&self, client: &ClientConnection, call: &RmcCall, response: &RmcResponse) {
+        (**self).after(client, call, response)
+    }
+}
+
+/// A call accepted by every middleware, returned by [RequestPipeline::begin_request].
+/// Pass it to [RequestPipeline::finish_request] with the handler's result, which runs
+/// the middleware's `after` and sends the response.
+pub struct ActiveRequest<'a> {
+    packet: &'a PacketV1,
+    call: RmcCall<'a>,
+    middlewares: Vec<Box<dyn Middleware + 'a>>,
+}
+
+impl<'a> ActiveRequest<'a> {
+    pub fn get_call(&self) -> &RmcCall<'a> {
+        &self.call
+    }
+
+    fn get_middlewares(&self) -> Vec<&dyn Middleware> {
+        self.middlewares
+            .iter()
+            .map(|middleware| middleware.as_ref() as &dyn Middleware)
+            .collect()
+    }
+}
+
+/// The middleware and response path shared by every protocol dispatcher.
+///
+/// Dispatchers begin a request, run the handler, then finish the request with the handler's
+/// result, so each middleware's `after` runs exactly when its `before` accepted the call.
+#[async_trait(?Send)]
+pub trait RequestPipeline: Server + ClientContextProvider {
+    /// The middleware run around every request: metrics, session authorization and
+    /// rate limiting when configured on the provider, then its middleware chain.
+    /// Only calls that would run spend a rate limit token, so rate limiting comes
+    /// after authorization.
+    fn get_middlewares(&self) -> Vec<Box<dyn Middleware + '_>> {
+        let mut middlewares: Vec<Box<dyn Middleware + '_>> = vec![];
+
+        if let Some(metrics) = self.get_metrics() {
+            middlewares.push(Box::new(metrics));
+        }
+
+        middlewares.push(Box::new(SessionAuthorization::new(
+            self.get_guest_policy(),
+            self.get_authorization_policy(),
+        )));
+
+        if let Some(rate_limiter) = self.get_rate_limiter() {
+            middlewares.push(Box::new(rate_limiter));
+        }
+
+        if let Some(chain) = self.get_middleware_chain() {
+            middlewares.push(Box::new(chain));
+        }
+
+        middlewares
+    }
+
+    /// Runs each middleware's `before`. A rejection has already been passed to the `after`
+    /// of the middleware that accepted the call, and only needs to be sent with [Self::send_response].
+    fn begin_request<'a>(
+        &'a self,
+        client: &ClientConnection,
+        packet: &'a PacketV1,
+        requires_session: bool,
+    ) -> Result<ActiveRequest<'a>, ResultCode> {
+        self.get_client_context_table().touch(client);
+
+        let request = ActiveRequest {
+            packet,
+            call: RmcCall::new(self, client, packet, requires_session),
+            middlewares: self.get_middlewares(),
+        };
+
+        run_before(&request.get_middlewares(), client, &request.call)?;
+        Ok(request)
+    }
+
+    /// Runs each middleware's `after` with the handler's result, then sends its response.
+    async fn finish_request(
+        &self,
+        client: &mut ClientConnection,
+        request: ActiveRequest<'_>,
+        result: HandlerResult,
+    ) -> Result<(), &'static str> {
+        let response = match &result {
+            Ok(Ok(data)) => RmcResponse::Success(data),
+            Ok(Err(error_code)) => RmcResponse::Error(*error_code),
+            Err(message) => RmcResponse::Failure(*message),
+        };
+
+        run_after(&request.get_middlewares(), client, &request.call, &response);
+        self.send_response(client, request.packet, result?).await
+    }
+
+    /// Sends a response without running any middleware.
+    async fn send_response(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+        result: Result<Vec<u8>, ResultCode>,
+    ) -> Result<(), &'static str> {
+        let rmc_request = packet.get_rmc_request();
+
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok(data) => tracing::debug!(response_size = data.len(), "request succeeded"),
+            Err(error_code) => tracing::debug!(error_code = ?error_code, "request failed"),
+        }
+
+        match result {
+            Ok(data) => {
+                self.send_success(
+                    client,
+                    rmc_request.protocol_id,
+                    rmc_request.method_id,
+                    rmc_request.call_id,
+                    data,
+                )
+                .await?
+            }
+            Err(error_code) => {
+                self.send_error(
+                    client,
+                    rmc_request.protocol_id,
+                    rmc_request.method_id,
+                    rmc_request.call_id,
+                    error_code.into(),
+                )
+                .await?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Server + ClientContextProvider + ?Sized> RequestPipeline for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    struct Recorder {
+        name: &'static str,
+        rejects: bool,
+        log: Log,
+    }
+
+    impl Middleware for Recorder {
+        fn before(&self, _client: &ClientConnection, _call: &RmcCall) -> Result<(), ResultCode> {
+            self.log.borrow_mut().push(format!("before {}", self.name));
+
+            if self.rejects {
+                return Err(ResultCode::Core_AccessDenied);
+            }
+
+            Ok(())
+        }
+
+        fn after(&self, _client: &ClientConnection, _call: &RmcCall, response: &RmcResponse) {
+            self.log
+                .borrow_mut()
+                .push(format!("after {} {:?}", self.name, response));
+        }
+    }
+
+    fn chain(log: &Log, rejecting: Option<&'static str>) -> MiddlewareChain {
+        let mut chain = MiddlewareChain::new();
+
+        for name in ["a", "b", "c"] {
+            chain.push(Recorder {
+                name,
+                rejects: rejecting == Some(name),
+                log: log.clone(),
+            });
+        }
+
+        chain
+    }
+
+    fn call() -> (ClientConnection, RmcCall<'static>) {
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, 1));
+        let client = ClientConnection::new(address, Default::default());
+        let call = RmcCall {
+            protocol_id: 1,
+            method_id: 1,
+            call_id: 1,
+            parameters: &[],
+            session: None,
+            requires_session: false,
+            received_at: Instant::now(),
+        };
+        (client, call)
+    }
+
+    #[test]
+    fn runs_after_in_reverse_order() {
+        let log = Log::default();
+        let chain = chain(&log, None);
+        let (client, call) = call();
+
+        assert_eq!(chain.before(&client, &call), Ok(()));
+        chain.after(&client, &call, &RmcResponse::Success(&[]));
+
+        assert_eq!(
+            *log.borrow(),
+            [
+                "before a",
+                "before b",
+                "before c",
+                "after c Success([])",
+                "after b Success([])",
+                "after a Success([])",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejections_only_reach_middleware_that_accepted_the_call() {
+        let log = Log::default();
+        let chain = chain(&log, Some("b"));
+        let (client, call) = call();
+
+        assert_eq!(
+            chain.before(&client, &call),
+            Err(ResultCode::Core_AccessDenied)
+        );
+
+        assert_eq!(
+            *log.borrow(),
+            ["before a", "before b", "after a Error(Core_AccessDenied)"]
+        );
+    }
+
+    #[test]
+    fn nested_chains_unwind_the_outer_middleware() {
+        let log = Log::default();
+        let outer = Recorder {
+            name: "outer",
+            rejects: false,
+            log: log.clone(),
+        };
+        let inner = chain(&log, Some("c"));
+        let middlewares: [&dyn Middleware; 2] = [&outer, &inner];
+        let (client, call) = call();
+
+        assert_eq!(
+            run_before(&middlewares, &client, &call),
+            Err(ResultCode::Core_AccessDenied)
+        );
+
+        assert_eq!(
+            *log.borrow(),
+            [
+                "before outer",
+                "before a",
+                "before b",
+                "before c",
+                "after b Error(Core_AccessDenied)",
+                "after a Error(Core_AccessDenied)",
+                "after outer Error(Core_AccessDenied)",
+            ]
+        );
+    }
+}
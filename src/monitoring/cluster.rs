@@ -0,0 +1,174 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MEMBER_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClusterNodeRole {
+    Authentication,
+    Secure,
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterMember {
+    pub station_url: String,
+    pub role: ClusterNodeRole,
+    pub joined_at: Instant,
+    pub last_seen: Instant,
+}
+
+/// Cluster members keyed by station url.
+/// Members that haven't announced themselves within the member timeout are no longer live.
+#[derive(Debug)]
+pub struct ClusterRegistry {
+    member_timeout: Duration,
+    members: RefCell<BTreeMap<String, ClusterMember>>,
+}
+
+impl Default for ClusterRegistry {
+    fn default() -> Self {
+        Self::with_member_timeout(DEFAULT_MEMBER_TIMEOUT)
+    }
+}
+
+impl ClusterRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_member_timeout(member_timeout: Duration) -> Self {
+        Self {
+            member_timeout,
+            members: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn announce(&self, station_url: &str, role: ClusterNodeRole) {
+        self.announce_at(station_url, role, Instant::now())
+    }
+
+    /// Adds a member, or refreshes it if it's already known.
+    pub fn announce_at(&self, station_url: &str, role: ClusterNodeRole, now: Instant) {
+        self.members
+            .borrow_mut()
+            .entry(station_url.to_string())
+            .and_modify(|member| {
+                member.role = role.clone();
+                member.last_seen = now;
+            })
+            .or_insert_with(|| ClusterMember {
+                station_url: station_url.to_string(),
+                role,
+                joined_at: now,
+                last_seen: now,
+            });
+    }
+
+    pub fn leave(&self, station_url: &str) -> Option<ClusterMember> {
+        self.members.borrow_mut().remove(station_url)
+    }
+
+    fn is_live(&self, member: &ClusterMember, now: Instant) -> bool {
+        now.saturating_duration_since(member.last_seen) <= self.member_timeout
+    }
+
+    pub fn get_live_members(&self) -> Vec<ClusterMember> {
+        self.get_live_members_at(Instant::now())
+    }
+
+    pub fn get_live_members_at(&self, now: Instant) -> Vec<ClusterMember> {
+        self.members
+            .borrow()
+            .values()
+            .filter(|member| self.is_live(member, now))
+            .cloned()
+            .collect()
+    }
+
+    /// Removes members that are no longer live.
+    pub fn prune(&self, now: Instant) {
+        self.members.borrow_mut().retain(|_, member| {
+            now.saturating_duration_since(member.last_seen) <= self.member_timeout
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn station_urls(members: &[ClusterMember]) -> Vec<&str> {
+        members
+            .iter()
+            .map(|member| member.station_url.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn refreshes_announced_members() {
+        let registry = ClusterRegistry::with_member_timeout(TIMEOUT);
+        let start = Instant::now();
+        let later = start + Duration::from_secs(5);
+
+        registry.announce_at("prudps:/address=1", ClusterNodeRole::Authentication, start);
+        registry.announce_at("prudps:/address=1", ClusterNodeRole::Secure, later);
+
+        let members = registry.get_live_members_at(later);
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].role, ClusterNodeRole::Secure);
+        assert_eq!(members[0].joined_at, start);
+        assert_eq!(members[0].last_seen, later);
+    }
+
+    #[test]
+    fn expires_members_after_the_timeout() {
+        let registry = ClusterRegistry::with_member_timeout(TIMEOUT);
+        let start = Instant::now();
+
+        registry.announce_at("prudps:/address=1", ClusterNodeRole::Authentication, start);
+        registry.announce_at(
+            "prudps:/address=2",
+            ClusterNodeRole::Secure,
+            start + Duration::from_secs(5),
+        );
+
+        assert_eq!(
+            station_urls(&registry.get_live_members_at(start + TIMEOUT)),
+            vec!["prudps:/address=1", "prudps:/address=2"]
+        );
+        assert_eq!(
+            station_urls(&registry.get_live_members_at(start + TIMEOUT + Duration::from_secs(1))),
+            vec!["prudps:/address=2"]
+        );
+    }
+
+    #[test]
+    fn prunes_expired_members() {
+        let registry = ClusterRegistry::with_member_timeout(TIMEOUT);
+        let start = Instant::now();
+        let expired_at = start + TIMEOUT + Duration::from_secs(1);
+
+        registry.announce_at("prudps:/address=1", ClusterNodeRole::Authentication, start);
+        registry.announce_at("prudps:/address=2", ClusterNodeRole::Secure, start);
+        registry.announce_at(
+            "prudps:/address=2",
+            ClusterNodeRole::Secure,
+            start + Duration::from_secs(5),
+        );
+
+        registry.prune(expired_at);
+
+        // Pruned members are gone even when checked before they expired
+        assert_eq!(
+            station_urls(&registry.get_live_members_at(start)),
+            vec!["prudps:/address=2"]
+        );
+        assert!(registry.leave("prudps:/address=1").is_none());
+        assert!(registry.leave("prudps:/address=2").is_some());
+        assert!(registry.get_live_members_at(start).is_empty());
+    }
+}
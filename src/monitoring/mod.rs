@@ -1,2 +1,8 @@
+mod cluster;
+pub use cluster::*;
+
 mod protocol;
 pub use protocol::*;
+
+mod server;
+pub use server::*;
@@ -1,3 +1,7 @@
+use crate::client_context::ClientContextProvider;
+use crate::middleware::{HandlerResult, RequestPipeline};
+#[cfg(feature = "tracing")]
+use crate::request_trace::request_span;
 use async_trait::async_trait;
 use nex_rs::client::ClientConnection;
 use nex_rs::nex_types::ResultCode;
@@ -5,6 +9,8 @@ use nex_rs::packet::{Packet, PacketV1};
 use nex_rs::server::Server;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
+pub const MONITORING_PROTOCOL_ID: u8 = 0x13;
+
 #[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive, IntoPrimitive)]
 #[repr(u32)]
 pub enum MonitoringMethod {
@@ -13,72 +19,96 @@ pub enum MonitoringMethod {
 }
 
 #[async_trait(?Send)]
-pub trait MonitoringProtocol: Server {
+pub trait MonitoringProtocol: Server + ClientContextProvider {
     async fn ping_daemon(&self, client: &mut ClientConnection) -> Result<Vec<u8>, ResultCode>;
     async fn get_cluster_members(
         &self,
         client: &mut ClientConnection,
     ) -> Result<Vec<u8>, ResultCode>;
 
-    async fn handle_ping_daemon(
+    /// Dispatches a request to its method's handler.
+    async fn handle_request(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
     ) -> Result<(), &'static str> {
         let rmc_request = packet.get_rmc_request();
-        match self.ping_daemon(client).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    rmc_request.protocol_id,
-                    rmc_request.method_id,
-                    rmc_request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    rmc_request.protocol_id,
-                    rmc_request.method_id,
-                    rmc_request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        let method = MonitoringMethod::try_from(rmc_request.method_id);
+
+        #[cfg(feature = "tracing")]
+        let span = request_span(
+            "Monitoring",
+            method.as_ref().ok(),
+            rmc_request.method_id,
+            rmc_request.call_id,
+            client.get_pid(),
+        );
+
+        let dispatch = async move {
+            let request = match self.begin_request(client, packet, true) {
+                Ok(request) => request,
+                Err(error_code) => {
+                    return self.send_response(client, packet, Err(error_code)).await
+                }
+            };
+
+            let result = match method {
+                Ok(MonitoringMethod::PingDaemon) => self.handle_ping_daemon(client, packet).await,
+                Ok(MonitoringMethod::GetClusterMembers) => {
+                    self.handle_get_cluster_members(client, packet).await
+                }
+                Err(_) => self.handle_unknown_method(client, packet).await,
+            };
+
+            self.finish_request(client, request, result).await
+        };
+
+        #[cfg(feature = "tracing")]
+        let dispatch = tracing::Instrument::instrument(dispatch, span);
+
+        dispatch.await
     }
 
-    async fn handle_get_cluster_members(
+    /// Called for method ids without a handler, e.g. to prototype undocumented methods.
+    /// Responds with `Core_NotImplemented` by default.
+    async fn handle_unknown(
+        &self,
+        _client: &mut ClientConnection,
+        _method_id: u32,
+        _parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn handle_unknown_method(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
-        let rmc_request = packet.get_rmc_request();
-        match self.get_cluster_members(client).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    rmc_request.protocol_id,
-                    rmc_request.method_id,
-                    rmc_request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    rmc_request.protocol_id,
-                    rmc_request.method_id,
-                    rmc_request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+
+        let result = self
+            .handle_unknown(client, request.method_id, parameters)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_ping_daemon(
+        &self,
+        client: &mut ClientConnection,
+        _packet: &PacketV1,
+    ) -> HandlerResult {
+        let result = self.ping_daemon(client).await;
+        Ok(result)
+    }
+
+    async fn handle_get_cluster_members(
+        &self,
+        client: &mut ClientConnection,
+        _packet: &PacketV1,
+    ) -> HandlerResult {
+        let result = self.get_cluster_members(client).await;
+        Ok(result)
     }
 }
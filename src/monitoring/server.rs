@@ -0,0 +1,46 @@
+use crate::client_context::ClientContextProvider;
+use crate::monitoring::{ClusterRegistry, MonitoringProtocol};
+use async_trait::async_trait;
+use nex_rs::client::ClientConnection;
+use nex_rs::nex_types::{NexList, NexString, ResultCode};
+use nex_rs::server::Server;
+use no_std_io::{EndianWrite, StreamContainer, StreamWriter};
+use std::time::Instant;
+
+fn write_response<T: EndianWrite>(value: &T) -> Result<Vec<u8>, ResultCode> {
+    let mut stream = StreamContainer::new(vec![]);
+    stream
+        .write_stream_le(value)
+        .map_err(|_| ResultCode::Core_SystemError)?;
+    Ok(stream.into_raw())
+}
+
+/// Implemented by servers that want [MonitoringProtocol] backed by a [ClusterRegistry].
+/// PingDaemon responds with the node's uptime in seconds as a u64, and
+/// GetClusterMembers with the station urls of the live members.
+pub trait MonitoringServer: Server + ClientContextProvider {
+    fn get_cluster_registry(&self) -> &ClusterRegistry;
+    fn get_start_time(&self) -> Instant;
+}
+
+#[async_trait(?Send)]
+impl<T: MonitoringServer> MonitoringProtocol for T {
+    async fn ping_daemon(&self, _client: &mut ClientConnection) -> Result<Vec<u8>, ResultCode> {
+        let uptime = self.get_start_time().elapsed().as_secs();
+        write_response(&uptime)
+    }
+
+    async fn get_cluster_members(
+        &self,
+        _client: &mut ClientConnection,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let station_urls: Vec<NexString> = self
+            .get_cluster_registry()
+            .get_live_members()
+            .into_iter()
+            .map(|member| NexString::from(member.station_url))
+            .collect();
+
+        write_response(&NexList::from(station_urls))
+    }
+}
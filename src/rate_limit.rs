@@ -0,0 +1,321 @@
+use crate::datastore_usum::{DataStoreMethod, DATASTORE_PROTOCOL_ID};
+use crate::matchmake_extension::{MatchmakeExtensionMethod, MATCHMAKE_EXTENSION_PROTOCOL_ID};
+use crate::middleware::{Middleware, RmcCall};
+use nex_rs::client::ClientConnection;
+use nex_rs::nex_types::ResultCode;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+
+/// Full buckets are pruned every this many checks, so buckets of pids that stopped
+/// calling don't pile up between disconnects.
+const PRUNE_INTERVAL: u32 = 1024;
+
+/// A token bucket allowing bursts of `capacity` calls, refilled at `refill_per_second`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub refill_per_second: f64,
+}
+
+impl RateLimit {
+    pub fn new(capacity: u32, refill_per_second: f64) -> Self {
+        Self {
+            capacity,
+            refill_per_second,
+        }
+    }
+}
+
+/// Who a bucket belongs to. Calls from sessions are limited per pid, calls made before
+/// a session exists, e.g. logins, per IP address so reconnecting doesn't reset them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateLimitKey {
+    Pid(u32),
+    Address(IpAddr),
+}
+
+impl From<u32> for RateLimitKey {
+    fn from(pid: u32) -> Self {
+        Self::Pid(pid)
+    }
+}
+
+impl From<IpAddr> for RateLimitKey {
+    fn from(address: IpAddr) -> Self {
+        Self::Address(address)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.refill_per_second).min(limit.capacity as f64);
+        self.updated_at = now;
+    }
+}
+
+/// Limits calls per ([RateLimitKey], protocol id, method id).
+/// Methods without a configured limit use the default limit, if any.
+#[derive(Debug)]
+pub struct RateLimiter {
+    default_limit: Option<RateLimit>,
+    limits: HashMap<(u8, u32), RateLimit>,
+    rejection_code: ResultCode,
+    buckets: RefCell<HashMap<(RateLimitKey, u8, u32), Bucket>>,
+    checks_since_prune: Cell<u32>,
+    limited_counts: RefCell<HashMap<(u8, u32), u64>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self {
+            default_limit: None,
+            limits: HashMap::new(),
+            rejection_code: ResultCode::Core_AccessDenied,
+            buckets: RefCell::new(HashMap::new()),
+            checks_since_prune: Cell::new(0),
+            limited_counts: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Creates a limiter without any limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the methods that are the easiest to spam.
+    pub fn recommended() -> Self {
+        let mut limiter = Self::new();
+        limiter.set_limit(
+            DATASTORE_PROTOCOL_ID,
+            DataStoreMethod::RateObject,
+            RateLimit::new(5, 0.5),
+        );
+        limiter.set_limit(
+            DATASTORE_PROTOCOL_ID,
+            DataStoreMethod::SearchPokemonV2,
+            RateLimit::new(10, 1.0),
+        );
+        limiter.set_limit(
+            MATCHMAKE_EXTENSION_PROTOCOL_ID,
+            MatchmakeExtensionMethod::BrowseMatchmakeSession,
+            RateLimit::new(10, 1.0),
+        );
+        limiter
+    }
+
+    pub fn set_default_limit(&mut self, limit: Option<RateLimit>) {
+        self.default_limit = limit;
+    }
+
+    pub fn set_limit(&mut self, protocol_id: u8, method: impl Into<u32>, limit: RateLimit) {
+        self.limits.insert((protocol_id, method.into()), limit);
+    }
+
+    /// The result code sent for limited calls, `Core_AccessDenied` by default.
+    pub fn set_rejection_code(&mut self, rejection_code: ResultCode) {
+        self.rejection_code = rejection_code;
+    }
+
+    pub fn get_limit(&self, protocol_id: u8, method_id: u32) -> Option<&RateLimit> {
+        self.limits
+            .get(&(protocol_id, method_id))
+            .or(self.default_limit.as_ref())
+    }
+
+    pub fn check(
+        &self,
+        key: impl Into<RateLimitKey>,
+        protocol_id: u8,
+        method_id: u32,
+    ) -> Result<(), ResultCode> {
+        self.check_at(key, protocol_id, method_id, Instant::now())
+    }
+
+    /// Takes a token from the caller's bucket, rejecting the call if the bucket is empty.
+    pub fn check_at(
+        &self,
+        key: impl Into<RateLimitKey>,
+        protocol_id: u8,
+        method_id: u32,
+        now: Instant,
+    ) -> Result<(), ResultCode> {
+        let limit = match self.get_limit(protocol_id, method_id) {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let checks_since_prune = self.checks_since_prune.get() + 1;
+        if checks_since_prune >= PRUNE_INTERVAL {
+            self.prune(now);
+            self.checks_since_prune.set(0);
+        } else {
+            self.checks_since_prune.set(checks_since_prune);
+        }
+
+        let mut buckets = self.buckets.borrow_mut();
+        let bucket = buckets
+            .entry((key.into(), protocol_id, method_id))
+            .or_insert(Bucket {
+                tokens: limit.capacity as f64,
+                updated_at: now,
+            });
+        bucket.refill(limit, now);
+
+        if bucket.tokens < 1.0 {
+            *self
+                .limited_counts
+                .borrow_mut()
+                .entry((protocol_id, method_id))
+                .or_insert(0) += 1;
+            return Err(self.rejection_code);
+        }
+
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Forgets a pid's buckets, e.g. when it disconnects.
+    pub fn remove_pid(&self, pid: u32) {
+        self.buckets
+            .borrow_mut()
+            .retain(|(key, _, _), _| *key != RateLimitKey::Pid(pid));
+    }
+
+    /// Drops buckets that have refilled completely, since they behave like new buckets.
+    /// Checks also do this periodically.
+    pub fn prune(&self, now: Instant) {
+        self.buckets
+            .borrow_mut()
+            .retain(|(_, protocol_id, method_id), bucket| {
+                match self.get_limit(*protocol_id, *method_id) {
+                    Some(limit) => {
+                        bucket.refill(limit, now);
+                        bucket.tokens < limit.capacity as f64
+                    }
+                    None => false,
+                }
+            });
+    }
+
+    /// How often calls to a method were limited.
+    pub fn get_limited_count(&self, protocol_id: u8, method_id: u32) -> u64 {
+        self.limited_counts
+            .borrow()
+            .get(&(protocol_id, method_id))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn get_limited_counts(&self) -> HashMap<(u8, u32), u64> {
+        self.limited_counts.borrow().clone()
+    }
+
+    pub fn get_total_limited_count(&self) -> u64 {
+        self.limited_counts.borrow().values().sum()
+    }
+}
+
+/// Limits every call, by the caller's pid once it has a session and by its IP address before.
+/// Run by the protocol dispatchers after authorization, so only calls that would run spend a token.
+impl Middleware for RateLimiter {
+    fn before(&self, client: &ClientConnection, call: &RmcCall) -> Result<(), ResultCode> {
+        let key = match &call.session {
+            Some(session) => RateLimitKey::Pid(session.pid),
+            None => RateLimitKey::Address(client.get_address().ip()),
+        };
+
+        self.check(key, call.protocol_id, call.method_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ticket_granting::{TicketGrantingMethod, AUTHENTICATION_PROTOCOL_ID};
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::time::Duration;
+
+    fn limiter() -> RateLimiter {
+        let mut limiter = RateLimiter::new();
+        limiter.set_default_limit(Some(RateLimit::new(2, 1.0)));
+        limiter
+    }
+
+    #[test]
+    fn limits_bursts_and_refills() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        assert_eq!(limiter.check_at(1, 1, 1, now), Ok(()));
+        assert_eq!(limiter.check_at(1, 1, 1, now), Ok(()));
+        assert_eq!(
+            limiter.check_at(1, 1, 1, now),
+            Err(ResultCode::Core_AccessDenied)
+        );
+        assert_eq!(limiter.check_at(2, 1, 1, now), Ok(()));
+        assert_eq!(
+            limiter.check_at(1, 1, 1, now + Duration::from_secs(1)),
+            Ok(())
+        );
+        assert_eq!(limiter.get_limited_count(1, 1), 1);
+    }
+
+    #[test]
+    fn limits_calls_without_a_session_by_address() {
+        let limiter = limiter();
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, 1));
+        let client = ClientConnection::new(address, Default::default());
+        let call = RmcCall {
+            protocol_id: AUTHENTICATION_PROTOCOL_ID,
+            method_id: TicketGrantingMethod::Login.into(),
+            call_id: 1,
+            parameters: &[],
+            session: None,
+            requires_session: false,
+            received_at: Instant::now(),
+        };
+
+        assert_eq!(limiter.before(&client, &call), Ok(()));
+        assert_eq!(limiter.before(&client, &call), Ok(()));
+        assert_eq!(
+            limiter.before(&client, &call),
+            Err(ResultCode::Core_AccessDenied)
+        );
+
+        // A reconnect from the same address shares the bucket
+        let reconnected = ClientConnection::new(
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 2)),
+            Default::default(),
+        );
+        assert_eq!(
+            limiter.before(&reconnected, &call),
+            Err(ResultCode::Core_AccessDenied)
+        );
+    }
+
+    #[test]
+    fn prunes_full_buckets_while_checking() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for pid in 0..PRUNE_INTERVAL - 1 {
+            limiter.check_at(pid, 1, 1, now).unwrap();
+        }
+        assert_eq!(limiter.buckets.borrow().len(), PRUNE_INTERVAL as usize - 1);
+
+        let later = now + Duration::from_secs(10);
+        limiter.check_at(PRUNE_INTERVAL, 1, 1, later).unwrap();
+        assert_eq!(limiter.buckets.borrow().len(), 1);
+    }
+}
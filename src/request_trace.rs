@@ -0,0 +1,42 @@
+//! Structured tracing for RMC requests, enabled with the `tracing` feature.
+
+use core::fmt;
+
+/// The span every protocol dispatcher runs its handler in.
+/// Methods without a name, e.g. ones the protocol doesn't know, are shown by their id.
+#[cfg(feature = "tracing")]
+pub fn request_span<M: fmt::Debug>(
+    protocol: &'static str,
+    method: Option<&M>,
+    method_id: u32,
+    call_id: u32,
+    pid: u32,
+) -> tracing::Span {
+    let method = match method {
+        Some(method) => format!("{:?}", method),
+        None => format!("{:#x}", method_id),
+    };
+
+    tracing::info_span!("rmc_request", protocol, method = %method, call_id, pid)
+}
+
+/// Stands in for secrets, e.g. tokens and passwords, in Debug output
+/// so they don't end up in traced parameters.
+pub(crate) struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+/// Logs a handler's decoded parameters with their Debug representation.
+/// Expands to nothing without the `tracing` feature.
+macro_rules! trace_parameters {
+    ($($parameter:ident),* $(,)?) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($parameter = ?$parameter,)* "decoded parameters");
+    };
+}
+
+pub(crate) use trace_parameters;
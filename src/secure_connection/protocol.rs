@@ -1,6 +1,13 @@
+use crate::any_data_holder::AnyDataHolder;
+use crate::client_context::ClientContextProvider;
+use crate::middleware::{HandlerResult, RequestPipeline};
+#[cfg(feature = "tracing")]
+use crate::request_trace::request_span;
+use crate::request_trace::trace_parameters;
+use crate::structure::{read_list, read_qbuffer};
 use async_trait::async_trait;
 use nex_rs::client::ClientConnection;
-use nex_rs::nex_types::{DataHolder, NexList, NexQBuffer, NexString, ResultCode};
+use nex_rs::nex_types::{NexList, NexQBuffer, NexString, ResultCode};
 use nex_rs::packet::{Packet, PacketV1};
 use nex_rs::server::Server;
 use no_std_io::{StreamContainer, StreamReader};
@@ -22,7 +29,7 @@ pub enum SecureConnectionMethod {
 }
 
 #[async_trait(?Send)]
-pub trait SecureConnectionProtocol: Server {
+pub trait SecureConnectionProtocol: Server + ClientContextProvider {
     async fn register(
         &self,
         client: &mut ClientConnection,
@@ -44,70 +51,148 @@ pub trait SecureConnectionProtocol: Server {
         &self,
         client: &mut ClientConnection,
         my_urls: NexList<NexString>,
-        custom_data: DataHolder<NexString>,
+        custom_data: AnyDataHolder,
     ) -> Result<Vec<u8>, ResultCode>;
-    async fn test_connectivity(&self, client: &mut ClientConnection) -> Result<(), &'static str>;
+    async fn test_connectivity(&self, client: &mut ClientConnection)
+        -> Result<Vec<u8>, ResultCode>;
     async fn update_urls(
         &self,
         client: &mut ClientConnection,
         my_urls: NexList<NexString>,
-    ) -> Result<(), &'static str>;
+    ) -> Result<Vec<u8>, ResultCode>;
     async fn replace_url(
         &self,
         client: &mut ClientConnection,
         target: NexString,
         url: NexString,
-    ) -> Result<(), &'static str>;
+    ) -> Result<Vec<u8>, ResultCode>;
     async fn send_report(
         &self,
         client: &mut ClientConnection,
         report_id: u32,
         report_data: NexQBuffer,
-    ) -> Result<(), &'static str>;
+    ) -> Result<Vec<u8>, ResultCode>;
 
-    async fn handle_register(
+    /// Dispatches a request to its method's handler.
+    async fn handle_request(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
     ) -> Result<(), &'static str> {
+        let rmc_request = packet.get_rmc_request();
+        let method = SecureConnectionMethod::try_from(rmc_request.method_id);
+
+        #[cfg(feature = "tracing")]
+        let span = request_span(
+            "SecureConnection",
+            method.as_ref().ok(),
+            rmc_request.method_id,
+            rmc_request.call_id,
+            client.get_pid(),
+        );
+
+        let dispatch = async move {
+            let requires_session = !matches!(
+                method,
+                Ok(SecureConnectionMethod::Register | SecureConnectionMethod::RegisterEx)
+            );
+
+            let request = match self.begin_request(client, packet, requires_session) {
+                Ok(request) => request,
+                Err(error_code) => {
+                    return self.send_response(client, packet, Err(error_code)).await
+                }
+            };
+
+            let result = match method {
+                Ok(SecureConnectionMethod::Register) => self.handle_register(client, packet).await,
+                Ok(SecureConnectionMethod::RequestConnectionData) => {
+                    self.handle_request_connection_data(client, packet).await
+                }
+                Ok(SecureConnectionMethod::RequestURLs) => {
+                    self.handle_request_urls(client, packet).await
+                }
+                Ok(SecureConnectionMethod::RegisterEx) => {
+                    self.handle_register_ex(client, packet).await
+                }
+                Ok(SecureConnectionMethod::TestConnectivity) => {
+                    self.handle_test_connectivity(client, packet).await
+                }
+                Ok(SecureConnectionMethod::UpdateURLs) => {
+                    self.handle_update_urls(client, packet).await
+                }
+                Ok(SecureConnectionMethod::ReplaceURL) => {
+                    self.handle_replace_url(client, packet).await
+                }
+                Ok(SecureConnectionMethod::SendReport) => {
+                    self.handle_send_report(client, packet).await
+                }
+                Err(_) => self.handle_unknown_method(client, packet).await,
+            };
+
+            self.finish_request(client, request, result).await
+        };
+
+        #[cfg(feature = "tracing")]
+        let dispatch = tracing::Instrument::instrument(dispatch, span);
+
+        dispatch.await
+    }
+
+    /// Called for method ids without a handler, e.g. to prototype undocumented methods.
+    /// Responds with `Core_NotImplemented` by default.
+    async fn handle_unknown(
+        &self,
+        _client: &mut ClientConnection,
+        _method_id: u32,
+        _parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn handle_unknown_method(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+
+        let result = self
+            .handle_unknown(client, request.method_id, parameters)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_register(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
         let mut parameters_stream = StreamContainer::new(parameters);
 
-        let my_urls = parameters_stream
-            .read_stream_le::<NexList<NexString>>()
-            .map_err(|_| "Can not read my urls")?;
-
-        match self.register(client, my_urls).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
+        let my_urls =
+            read_list::<NexString>(&mut parameters_stream).map_err(|_| "Can not read my urls")?;
+        let station_urls: Vec<String> = my_urls.iter().map(|url| url.clone().into()).collect();
+
+        trace_parameters!(my_urls);
+        let result = self.register(client, my_urls).await;
+
+        if result.is_ok() {
+            self.get_client_context_table()
+                .start_session(client, station_urls);
         }
-        Ok(())
+
+        Ok(result)
     }
 
     async fn handle_request_connection_data(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
         let mut parameters_stream = StreamContainer::new(parameters);
@@ -120,39 +205,18 @@ pub trait SecureConnectionProtocol: Server {
             .read_stream_le::<u32>()
             .map_err(|_| "Can not read pid target")?;
 
-        match self
+        trace_parameters!(cid_target, pid_target);
+        let result = self
             .request_connection_data(client, cid_target, pid_target)
-            .await
-        {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+            .await;
+        Ok(result)
     }
 
     async fn handle_request_urls(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
         let mut parameters_stream = StreamContainer::new(parameters);
@@ -165,134 +229,144 @@ pub trait SecureConnectionProtocol: Server {
             .read_stream_le::<u32>()
             .map_err(|_| "Can not read pid target")?;
 
-        match self.request_urls(client, cid_target, pid_target).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        trace_parameters!(cid_target, pid_target);
+        let result = self.request_urls(client, cid_target, pid_target).await;
+        Ok(result)
     }
 
     async fn handle_register_ex(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
         let mut parameters_stream = StreamContainer::new(parameters);
 
-        let my_urls = parameters_stream
-            .read_stream_le::<NexList<NexString>>()
-            .map_err(|_| "Can not read my urls")?;
+        let my_urls =
+            read_list::<NexString>(&mut parameters_stream).map_err(|_| "Can not read my urls")?;
+        let station_urls: Vec<String> = my_urls.iter().map(|url| url.clone().into()).collect();
 
-        let custom_data = parameters_stream
-            .read_stream_le::<DataHolder<NexString>>()
+        let nex_version = self.get_title_profile(client).nex_version;
+        let custom_data = self
+            .get_client_context_table()
+            .get_data_holders()
+            .read(&mut parameters_stream, nex_version)
             .map_err(|_| "Can not read custom data")?;
 
-        match self.register_ex(client, my_urls, custom_data).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
+        trace_parameters!(my_urls, custom_data);
+
+        let title_id = match self.get_title_id_from_data_holder(&custom_data) {
+            Ok(title_id) => title_id,
+            Err(error_code) => return Ok(Err(error_code)),
+        };
+
+        let result = self.register_ex(client, my_urls, custom_data).await;
+
+        if result.is_ok() {
+            self.get_client_context_table().start_session_with_title(
+                client,
+                station_urls,
+                title_id,
+            );
         }
-        Ok(())
+
+        Ok(result)
     }
 
     async fn handle_test_connectivity(
         &self,
         client: &mut ClientConnection,
         _packet: &PacketV1,
-    ) -> Result<(), &'static str> {
-        self.test_connectivity(client).await
+    ) -> HandlerResult {
+        let result = self.test_connectivity(client).await;
+        Ok(result)
     }
 
     async fn handle_update_urls(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
         let mut parameters_stream = StreamContainer::new(parameters);
 
-        let my_urls = parameters_stream
-            .read_stream_le::<NexList<NexString>>()
-            .map_err(|_| "Can not read my urls")?;
+        let my_urls =
+            read_list::<NexString>(&mut parameters_stream).map_err(|_| "Can not read my urls")?;
+
+        let station_urls: Vec<String> = my_urls.iter().map(|url| url.clone().into()).collect();
+
+        trace_parameters!(my_urls);
+        let result = self.update_urls(client, my_urls).await;
+
+        if result.is_ok() {
+            self.get_client_context_table().update(client, |context| {
+                if let Some(session) = context.session.as_mut() {
+                    session.station_urls = station_urls;
+                }
+            });
+        }
 
-        self.update_urls(client, my_urls).await
+        Ok(result)
     }
 
     async fn handle_replace_url(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
         let mut parameters_stream = StreamContainer::new(parameters);
 
         let target = parameters_stream
             .read_stream_le::<NexString>()
-            .map_err(|_| "Can not read my urls")?;
+            .map_err(|_| "Can not read target url")?;
 
         let url = parameters_stream
             .read_stream_le::<NexString>()
-            .map_err(|_| "Can not read my urls")?;
+            .map_err(|_| "Can not read url")?;
+
+        let target_url: String = target.clone().into();
+        let station_url: String = url.clone().into();
+
+        trace_parameters!(target, url);
+        let result = self.replace_url(client, target, url).await;
 
-        self.replace_url(client, target, url).await
+        if result.is_ok() {
+            self.get_client_context_table().update(client, |context| {
+                if let Some(session) = context.session.as_mut() {
+                    for session_url in session.station_urls.iter_mut() {
+                        if *session_url == target_url {
+                            *session_url = station_url.clone();
+                        }
+                    }
+                }
+            });
+        }
+
+        Ok(result)
     }
 
     async fn handle_send_report(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
         let parameters = request.parameters.as_slice();
         let mut parameters_stream = StreamContainer::new(parameters);
 
         let report_id = parameters_stream
             .read_stream_le::<u32>()
-            .map_err(|_| "Can not read my urls")?;
+            .map_err(|_| "Can not read report id")?;
 
-        let report_data = parameters_stream
-            .read_stream_le::<NexQBuffer>()
-            .map_err(|_| "Can not read my urls")?;
+        let report_data =
+            read_qbuffer(&mut parameters_stream).map_err(|_| "Can not read report data")?;
 
-        self.send_report(client, report_id, report_data).await
+        trace_parameters!(report_id, report_data);
+        let result = self.send_report(client, report_id, report_data).await;
+        Ok(result)
     }
 }
@@ -0,0 +1,488 @@
+use nex_rs::nex_types::{NexBuffer, NexList, NexQBuffer, NexString, NexStruct};
+use no_std_io::{
+    Cursor, EndianRead, EndianWrite, Error, ReadOutput, StreamContainer, StreamReader,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NexVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+    /// Last, so a headerless version still compares as at least its own version.
+    headerless: bool,
+}
+
+impl NexVersion {
+    pub const fn new(major: u8, minor: u8, patch: u8) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            headerless: false,
+        }
+    }
+
+    /// Keeps the fields of this version but encodes structures without headers,
+    /// like the derived layouts used before structures were NEX version aware.
+    pub const fn without_structure_headers(self) -> Self {
+        Self {
+            headerless: true,
+            ..self
+        }
+    }
+
+    pub fn has_structure_headers(&self) -> bool {
+        !self.headerless && self.has_nex_struct_headers()
+    }
+
+    /// Fixed layout types wrapped in a [NexStruct] keep their header in headerless versions,
+    /// as they always had one before structures were NEX version aware.
+    pub fn has_nex_struct_headers(&self) -> bool {
+        *self >= NexVersion::new(3, 5, 0)
+    }
+}
+
+/// The layout structures used before they were encoded with NEX version awareness:
+/// every field up to NEX 3.8, without structure headers.
+///
+/// Title profiles for clients that send headers should use [NexVersion::new] instead.
+pub const DEFAULT_NEX_VERSION: NexVersion = NexVersion::new(3, 8, 0).without_structure_headers();
+
+/// Upper bound for the item count of a decoded list.
+/// Counts come straight from requests, so larger ones are rejected before any items are read.
+pub const MAX_LIST_LENGTH: u32 = 0x1000;
+
+/// Upper bound for the byte length of a decoded Buffer or qBuffer.
+pub const MAX_BUFFER_LENGTH: u32 = 0x4000;
+
+/// Upper bound for the object length of a decoded DataHolder.
+pub const MAX_DATA_HOLDER_LENGTH: u32 = 0x10000;
+
+/// The root of every NEX structure hierarchy, e.g. Data -> Gathering -> MatchmakeSession.
+#[derive(Debug, Default, EndianRead, EndianWrite)]
+pub struct Data {}
+
+/// A NEX Structure whose layout depends on the NEX version.
+///
+/// From NEX 3.5 onwards each structure in a hierarchy is prefixed with a header
+/// holding its structure version and content length, parent first.
+pub trait NexStructure: Sized {
+    const STRUCTURE_VERSION: u8 = 0;
+
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error>;
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error>;
+}
+
+impl NexStructure for Data {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        read_structure_content(stream, nex_version, |_| Ok(Data {}))
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        write_structure_content(Self::STRUCTURE_VERSION, nex_version, vec![])
+    }
+}
+
+/// Reads a structure's own content, skipping any trailing content newer than `nex_version`.
+pub fn read_structure_content<T>(
+    stream: &mut StreamContainer<&[u8]>,
+    nex_version: NexVersion,
+    read_content: impl FnOnce(&mut StreamContainer<&[u8]>) -> Result<T, Error>,
+) -> Result<T, Error> {
+    if !nex_version.has_structure_headers() {
+        return read_content(stream);
+    }
+
+    let _structure_version = stream.read_stream_le::<u8>()?;
+    let content_length = stream.read_stream_le::<u32>()? as usize;
+    let content_start = stream.get_index();
+    let content = read_content(stream)?;
+    let content_end = content_start
+        .checked_add(content_length)
+        .ok_or(Error::InvalidRead {
+            message: "Structure content length is too large",
+        })?;
+
+    if stream.get_index() > content_end {
+        return Err(Error::InvalidRead {
+            message: "Structure content exceeds its header length",
+        });
+    }
+
+    stream.set_index(content_end);
+    Ok(content)
+}
+
+/// Prefixes a structure's own content with its header when `nex_version` uses headers.
+pub fn write_structure_content(
+    structure_version: u8,
+    nex_version: NexVersion,
+    content: Vec<u8>,
+) -> Result<Vec<u8>, Error> {
+    if !nex_version.has_structure_headers() {
+        return Ok(content);
+    }
+
+    let mut data = Vec::with_capacity(content.len() + 5);
+    data.push(structure_version);
+    data.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    data.extend(content);
+    Ok(data)
+}
+
+pub fn write_field<T: EndianWrite>(content: &mut Vec<u8>, value: &T) -> Result<(), Error> {
+    let mut field = vec![0; value.get_size()];
+    value.try_write_le(&mut field)?;
+    content.extend(field);
+    Ok(())
+}
+
+pub fn read_structure_list<T: NexStructure>(
+    stream: &mut StreamContainer<&[u8]>,
+    nex_version: NexVersion,
+) -> Result<NexList<T>, Error> {
+    let count = stream.read_stream_le::<u32>()?;
+
+    if count > MAX_LIST_LENGTH {
+        return Err(Error::InvalidRead {
+            message: "Structure list length exceeds the limit",
+        });
+    }
+
+    let mut items = vec![];
+
+    for _ in 0..count {
+        items.push(T::read_structure(stream, nex_version)?);
+    }
+
+    Ok(NexList::from(items))
+}
+
+/// Checks the length prefix at the stream's index without consuming it.
+fn check_length_prefix<T: EndianRead + Into<u32>>(
+    stream: &mut StreamContainer<&[u8]>,
+    max_length: u32,
+    message: &'static str,
+) -> Result<(), Error> {
+    let start = stream.get_index();
+    let length: u32 = stream.read_stream_le::<T>()?.into();
+    stream.set_index(start);
+
+    if length > max_length {
+        return Err(Error::InvalidRead { message });
+    }
+
+    Ok(())
+}
+
+/// Reads a List, rejecting counts over [MAX_LIST_LENGTH] before any items are read.
+pub fn read_list<T>(stream: &mut StreamContainer<&[u8]>) -> Result<NexList<T>, Error>
+where
+    NexList<T>: EndianRead,
+{
+    check_length_prefix::<u32>(stream, MAX_LIST_LENGTH, "List length exceeds the limit")?;
+    stream.read_stream_le::<NexList<T>>()
+}
+
+/// Reads a Buffer, rejecting lengths over [MAX_BUFFER_LENGTH].
+pub fn read_buffer(stream: &mut StreamContainer<&[u8]>) -> Result<NexBuffer, Error> {
+    check_length_prefix::<u32>(stream, MAX_BUFFER_LENGTH, "Buffer length exceeds the limit")?;
+    stream.read_stream_le::<NexBuffer>()
+}
+
+/// Reads a qBuffer, rejecting lengths over [MAX_BUFFER_LENGTH].
+pub fn read_qbuffer(stream: &mut StreamContainer<&[u8]>) -> Result<NexQBuffer, Error> {
+    check_length_prefix::<u16>(
+        stream,
+        MAX_BUFFER_LENGTH,
+        "qBuffer length exceeds the limit",
+    )?;
+    stream.read_stream_le::<NexQBuffer>()
+}
+
+/// Reads the next `length` bytes without copying them.
+pub fn read_slice<'a>(
+    stream: &mut StreamContainer<&'a [u8]>,
+    length: usize,
+) -> Result<&'a [u8], Error> {
+    let start = stream.get_index();
+    let data = std::mem::replace(stream, StreamContainer::new(&[][..])).into_raw();
+    *stream = StreamContainer::new(data);
+    stream.set_index(start);
+
+    let end = start
+        .checked_add(length)
+        .filter(|end| *end <= data.len())
+        .ok_or(Error::InvalidRead {
+            message: "Not enough data to read",
+        })?;
+
+    stream.set_index(end);
+    Ok(&data[start..end])
+}
+
+pub fn write_structure_list<'a, T: NexStructure + 'a>(
+    items: impl ExactSizeIterator<Item = &'a T>,
+    nex_version: NexVersion,
+) -> Result<Vec<u8>, Error> {
+    let mut data = (items.len() as u32).to_le_bytes().to_vec();
+
+    for item in items {
+        data.extend(item.write_structure(nex_version)?);
+    }
+
+    Ok(data)
+}
+
+/// Reads a fixed layout structure, which only has a [NexStruct] header from NEX 3.5 onwards.
+pub fn read_nex_struct<T: EndianRead + EndianWrite>(
+    stream: &mut StreamContainer<&[u8]>,
+    nex_version: NexVersion,
+) -> Result<T, Error> {
+    if nex_version.has_nex_struct_headers() {
+        Ok(stream.read_stream_le::<NexStruct<T>>()?.into_raw())
+    } else {
+        stream.read_stream_le::<T>()
+    }
+}
+
+/// Checks the two lengths preceding a DataHolder's object,
+/// the first of which also counts the second.
+pub fn check_data_holder_lengths(length_1: u32, length_2: u32) -> Result<(), Error> {
+    if length_2 > MAX_DATA_HOLDER_LENGTH {
+        return Err(Error::InvalidRead {
+            message: "DataHolder length exceeds the limit",
+        });
+    }
+
+    if length_2.checked_add(4) != Some(length_1) {
+        return Err(Error::InvalidRead {
+            message: "DataHolder lengths don't match",
+        });
+    }
+
+    Ok(())
+}
+
+/// Reads a DataHolder wrapping a NEX structure, returning the holder's class name and object.
+pub fn read_data_holder_structure<T: NexStructure>(
+    stream: &mut StreamContainer<&[u8]>,
+    nex_version: NexVersion,
+) -> Result<(String, T), Error> {
+    let name: String = stream.read_stream_le::<NexString>()?.into();
+    let length_1 = stream.read_stream_le::<u32>()?;
+    let length_2 = stream.read_stream_le::<u32>()?;
+    check_data_holder_lengths(length_1, length_2)?;
+
+    let start = stream.get_index();
+    let object = T::read_structure(stream, nex_version)?;
+
+    if stream.get_index() - start != length_2 as usize {
+        return Err(Error::InvalidRead {
+            message: "DataHolder object doesn't match its length",
+        });
+    }
+
+    Ok((name, object))
+}
+
+/// Writes a NEX structure wrapped in a DataHolder with the given class name.
+pub fn write_data_holder_structure<T: NexStructure>(
+    name: &str,
+    object: &T,
+    nex_version: NexVersion,
+) -> Result<Vec<u8>, Error> {
+    let object = object.write_structure(nex_version)?;
+    let mut data = vec![];
+
+    write_field(&mut data, &NexString::from(name.to_string()))?;
+    write_field(&mut data, &(object.len() as u32 + 4))?;
+    write_field(&mut data, &(object.len() as u32))?;
+    data.extend(object);
+    Ok(data)
+}
+
+pub fn read_nex_structure<T: NexStructure>(
+    bytes: &[u8],
+    nex_version: NexVersion,
+) -> Result<ReadOutput<T>, Error> {
+    let mut stream = StreamContainer::new(bytes);
+    let structure = T::read_structure(&mut stream, nex_version)?;
+    Ok(ReadOutput::new(structure, stream.get_index()))
+}
+
+pub fn write_nex_structure<T: NexStructure>(
+    structure: &T,
+    nex_version: NexVersion,
+    dst: &mut [u8],
+) -> Result<usize, Error> {
+    let data = structure.write_structure(nex_version)?;
+
+    if dst.len() < data.len() {
+        return Err(Error::InvalidSize {
+            wanted_size: data.len(),
+            offset: 0,
+            data_len: dst.len(),
+        });
+    }
+
+    dst[..data.len()].copy_from_slice(&data);
+    Ok(data.len())
+}
+
+/// Implements EndianRead and EndianWrite for NEX structures using [DEFAULT_NEX_VERSION],
+/// so they can still be embedded in derived types.
+macro_rules! impl_endian_for_nex_structure {
+    ($($structure:ty),* $(,)?) => {
+        $(
+            impl no_std_io::EndianRead for $structure {
+                fn try_read_le(
+                    bytes: &[u8],
+                ) -> Result<no_std_io::ReadOutput<Self>, no_std_io::Error> {
+                    $crate::structure::read_nex_structure(
+                        bytes,
+                        $crate::structure::DEFAULT_NEX_VERSION,
+                    )
+                }
+
+                fn try_read_be(
+                    _bytes: &[u8],
+                ) -> Result<no_std_io::ReadOutput<Self>, no_std_io::Error> {
+                    Err(no_std_io::Error::InvalidRead {
+                        message: "NEX structures are little endian",
+                    })
+                }
+            }
+
+            impl no_std_io::EndianWrite for $structure {
+                fn get_size(&self) -> usize {
+                    $crate::structure::NexStructure::write_structure(
+                        self,
+                        $crate::structure::DEFAULT_NEX_VERSION,
+                    )
+                    .map(|data| data.len())
+                    .unwrap_or(0)
+                }
+
+                fn try_write_le(&self, dst: &mut [u8]) -> Result<usize, no_std_io::Error> {
+                    $crate::structure::write_nex_structure(
+                        self,
+                        $crate::structure::DEFAULT_NEX_VERSION,
+                        dst,
+                    )
+                }
+
+                fn try_write_be(&self, _dst: &mut [u8]) -> Result<usize, no_std_io::Error> {
+                    Err(no_std_io::Error::InvalidWrite {
+                        message: "NEX structures are little endian",
+                    })
+                }
+            }
+        )*
+    };
+}
+
+pub(crate) use impl_endian_for_nex_structure;
+
+/// Implements EndianRead for a fixed layout type field by field, reading the fields given a
+/// reader such as [read_list] or [read_qbuffer] with it. Derived implementations decode nested
+/// Lists and Buffers with nex-rs, which allocates whatever length the request claims.
+macro_rules! impl_bounded_endian_read {
+    ($structure:ident { $($field:ident $(: $reader:path)?),* $(,)? }) => {
+        impl no_std_io::EndianRead for $structure {
+            fn try_read_le(bytes: &[u8]) -> Result<no_std_io::ReadOutput<Self>, no_std_io::Error> {
+                #[allow(unused_imports)]
+                use no_std_io::{Cursor as _, StreamReader as _};
+
+                let mut stream = no_std_io::StreamContainer::new(bytes);
+                let structure = Self {
+                    $($field: $crate::structure::impl_bounded_endian_read!(
+                        @field stream $($reader)?
+                    ),)*
+                };
+                Ok(no_std_io::ReadOutput::new(structure, stream.get_index()))
+            }
+
+            fn try_read_be(_bytes: &[u8]) -> Result<no_std_io::ReadOutput<Self>, no_std_io::Error> {
+                Err(no_std_io::Error::InvalidRead {
+                    message: "NEX structures are little endian",
+                })
+            }
+        }
+    };
+    (@field $stream:ident) => {
+        $stream.read_stream_le()?
+    };
+    (@field $stream:ident $reader:path) => {
+        $reader(&mut $stream)?
+    };
+}
+
+pub(crate) use impl_bounded_endian_read;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(count: u32, items: &[u32]) -> Vec<u8> {
+        let mut data = count.to_le_bytes().to_vec();
+        for item in items {
+            data.extend_from_slice(&item.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn reads_lists_within_the_limit() {
+        let data = list(2, &[1, 2]);
+        let mut stream = StreamContainer::new(data.as_slice());
+
+        let items = Vec::from(read_list::<u32>(&mut stream).unwrap());
+        assert_eq!(items, vec![1, 2]);
+        assert_eq!(stream.get_index(), data.len());
+    }
+
+    #[test]
+    fn rejects_lists_over_the_limit() {
+        let data = list(MAX_LIST_LENGTH + 1, &[]);
+        let mut stream = StreamContainer::new(data.as_slice());
+
+        assert!(read_list::<u32>(&mut stream).is_err());
+    }
+
+    #[test]
+    fn rejects_buffers_over_the_limit() {
+        let data = (MAX_BUFFER_LENGTH + 1).to_le_bytes();
+        assert!(read_buffer(&mut StreamContainer::new(data.as_slice())).is_err());
+
+        let data = (MAX_BUFFER_LENGTH as u16 + 1).to_le_bytes();
+        assert!(read_qbuffer(&mut StreamContainer::new(data.as_slice())).is_err());
+    }
+
+    #[test]
+    fn reads_slices_in_bounds() {
+        let data = [1, 2, 3, 4];
+        let mut stream = StreamContainer::new(&data[..]);
+        stream.set_index(1);
+
+        assert_eq!(read_slice(&mut stream, 2).unwrap(), &[2, 3]);
+        assert_eq!(stream.get_index(), 3);
+        assert!(read_slice(&mut stream, 2).is_err());
+        assert!(read_slice(&mut stream, usize::MAX).is_err());
+        assert_eq!(stream.get_index(), 3);
+    }
+
+    #[test]
+    fn checks_data_holder_lengths() {
+        assert!(check_data_holder_lengths(8, 4).is_ok());
+        assert!(check_data_holder_lengths(4, 4).is_err());
+        assert!(check_data_holder_lengths(3, u32::MAX).is_err());
+    }
+}
@@ -0,0 +1,355 @@
+use no_std_io::{StreamContainer, StreamReader};
+use std::fmt;
+use std::path::Path;
+
+const RMC_REQUEST_FLAG: u8 = 0x80;
+const RMC_RESPONSE_METHOD_FLAG: u32 = 0x8000;
+const EXTENDED_PROTOCOL_ID: u8 = 0x7F;
+
+/// An RMC request as the client sent it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedRequest {
+    pub protocol_id: u8,
+    pub method_id: u32,
+    pub call_id: u32,
+    pub parameters: Vec<u8>,
+}
+
+impl CapturedRequest {
+    pub fn new(protocol_id: u8, method_id: u32, call_id: u32, parameters: Vec<u8>) -> Self {
+        Self {
+            protocol_id,
+            method_id,
+            call_id,
+            parameters,
+        }
+    }
+
+    /// Encodes the request as an RMC payload.
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut body = vec![self.protocol_id | RMC_REQUEST_FLAG];
+        body.extend_from_slice(&self.call_id.to_le_bytes());
+        body.extend_from_slice(&self.method_id.to_le_bytes());
+        body.extend_from_slice(&self.parameters);
+        with_size_prefix(body)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CapturedResult {
+    Success(Vec<u8>),
+    /// The raw result code sent with an error response.
+    Error(u32),
+}
+
+/// An RMC response as the server sent it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedResponse {
+    pub protocol_id: u8,
+    /// Not sent with error responses, so `None` for them.
+    pub method_id: Option<u32>,
+    pub call_id: u32,
+    pub result: CapturedResult,
+}
+
+impl CapturedResponse {
+    pub fn success(protocol_id: u8, method_id: u32, call_id: u32, data: Vec<u8>) -> Self {
+        Self {
+            protocol_id,
+            method_id: Some(method_id),
+            call_id,
+            result: CapturedResult::Success(data),
+        }
+    }
+
+    pub fn error(protocol_id: u8, call_id: u32, error_code: u32) -> Self {
+        Self {
+            protocol_id,
+            method_id: None,
+            call_id,
+            result: CapturedResult::Error(error_code),
+        }
+    }
+
+    /// Encodes the response as an RMC payload.
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut body = vec![self.protocol_id];
+
+        match &self.result {
+            CapturedResult::Success(data) => {
+                let method_id = self.method_id.unwrap_or_default() | RMC_RESPONSE_METHOD_FLAG;
+                body.push(1);
+                body.extend_from_slice(&self.call_id.to_le_bytes());
+                body.extend_from_slice(&method_id.to_le_bytes());
+                body.extend_from_slice(data);
+            }
+            CapturedResult::Error(error_code) => {
+                body.push(0);
+                body.extend_from_slice(&error_code.to_le_bytes());
+                body.extend_from_slice(&self.call_id.to_le_bytes());
+            }
+        }
+
+        with_size_prefix(body)
+    }
+}
+
+fn with_size_prefix(body: Vec<u8>) -> Vec<u8> {
+    let mut payload = (body.len() as u32).to_le_bytes().to_vec();
+    payload.extend(body);
+    payload
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CapturedMessage {
+    Request(CapturedRequest),
+    Response(CapturedResponse),
+}
+
+impl CapturedMessage {
+    /// Decodes a reassembled, decrypted RMC payload, e.g. copied from Wireshark's PRUDP dissector.
+    pub fn from_payload(payload: &[u8]) -> Result<Self, &'static str> {
+        let mut stream = StreamContainer::new(payload);
+        let size = stream
+            .read_stream_le::<u32>()
+            .map_err(|_| "Can not read payload size")? as usize;
+
+        if size != payload.len() - 4 {
+            return Err("Payload size does not match its header");
+        }
+
+        let protocol_id = stream
+            .read_stream_le::<u8>()
+            .map_err(|_| "Can not read protocol id")?;
+
+        if protocol_id & !RMC_REQUEST_FLAG == EXTENDED_PROTOCOL_ID {
+            return Err("Extended protocol ids are not supported");
+        }
+
+        if protocol_id & RMC_REQUEST_FLAG != 0 {
+            let call_id = stream
+                .read_stream_le::<u32>()
+                .map_err(|_| "Can not read call id")?;
+            let method_id = stream
+                .read_stream_le::<u32>()
+                .map_err(|_| "Can not read method id")?;
+            let parameters = payload[stream.get_index()..].to_vec();
+
+            return Ok(Self::Request(CapturedRequest::new(
+                protocol_id & !RMC_REQUEST_FLAG,
+                method_id,
+                call_id,
+                parameters,
+            )));
+        }
+
+        let success = stream
+            .read_stream_le::<u8>()
+            .map_err(|_| "Can not read success flag")?;
+
+        let response = if success == 1 {
+            let call_id = stream
+                .read_stream_le::<u32>()
+                .map_err(|_| "Can not read call id")?;
+            let method_id = stream
+                .read_stream_le::<u32>()
+                .map_err(|_| "Can not read method id")?;
+            let data = payload[stream.get_index()..].to_vec();
+            CapturedResponse::success(
+                protocol_id,
+                method_id & !RMC_RESPONSE_METHOD_FLAG,
+                call_id,
+                data,
+            )
+        } else {
+            let error_code = stream
+                .read_stream_le::<u32>()
+                .map_err(|_| "Can not read error code")?;
+            let call_id = stream
+                .read_stream_le::<u32>()
+                .map_err(|_| "Can not read call id")?;
+            CapturedResponse::error(protocol_id, call_id, error_code)
+        };
+
+        Ok(Self::Response(response))
+    }
+
+    pub fn to_payload(&self) -> Vec<u8> {
+        match self {
+            Self::Request(request) => request.to_payload(),
+            Self::Response(response) => response.to_payload(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureParseError {
+    /// 1-based line number in the capture text.
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for CaptureParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CaptureParseError {}
+
+/// The RMC messages of a captured session, in the order they were sent.
+///
+/// The text form has one message per line: `>` followed by the hex of a client's RMC payload,
+/// or `<` followed by the hex of the server's. Blank lines and lines starting with `#` are ignored,
+/// and whitespace within the hex is allowed, so Wireshark's "Copy as Hex Stream" output of the
+/// decrypted PRUDP payload can be pasted directly.
+///
+/// ```text
+/// # SecureConnection RequestURLs
+/// > 11000000 8b 01000000 03000000 01000000 02000000
+/// < ...
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RmcCapture {
+    pub messages: Vec<CapturedMessage>,
+}
+
+impl RmcCapture {
+    pub fn new(messages: Vec<CapturedMessage>) -> Self {
+        Self { messages }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, CaptureParseError> {
+        let mut messages = vec![];
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let to_error = |message| CaptureParseError {
+                line: index + 1,
+                message,
+            };
+
+            let (expects_request, hex) = match (line.strip_prefix('>'), line.strip_prefix('<')) {
+                (Some(hex), _) => (true, hex),
+                (_, Some(hex)) => (false, hex),
+                _ => return Err(to_error("Lines must start with '>' or '<'")),
+            };
+            let payload = decode_hex(hex).map_err(to_error)?;
+            let message = CapturedMessage::from_payload(&payload).map_err(to_error)?;
+
+            let is_request = matches!(message, CapturedMessage::Request(_));
+            match (expects_request, is_request) {
+                (true, true) | (false, false) => messages.push(message),
+                (true, false) => return Err(to_error("Expected a request payload")),
+                (false, true) => return Err(to_error("Expected a response payload")),
+            }
+        }
+
+        Ok(Self { messages })
+    }
+
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(Self::parse(&text)?)
+    }
+
+    pub fn to_text(&self) -> String {
+        self.messages
+            .iter()
+            .map(|message| {
+                let direction = match message {
+                    CapturedMessage::Request(_) => '>',
+                    CapturedMessage::Response(_) => '<',
+                };
+                format!("{} {}\n", direction, encode_hex(&message.to_payload()))
+            })
+            .collect()
+    }
+
+    pub fn requests(&self) -> impl Iterator<Item = &CapturedRequest> {
+        self.messages.iter().filter_map(|message| match message {
+            CapturedMessage::Request(request) => Some(request),
+            CapturedMessage::Response(_) => None,
+        })
+    }
+
+    /// Returns the captured response to a request, matched by protocol and call id.
+    pub fn get_response(&self, request: &CapturedRequest) -> Option<&CapturedResponse> {
+        self.messages.iter().find_map(|message| match message {
+            CapturedMessage::Response(response)
+                if response.protocol_id == request.protocol_id
+                    && response.call_id == request.call_id =>
+            {
+                Some(response)
+            }
+            _ => None,
+        })
+    }
+}
+
+pub fn decode_hex(hex: &str) -> Result<Vec<u8>, &'static str> {
+    let digits = hex
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).ok_or("Invalid hex digit"))
+        .collect::<Result<Vec<u32>, _>>()?;
+
+    if digits.len() % 2 != 0 {
+        return Err("Hex has an odd number of digits");
+    }
+
+    Ok(digits
+        .chunks(2)
+        .map(|pair| (pair[0] << 4 | pair[1]) as u8)
+        .collect())
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture() -> RmcCapture {
+        RmcCapture {
+            messages: vec![
+                CapturedMessage::Request(CapturedRequest {
+                    protocol_id: 115,
+                    method_id: 8,
+                    call_id: 1,
+                    parameters: vec![1, 2, 3],
+                }),
+                CapturedMessage::Response(CapturedResponse::success(115, 8, 1, vec![4, 5])),
+            ],
+        }
+    }
+
+    #[test]
+    fn parses_its_own_text() {
+        let capture = capture();
+        assert_eq!(RmcCapture::parse(&capture.to_text()).unwrap(), capture);
+    }
+
+    #[test]
+    fn rejects_mismatched_directions() {
+        let text = capture().to_text().replacen('>', "<", 1);
+        assert_eq!(
+            RmcCapture::parse(&text).unwrap_err().message,
+            "Expected a response payload"
+        );
+    }
+
+    #[test]
+    fn rejects_multi_byte_directions() {
+        let error = RmcCapture::parse("→00000000").unwrap_err();
+        assert_eq!(error.line, 1);
+        assert_eq!(error.message, "Lines must start with '>' or '<'");
+    }
+}
@@ -0,0 +1,168 @@
+use super::capture::{CapturedRequest, CapturedResponse};
+use super::packet::build_request_packet;
+use super::replay::ReplayTarget;
+use async_trait::async_trait;
+use nex_rs::client::ClientConnection;
+use nex_rs::packet::PacketV1;
+use nex_rs::server::BaseServer;
+use std::cell::RefCell;
+
+/// The in-memory half of a test server. Responses passed to `send_success` and `send_error`
+/// are recorded here instead of being written to a PRUDP stream.
+///
+/// Embed it in the type under test and implement [Server](nex_rs::server::Server) with
+/// [impl_mock_server](crate::impl_mock_server).
+#[derive(Default)]
+pub struct MockServer {
+    base: BaseServer,
+    responses: RefCell<Vec<CapturedResponse>>,
+}
+
+impl MockServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_base(&self) -> &BaseServer {
+        &self.base
+    }
+
+    pub fn get_mut_base(&mut self) -> &mut BaseServer {
+        &mut self.base
+    }
+
+    pub fn record_success(&self, protocol_id: u8, method_id: u32, call_id: u32, data: Vec<u8>) {
+        self.responses.borrow_mut().push(CapturedResponse::success(
+            protocol_id,
+            method_id,
+            call_id,
+            data,
+        ));
+    }
+
+    /// Error responses don't carry a method id on the wire, so none is recorded.
+    pub fn record_error(&self, protocol_id: u8, call_id: u32, error_code: u32) {
+        self.responses
+            .borrow_mut()
+            .push(CapturedResponse::error(protocol_id, call_id, error_code));
+    }
+
+    pub fn get_responses(&self) -> Vec<CapturedResponse> {
+        self.responses.borrow().clone()
+    }
+
+    pub fn get_last_response(&self) -> Option<CapturedResponse> {
+        self.responses.borrow().last().cloned()
+    }
+
+    /// Returns and clears the recorded responses.
+    pub fn take_responses(&self) -> Vec<CapturedResponse> {
+        self.responses.take()
+    }
+}
+
+/// Implements [Server](nex_rs::server::Server) for a type embedding a [MockServer],
+/// recording responses in it.
+///
+/// ```ignore
+/// struct TestServer {
+///     mock: MockServer,
+///     client_contexts: ClientContextTable,
+/// }
+///
+/// impl_mock_server!(TestServer, mock);
+/// ```
+#[macro_export]
+macro_rules! impl_mock_server {
+    ($server:ty, $mock:ident) => {
+        #[$crate::testing::async_trait(?Send)]
+        impl nex_rs::server::Server for $server {
+            fn get_base(&self) -> &nex_rs::server::BaseServer {
+                self.$mock.get_base()
+            }
+
+            fn get_mut_base(&mut self) -> &mut nex_rs::server::BaseServer {
+                self.$mock.get_mut_base()
+            }
+
+            async fn send_success(
+                &self,
+                _client: &mut nex_rs::client::ClientConnection,
+                protocol_id: u8,
+                method_id: u32,
+                call_id: u32,
+                data: Vec<u8>,
+            ) -> Result<(), &'static str> {
+                self.$mock
+                    .record_success(protocol_id, method_id, call_id, data);
+                Ok(())
+            }
+
+            async fn send_error(
+                &self,
+                _client: &mut nex_rs::client::ClientConnection,
+                protocol_id: u8,
+                _method_id: u32,
+                call_id: u32,
+                error_code: u32,
+            ) -> Result<(), &'static str> {
+                self.$mock.record_error(protocol_id, call_id, error_code);
+                Ok(())
+            }
+        }
+    };
+}
+
+/// Routes a request to the protocol implementations of a test server,
+/// usually by matching the protocol id and calling that protocol's `handle_request`.
+#[async_trait(?Send)]
+pub trait MockDispatch {
+    fn get_mock_server(&self) -> &MockServer;
+
+    async fn dispatch_request(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> Result<(), &'static str>;
+}
+
+/// A test server together with the client its requests come from.
+pub struct MockHarness<S: MockDispatch> {
+    pub server: S,
+    pub client: ClientConnection,
+}
+
+impl<S: MockDispatch> MockHarness<S> {
+    pub fn new(server: S, client: ClientConnection) -> Self {
+        Self { server, client }
+    }
+
+    /// Dispatches a packet and returns the responses it produced.
+    pub async fn send(&mut self, packet: &PacketV1) -> Result<Vec<CapturedResponse>, &'static str> {
+        self.server.get_mock_server().take_responses();
+        self.server
+            .dispatch_request(&mut self.client, packet)
+            .await?;
+        Ok(self.server.get_mock_server().take_responses())
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: MockDispatch> ReplayTarget for MockHarness<S> {
+    async fn dispatch(
+        &mut self,
+        request: &CapturedRequest,
+    ) -> Result<Option<CapturedResponse>, &'static str> {
+        let packet = build_request_packet(
+            request.protocol_id,
+            request.method_id,
+            request.call_id,
+            request.parameters.clone(),
+        );
+        let responses = self.send(&packet).await?;
+
+        Ok(responses
+            .into_iter()
+            .find(|response| response.call_id == request.call_id))
+    }
+}
@@ -0,0 +1,14 @@
+mod capture;
+pub use capture::*;
+
+mod mock_server;
+pub use mock_server::*;
+
+mod packet;
+pub use packet::*;
+
+mod replay;
+pub use replay::*;
+
+#[doc(hidden)]
+pub use async_trait::async_trait;
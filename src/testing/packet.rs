@@ -0,0 +1,93 @@
+use crate::structure::{NexStructure, NexVersion};
+use nex_rs::client::{ClientConnection, ClientContext};
+use nex_rs::packet::{Packet, PacketV1};
+use nex_rs::rmc::RMCRequest;
+use no_std_io::{EndianWrite, Error};
+use std::cell::Cell;
+use std::net::{Ipv4Addr, SocketAddr};
+
+thread_local! {
+    static NEXT_CLIENT_ID: Cell<u32> = Cell::new(0);
+}
+
+/// Creates a client that is never connected to a socket.
+///
+/// Each client gets its own loopback address and port, so they have separate
+/// [ClientContext](crate::client_context::ClientContext)s even with the same connection id.
+pub fn fake_client(pid: u32, connection_id: u32) -> ClientConnection {
+    let client_id = NEXT_CLIENT_ID.with(|next_id| {
+        let client_id = next_id.get();
+        next_id.set(
+            client_id
+                .checked_add(1)
+                .expect("ran out of fake client addresses"),
+        );
+        client_id
+    });
+
+    let ip = Ipv4Addr::from(0x7F00_0000 | (client_id & 0x00FF_FFFF));
+    let port = (client_id >> 24) as u16 + 1;
+    let address = SocketAddr::from((ip, port));
+    let mut client = ClientConnection::new(address, ClientContext::default());
+    client.set_pid(pid);
+    client.set_connection_id(connection_id);
+    client
+}
+
+/// Builds a packet carrying an RMC request with already encoded parameters.
+pub fn build_request_packet(
+    protocol_id: u8,
+    method_id: impl Into<u32>,
+    call_id: u32,
+    parameters: Vec<u8>,
+) -> PacketV1 {
+    let mut packet = PacketV1::default();
+    packet.set_rmc_request(RMCRequest {
+        protocol_id,
+        method_id: method_id.into(),
+        call_id,
+        parameters,
+    });
+    packet
+}
+
+/// Encodes a *Request struct as RMC parameters.
+pub fn encode_parameters<T: EndianWrite>(request: &T) -> Result<Vec<u8>, Error> {
+    let mut parameters = vec![0; request.get_size()];
+    request.try_write_le(&mut parameters)?;
+    Ok(parameters)
+}
+
+/// Builds a packet from a *Request struct, e.g. a [ChangeMetasRequest](crate::datastore_usum::ChangeMetasRequest).
+pub fn build_typed_request<T: EndianWrite>(
+    protocol_id: u8,
+    method_id: impl Into<u32>,
+    call_id: u32,
+    request: &T,
+) -> Result<PacketV1, Error> {
+    let parameters = encode_parameters(request)?;
+    Ok(build_request_packet(
+        protocol_id,
+        method_id,
+        call_id,
+        parameters,
+    ))
+}
+
+/// Builds a packet from a version dependent structure parameter,
+/// e.g. a [CreateMatchmakeSessionParam](crate::matchmake_extension::CreateMatchmakeSessionParam).
+pub fn build_structure_request<T: NexStructure>(
+    protocol_id: u8,
+    method_id: impl Into<u32>,
+    call_id: u32,
+    structure: &T,
+    nex_version: NexVersion,
+) -> Result<PacketV1, Error> {
+    let parameters = structure.write_structure(nex_version)?;
+    Ok(build_request_packet(
+        protocol_id,
+        method_id,
+        call_id,
+        parameters,
+    ))
+}
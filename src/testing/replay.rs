@@ -0,0 +1,183 @@
+use super::capture::{encode_hex, CapturedRequest, CapturedResponse, CapturedResult, RmcCapture};
+use async_trait::async_trait;
+use std::fmt;
+
+/// Something captured requests can be dispatched to, usually a [MockHarness](super::MockHarness).
+#[async_trait(?Send)]
+pub trait ReplayTarget {
+    /// Dispatches the request, returning the response the handler sent, if any.
+    async fn dispatch(
+        &mut self,
+        request: &CapturedRequest,
+    ) -> Result<Option<CapturedResponse>, &'static str>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayMismatch {
+    /// The handler returned an error, e.g. because it couldn't decode the parameters.
+    DispatchFailed {
+        request: CapturedRequest,
+        message: &'static str,
+    },
+    /// The capture has a response, but the handler sent none.
+    MissingResponse {
+        request: CapturedRequest,
+        expected: CapturedResponse,
+    },
+    /// The handler sent a response the capture doesn't have.
+    UnexpectedResponse {
+        request: CapturedRequest,
+        actual: CapturedResponse,
+    },
+    ResponseDiffers {
+        request: CapturedRequest,
+        expected: CapturedResponse,
+        actual: CapturedResponse,
+    },
+}
+
+impl ReplayMismatch {
+    pub fn get_request(&self) -> &CapturedRequest {
+        match self {
+            Self::DispatchFailed { request, .. }
+            | Self::MissingResponse { request, .. }
+            | Self::UnexpectedResponse { request, .. }
+            | Self::ResponseDiffers { request, .. } => request,
+        }
+    }
+}
+
+fn describe_result(result: &CapturedResult) -> String {
+    match result {
+        CapturedResult::Success(data) => format!("success {}", encode_hex(data)),
+        CapturedResult::Error(error_code) => format!("error {:#010x}", error_code),
+    }
+}
+
+/// Returns the offset of the first differing byte, if any.
+fn first_difference(expected: &[u8], actual: &[u8]) -> Option<usize> {
+    expected
+        .iter()
+        .zip(actual)
+        .position(|(expected, actual)| expected != actual)
+        .or_else(|| (expected.len() != actual.len()).then_some(expected.len().min(actual.len())))
+}
+
+impl fmt::Display for ReplayMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let request = self.get_request();
+        write!(
+            f,
+            "protocol {:#04x} method {:#x} call {}: ",
+            request.protocol_id, request.method_id, request.call_id
+        )?;
+
+        match self {
+            Self::DispatchFailed { message, .. } => write!(f, "dispatch failed: {}", message),
+            Self::MissingResponse { expected, .. } => {
+                write!(
+                    f,
+                    "no response, expected {}",
+                    describe_result(&expected.result)
+                )
+            }
+            Self::UnexpectedResponse { actual, .. } => {
+                write!(f, "unexpected response {}", describe_result(&actual.result))
+            }
+            Self::ResponseDiffers {
+                expected, actual, ..
+            } => {
+                writeln!(f, "response differs")?;
+
+                if let (CapturedResult::Success(expected), CapturedResult::Success(actual)) =
+                    (&expected.result, &actual.result)
+                {
+                    if let Some(offset) = first_difference(expected, actual) {
+                        writeln!(f, "  first difference at byte {}", offset)?;
+                    }
+                }
+
+                writeln!(f, "  expected {}", describe_result(&expected.result))?;
+                write!(f, "  actual   {}", describe_result(&actual.result))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    pub replayed: usize,
+    pub mismatches: Vec<ReplayMismatch>,
+}
+
+impl ReplayReport {
+    pub fn is_success(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "replayed {} requests, {} mismatches",
+            self.replayed,
+            self.mismatches.len()
+        )?;
+
+        for mismatch in self.mismatches.iter() {
+            writeln!(f, "{}", mismatch)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Dispatches every captured request to `target` in order and diffs each response
+/// against the captured one.
+pub async fn replay_capture<T: ReplayTarget + ?Sized>(
+    target: &mut T,
+    capture: &RmcCapture,
+) -> ReplayReport {
+    let mut report = ReplayReport::default();
+
+    for request in capture.requests() {
+        report.replayed += 1;
+
+        let expected = capture.get_response(request).cloned();
+        let actual = match target.dispatch(request).await {
+            Ok(actual) => actual,
+            Err(message) => {
+                report.mismatches.push(ReplayMismatch::DispatchFailed {
+                    request: request.clone(),
+                    message,
+                });
+                continue;
+            }
+        };
+
+        let mismatch = match (expected, actual) {
+            (None, None) => None,
+            (Some(expected), None) => Some(ReplayMismatch::MissingResponse {
+                request: request.clone(),
+                expected,
+            }),
+            (None, Some(actual)) => Some(ReplayMismatch::UnexpectedResponse {
+                request: request.clone(),
+                actual,
+            }),
+            (Some(expected), Some(actual)) if expected != actual => {
+                Some(ReplayMismatch::ResponseDiffers {
+                    request: request.clone(),
+                    expected,
+                    actual,
+                })
+            }
+            (Some(_), Some(_)) => None,
+        };
+
+        report.mismatches.extend(mismatch);
+    }
+
+    report
+}
@@ -0,0 +1,196 @@
+use crate::any_data_holder::AnyDataHolder;
+use crate::client_context::ClientContextProvider;
+use crate::ticket_granting::{
+    Account, AccountStore, TicketGrantingMethod, TicketGrantingProtocol, AUTHENTICATION_PROTOCOL_ID,
+};
+use async_trait::async_trait;
+use nex_rs::client::ClientConnection;
+use nex_rs::nex_types::{NexString, ResultCode};
+use nex_rs::server::Server;
+use no_std_io::{EndianWrite, StreamContainer, StreamWriter};
+
+fn write_response<T: EndianWrite>(value: &T) -> Result<Vec<u8>, ResultCode> {
+    let mut stream = StreamContainer::new(vec![]);
+    stream
+        .write_stream_le(value)
+        .map_err(|_| ResultCode::Core_SystemError)?;
+    Ok(stream.into_raw())
+}
+
+/// Implemented by servers that want [TicketGrantingProtocol] backed by an [AccountStore].
+/// Accounts are looked up and checked for bans before the implementor builds the
+/// Kerberos tickets; GetPID and GetName are answered from the store directly.
+/// The guest account of [ClientContextProvider::get_guest_policy] is served instead of the store.
+#[async_trait(?Send)]
+pub trait AccountServer: Server + ClientContextProvider {
+    fn get_account_store(&self) -> &dyn AccountStore;
+
+    fn is_guest(&self, pid: u32) -> bool {
+        self.get_guest_policy()
+            .map(|policy| policy.is_guest(pid))
+            .unwrap_or(false)
+    }
+
+    /// Rejects TicketGranting calls the guest policy doesn't allow the account to make.
+    /// The dispatcher can't check these itself, since the caller has no session yet.
+    fn check_guest_call(
+        &self,
+        account: &Account,
+        method: TicketGrantingMethod,
+    ) -> Result<(), ResultCode> {
+        match self.get_guest_policy() {
+            Some(policy) => {
+                policy.check_call(account.pid, AUTHENTICATION_PROTOCOL_ID, method.into())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn find_account_by_username(&self, username: &str) -> Result<Option<Account>, ResultCode> {
+        if let Some(policy) = self.get_guest_policy() {
+            let guest_account = policy.get_guest_account();
+
+            if guest_account.username == username {
+                return Ok(Some(guest_account.clone()));
+            }
+        }
+
+        self.get_account_store().get_account_by_username(username)
+    }
+
+    fn find_account_by_pid(&self, pid: u32) -> Result<Option<Account>, ResultCode> {
+        if let Some(policy) = self.get_guest_policy() {
+            let guest_account = policy.get_guest_account();
+
+            if guest_account.pid == pid {
+                return Ok(Some(guest_account.clone()));
+            }
+        }
+
+        self.get_account_store().get_account_by_pid(pid)
+    }
+
+    /// Builds the Login and LoginEx response for an account allowed to log in.
+    async fn create_login_response(
+        &self,
+        client: &mut ClientConnection,
+        account: &Account,
+    ) -> Result<Vec<u8>, ResultCode>;
+
+    /// Builds the RequestTicket response for a ticket from `user` to `server`.
+    async fn create_ticket_response(
+        &self,
+        client: &mut ClientConnection,
+        user: &Account,
+        server: &Account,
+    ) -> Result<Vec<u8>, ResultCode>;
+
+    /// Checks the LoginEx authentication data. Accepts everything by default.
+    async fn validate_login_data(
+        &self,
+        _client: &mut ClientConnection,
+        _account: &Account,
+        _login_data: &AnyDataHolder,
+    ) -> Result<(), ResultCode> {
+        Ok(())
+    }
+
+    fn get_active_account_by_username(&self, username: &str) -> Result<Account, ResultCode> {
+        let account = self
+            .find_account_by_username(username)?
+            .ok_or(ResultCode::RendezVous_InvalidUsername)?;
+
+        if account.banned {
+            return Err(ResultCode::RendezVous_AccountDisabled);
+        }
+
+        Ok(account)
+    }
+
+    fn get_active_account_by_pid(&self, pid: u32) -> Result<Account, ResultCode> {
+        let account = self
+            .find_account_by_pid(pid)?
+            .ok_or(ResultCode::RendezVous_InvalidPID)?;
+
+        if account.banned {
+            return Err(ResultCode::RendezVous_AccountDisabled);
+        }
+
+        Ok(account)
+    }
+}
+
+#[async_trait(?Send)]
+impl<T: AccountServer> TicketGrantingProtocol for T {
+    async fn login(
+        &self,
+        client: &mut ClientConnection,
+        username: String,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let account = self.get_active_account_by_username(&username)?;
+        self.check_guest_call(&account, TicketGrantingMethod::Login)?;
+        self.create_login_response(client, &account).await
+    }
+
+    async fn login_ex(
+        &self,
+        client: &mut ClientConnection,
+        username: String,
+        ticket_granting_info: AnyDataHolder,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let account = self.get_active_account_by_username(&username)?;
+        self.check_guest_call(&account, TicketGrantingMethod::LoginEx)?;
+        self.validate_login_data(client, &account, &ticket_granting_info)
+            .await?;
+        self.set_title_from_data_holder(client, &ticket_granting_info)?;
+        self.create_login_response(client, &account).await
+    }
+
+    async fn request_ticket(
+        &self,
+        client: &mut ClientConnection,
+        user_pid: u32,
+        server_pid: u32,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let user = self.get_active_account_by_pid(user_pid)?;
+        self.check_guest_call(&user, TicketGrantingMethod::RequestTicket)?;
+
+        if let Some(policy) = self.get_guest_policy() {
+            if policy.is_guest(user.pid) && !policy.is_ticket_target_allowed(server_pid) {
+                return Err(ResultCode::RendezVous_PermissionDenied);
+            }
+        }
+
+        let server = self.get_active_account_by_pid(server_pid)?;
+        self.create_ticket_response(client, &user, &server).await
+    }
+
+    async fn get_pid(
+        &self,
+        _client: &mut ClientConnection,
+        username: String,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let account = self
+            .find_account_by_username(&username)?
+            .ok_or(ResultCode::RendezVous_InvalidUsername)?;
+        write_response(&account.pid)
+    }
+
+    async fn get_name(
+        &self,
+        _client: &mut ClientConnection,
+        user_pid: u32,
+    ) -> Result<Vec<u8>, ResultCode> {
+        let account = self
+            .find_account_by_pid(user_pid)?
+            .ok_or(ResultCode::RendezVous_InvalidPID)?;
+        write_response(&NexString::from(account.username))
+    }
+
+    async fn login_with_param(
+        &self,
+        _client: &mut ClientConnection,
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+}
@@ -0,0 +1,340 @@
+use nex_rs::nex_types::ResultCode;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+pub const KERBEROS_KEY_SIZE: usize = 16;
+
+/// Derives a Kerberos key by hashing a NEX password `65000 + pid % 1024` times with MD5.
+pub fn derive_kerberos_key(pid: u32, password: &str) -> [u8; KERBEROS_KEY_SIZE] {
+    let mut key = md5::compute(password.as_bytes()).0;
+
+    for _ in 1..(65000 + pid % 1024) {
+        key = md5::compute(key).0;
+    }
+
+    key
+}
+
+/// Accounts only keep the Kerberos key derived from their password, never the password itself.
+/// The key still lets anyone holding it log in, so stores should be protected like passwords.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub pid: u32,
+    pub username: String,
+    pub kerberos_key: [u8; KERBEROS_KEY_SIZE],
+    pub banned: bool,
+}
+
+impl Account {
+    pub fn new(pid: u32, username: &str, password: &str) -> Self {
+        Self {
+            pid,
+            username: username.to_string(),
+            kerberos_key: derive_kerberos_key(pid, password),
+            banned: false,
+        }
+    }
+
+    pub fn get_kerberos_key(&self) -> [u8; KERBEROS_KEY_SIZE] {
+        self.kerberos_key
+    }
+}
+
+/// Account lookups used by the ticket granting server.
+/// Errors from the underlying storage are reported as `Core_SystemError`.
+pub trait AccountStore {
+    fn get_account_by_username(&self, username: &str) -> Result<Option<Account>, ResultCode>;
+    fn get_account_by_pid(&self, pid: u32) -> Result<Option<Account>, ResultCode>;
+
+    fn get_kerberos_key(&self, pid: u32) -> Result<Option<[u8; KERBEROS_KEY_SIZE]>, ResultCode> {
+        Ok(self
+            .get_account_by_pid(pid)?
+            .map(|account| account.get_kerberos_key()))
+    }
+
+    fn is_banned(&self, pid: u32) -> Result<bool, ResultCode> {
+        Ok(self
+            .get_account_by_pid(pid)?
+            .map(|account| account.banned)
+            .unwrap_or(false))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryAccountStore {
+    accounts: RefCell<HashMap<u32, Account>>,
+}
+
+impl InMemoryAccountStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an account, replacing any account with the same pid.
+    /// Usernames must be unique.
+    pub fn insert_account(&self, account: Account) -> Result<(), ResultCode> {
+        let mut accounts = self.accounts.borrow_mut();
+        let username_taken = accounts
+            .values()
+            .any(|existing| existing.username == account.username && existing.pid != account.pid);
+
+        if username_taken {
+            return Err(ResultCode::RendezVous_UsernameAlreadyExists);
+        }
+
+        accounts.insert(account.pid, account);
+        Ok(())
+    }
+
+    pub fn remove_account(&self, pid: u32) -> Option<Account> {
+        self.accounts.borrow_mut().remove(&pid)
+    }
+
+    pub fn set_banned(&self, pid: u32, banned: bool) -> Result<(), ResultCode> {
+        let mut accounts = self.accounts.borrow_mut();
+        let account = accounts
+            .get_mut(&pid)
+            .ok_or(ResultCode::RendezVous_InvalidPID)?;
+        account.banned = banned;
+        Ok(())
+    }
+}
+
+impl AccountStore for InMemoryAccountStore {
+    fn get_account_by_username(&self, username: &str) -> Result<Option<Account>, ResultCode> {
+        Ok(self
+            .accounts
+            .borrow()
+            .values()
+            .find(|account| account.username == username)
+            .cloned())
+    }
+
+    fn get_account_by_pid(&self, pid: u32) -> Result<Option<Account>, ResultCode> {
+        Ok(self.accounts.borrow().get(&pid).cloned())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteAccountStore;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::{Account, AccountStore};
+    use nex_rs::nex_types::ResultCode;
+    use rusqlite::{params, Connection, OptionalExtension, Row};
+    use std::path::Path;
+
+    /// An [AccountStore] backed by an `accounts` table in a SQLite database.
+    pub struct SqliteAccountStore {
+        connection: Connection,
+    }
+
+    impl SqliteAccountStore {
+        /// Opens the database at `path`, creating the accounts table if needed.
+        pub fn open(path: impl AsRef<Path>) -> Result<Self, ResultCode> {
+            let connection = Connection::open(path).map_err(|_| ResultCode::Core_SystemError)?;
+            Self::from_connection(connection)
+        }
+
+        pub fn open_in_memory() -> Result<Self, ResultCode> {
+            let connection =
+                Connection::open_in_memory().map_err(|_| ResultCode::Core_SystemError)?;
+            Self::from_connection(connection)
+        }
+
+        pub fn from_connection(connection: Connection) -> Result<Self, ResultCode> {
+            connection
+                .execute(
+                    "CREATE TABLE IF NOT EXISTS accounts (
+                        pid INTEGER PRIMARY KEY,
+                        username TEXT NOT NULL UNIQUE,
+                        kerberos_key BLOB NOT NULL,
+                        banned INTEGER NOT NULL DEFAULT 0
+                    )",
+                    [],
+                )
+                .map_err(|_| ResultCode::Core_SystemError)?;
+
+            Ok(Self { connection })
+        }
+
+        /// Adds an account, replacing any account with the same pid.
+        /// Usernames must be unique.
+        pub fn insert_account(&self, account: &Account) -> Result<(), ResultCode> {
+            self.connection
+                .execute(
+                    "INSERT INTO accounts (pid, username, kerberos_key, banned)
+                    VALUES (?1, ?2, ?3, ?4)
+                    ON CONFLICT(pid) DO UPDATE SET
+                        username = excluded.username,
+                        kerberos_key = excluded.kerberos_key,
+                        banned = excluded.banned",
+                    params![
+                        account.pid,
+                        account.username,
+                        account.kerberos_key,
+                        account.banned
+                    ],
+                )
+                .map_err(|error| match error {
+                    rusqlite::Error::SqliteFailure(failure, _)
+                        if failure.code == rusqlite::ErrorCode::ConstraintViolation =>
+                    {
+                        ResultCode::RendezVous_UsernameAlreadyExists
+                    }
+                    _ => ResultCode::Core_SystemError,
+                })?;
+            Ok(())
+        }
+
+        pub fn remove_account(&self, pid: u32) -> Result<(), ResultCode> {
+            self.connection
+                .execute("DELETE FROM accounts WHERE pid = ?1", params![pid])
+                .map_err(|_| ResultCode::Core_SystemError)?;
+            Ok(())
+        }
+
+        pub fn set_banned(&self, pid: u32, banned: bool) -> Result<(), ResultCode> {
+            let updated = self
+                .connection
+                .execute(
+                    "UPDATE accounts SET banned = ?1 WHERE pid = ?2",
+                    params![banned, pid],
+                )
+                .map_err(|_| ResultCode::Core_SystemError)?;
+
+            if updated == 0 {
+                return Err(ResultCode::RendezVous_InvalidPID);
+            }
+
+            Ok(())
+        }
+
+        fn read_account(row: &Row) -> rusqlite::Result<Account> {
+            Ok(Account {
+                pid: row.get(0)?,
+                username: row.get(1)?,
+                kerberos_key: row.get(2)?,
+                banned: row.get(3)?,
+            })
+        }
+
+        fn query_account(
+            &self,
+            condition: &str,
+            param: &dyn rusqlite::ToSql,
+        ) -> Result<Option<Account>, ResultCode> {
+            self.connection
+                .query_row(
+                    &format!(
+                        "SELECT pid, username, kerberos_key, banned FROM accounts WHERE {}",
+                        condition
+                    ),
+                    [param],
+                    Self::read_account,
+                )
+                .optional()
+                .map_err(|_| ResultCode::Core_SystemError)
+        }
+    }
+
+    impl AccountStore for SqliteAccountStore {
+        fn get_account_by_username(&self, username: &str) -> Result<Option<Account>, ResultCode> {
+            self.query_account("username = ?1", &username)
+        }
+
+        fn get_account_by_pid(&self, pid: u32) -> Result<Option<Account>, ResultCode> {
+            self.query_account("pid = ?1", &pid)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_store(store: &dyn AccountStore, insert: impl Fn(Account) -> Result<(), ResultCode>) {
+        insert(Account::new(1800000000, "1800000000", "password")).unwrap();
+        insert(Account::new(1800000001, "1800000001", "password")).unwrap();
+
+        let account = store
+            .get_account_by_username("1800000000")
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.pid, 1800000000);
+        assert_eq!(
+            account.get_kerberos_key(),
+            derive_kerberos_key(1800000000, "password")
+        );
+        assert_eq!(
+            store.get_account_by_pid(1800000001).unwrap(),
+            Some(Account::new(1800000001, "1800000001", "password"))
+        );
+        assert_eq!(store.get_account_by_pid(1).unwrap(), None);
+
+        assert_eq!(
+            insert(Account::new(1800000002, "1800000000", "password")),
+            Err(ResultCode::RendezVous_UsernameAlreadyExists)
+        );
+
+        insert(Account::new(1800000000, "renamed", "new password")).unwrap();
+        assert_eq!(store.get_account_by_username("1800000000").unwrap(), None);
+        assert_eq!(
+            store.get_kerberos_key(1800000000).unwrap(),
+            Some(derive_kerberos_key(1800000000, "new password"))
+        );
+        assert_eq!(
+            store
+                .get_account_by_username("1800000001")
+                .unwrap()
+                .map(|account| account.pid),
+            Some(1800000001)
+        );
+    }
+
+    #[test]
+    fn derives_kerberos_keys_per_pid() {
+        assert_ne!(
+            derive_kerberos_key(1800000000, "password"),
+            derive_kerberos_key(1800000001, "password")
+        );
+        assert_ne!(
+            derive_kerberos_key(1800000000, "password"),
+            md5::compute("password").0
+        );
+    }
+
+    #[test]
+    fn in_memory_store() {
+        let store = InMemoryAccountStore::new();
+        check_store(&store, |account| store.insert_account(account));
+
+        store.set_banned(1800000001, true).unwrap();
+        assert_eq!(store.is_banned(1800000001), Ok(true));
+        assert_eq!(
+            store.set_banned(1, true),
+            Err(ResultCode::RendezVous_InvalidPID)
+        );
+
+        store.remove_account(1800000001);
+        assert_eq!(store.get_account_by_pid(1800000001).unwrap(), None);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_store() {
+        let store = SqliteAccountStore::open_in_memory().unwrap();
+        check_store(&store, |account| store.insert_account(&account));
+
+        store.set_banned(1800000001, true).unwrap();
+        assert_eq!(store.is_banned(1800000001), Ok(true));
+        assert_eq!(
+            store.set_banned(1, true),
+            Err(ResultCode::RendezVous_InvalidPID)
+        );
+
+        store.remove_account(1800000001).unwrap();
+        assert_eq!(store.get_account_by_pid(1800000001).unwrap(), None);
+    }
+}
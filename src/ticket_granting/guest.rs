@@ -0,0 +1,137 @@
+use crate::secure_connection::SECURE_CONNECTION_PROTOCOL_ID;
+use crate::ticket_granting::{Account, AUTHENTICATION_PROTOCOL_ID};
+use nex_rs::nex_types::ResultCode;
+
+pub const GUEST_USERNAME: &str = "guest";
+pub const GUEST_PID: u32 = 100;
+pub const GUEST_PASSWORD: &str = "MMQea3n!fsik";
+/// The PID NEX servers conventionally give the secure server's account.
+pub const DEFAULT_SECURE_SERVER_PID: u32 = 2;
+
+/// Decides what a client logged in as the guest account may do.
+pub trait GuestPolicy {
+    fn get_guest_account(&self) -> &Account;
+    fn is_ticket_target_allowed(&self, server_pid: u32) -> bool;
+    fn is_call_allowed(&self, protocol_id: u8, method_id: u32) -> bool;
+
+    fn is_guest(&self, pid: u32) -> bool {
+        pid == self.get_guest_account().pid
+    }
+
+    /// Rejects calls the guest account isn't allowed to make. Other accounts are always allowed.
+    fn check_call(&self, pid: u32, protocol_id: u8, method_id: u32) -> Result<(), ResultCode> {
+        if self.is_guest(pid) && !self.is_call_allowed(protocol_id, method_id) {
+            return Err(ResultCode::RendezVous_PermissionDenied);
+        }
+
+        Ok(())
+    }
+}
+
+/// A [GuestPolicy] allowing guests to request tickets for a fixed set of servers
+/// and to call a fixed set of protocols.
+/// Methods are matched as (protocol id, method id). A denied method is rejected even if its
+/// protocol is allowed, and an allowed method is accepted even if its protocol isn't.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestConfig {
+    pub account: Account,
+    pub ticket_server_pids: Vec<u32>,
+    pub protocol_ids: Vec<u8>,
+    pub allowed_methods: Vec<(u8, u32)>,
+    pub denied_methods: Vec<(u8, u32)>,
+}
+
+impl Default for GuestConfig {
+    /// Uses the well known guest credentials, only allowing tickets for the secure server
+    /// and calls to the authentication and secure connection protocols.
+    fn default() -> Self {
+        Self {
+            account: Account::new(GUEST_PID, GUEST_USERNAME, GUEST_PASSWORD),
+            ticket_server_pids: vec![DEFAULT_SECURE_SERVER_PID],
+            protocol_ids: vec![AUTHENTICATION_PROTOCOL_ID, SECURE_CONNECTION_PROTOCOL_ID],
+            allowed_methods: vec![],
+            denied_methods: vec![],
+        }
+    }
+}
+
+impl GuestConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl GuestPolicy for GuestConfig {
+    fn get_guest_account(&self) -> &Account {
+        &self.account
+    }
+
+    fn is_ticket_target_allowed(&self, server_pid: u32) -> bool {
+        self.ticket_server_pids.contains(&server_pid)
+    }
+
+    fn is_call_allowed(&self, protocol_id: u8, method_id: u32) -> bool {
+        let method = (protocol_id, method_id);
+
+        if self.denied_methods.contains(&method) {
+            return false;
+        }
+
+        self.allowed_methods.contains(&method) || self.protocol_ids.contains(&protocol_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastore_usum::{DataStoreMethod, DATASTORE_PROTOCOL_ID};
+    use crate::ticket_granting::TicketGrantingMethod;
+
+    #[test]
+    fn rejects_denied_methods_of_allowed_protocols() {
+        let mut config = GuestConfig::new();
+        config.denied_methods.push((
+            AUTHENTICATION_PROTOCOL_ID,
+            TicketGrantingMethod::GetName.into(),
+        ));
+
+        assert_eq!(
+            config.check_call(
+                GUEST_PID,
+                AUTHENTICATION_PROTOCOL_ID,
+                TicketGrantingMethod::GetName.into()
+            ),
+            Err(ResultCode::RendezVous_PermissionDenied)
+        );
+        assert_eq!(
+            config.check_call(
+                GUEST_PID,
+                AUTHENTICATION_PROTOCOL_ID,
+                TicketGrantingMethod::Login.into()
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            config.check_call(
+                1800000000,
+                AUTHENTICATION_PROTOCOL_ID,
+                TicketGrantingMethod::GetName.into()
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn allows_listed_methods_of_other_protocols() {
+        let mut config = GuestConfig::new();
+        config
+            .allowed_methods
+            .push((DATASTORE_PROTOCOL_ID, DataStoreMethod::GetMetas.into()));
+
+        assert!(config.is_call_allowed(DATASTORE_PROTOCOL_ID, DataStoreMethod::GetMetas.into()));
+        assert!(!config.is_call_allowed(
+            DATASTORE_PROTOCOL_ID,
+            DataStoreMethod::PostMetaBinary.into()
+        ));
+    }
+}
@@ -0,0 +1,11 @@
+mod account_server;
+pub use account_server::*;
+
+mod account_store;
+pub use account_store::*;
+
+mod guest;
+pub use guest::*;
+
+mod protocol;
+pub use protocol::*;
@@ -0,0 +1,329 @@
+use crate::any_data_holder::AnyDataHolder;
+use crate::client_context::ClientContextProvider;
+use crate::middleware::{HandlerResult, RequestPipeline};
+#[cfg(feature = "tracing")]
+use crate::request_trace::request_span;
+use crate::request_trace::{trace_parameters, Redacted};
+use crate::structure::{
+    impl_endian_for_nex_structure, read_structure_content, write_field, write_structure_content,
+    Data, NexStructure, NexVersion,
+};
+use async_trait::async_trait;
+use nex_rs::client::ClientConnection;
+use nex_rs::nex_types::{NexString, ResultCode};
+use nex_rs::packet::{Packet, PacketV1};
+use nex_rs::server::Server;
+use no_std_io::{Error, StreamContainer, StreamReader};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::fmt;
+
+pub const AUTHENTICATION_PROTOCOL_ID: u8 = 0xA;
+
+#[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive, IntoPrimitive)]
+#[repr(u32)]
+pub enum TicketGrantingMethod {
+    Login = 0x1,
+    LoginEx = 0x2,
+    RequestTicket = 0x3,
+    GetPID = 0x4,
+    GetName = 0x5,
+    LoginWithParam = 0x6,
+}
+
+#[derive(Default)]
+pub struct AuthenticationInfo {
+    pub token: NexString,
+    pub ngs_version: u32,
+    pub token_type: u8,
+    pub server_version: u32,
+}
+
+impl fmt::Debug for AuthenticationInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthenticationInfo")
+            .field("token", &Redacted)
+            .field("ngs_version", &self.ngs_version)
+            .field("token_type", &self.token_type)
+            .field("server_version", &self.server_version)
+            .finish()
+    }
+}
+
+impl AuthenticationInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl NexStructure for AuthenticationInfo {
+    fn read_structure(
+        stream: &mut StreamContainer<&[u8]>,
+        nex_version: NexVersion,
+    ) -> Result<Self, Error> {
+        Data::read_structure(stream, nex_version)?;
+        read_structure_content(stream, nex_version, |stream| {
+            Ok(Self {
+                token: stream.read_stream_le()?,
+                ngs_version: stream.read_stream_le()?,
+                token_type: stream.read_stream_le()?,
+                server_version: stream.read_stream_le()?,
+            })
+        })
+    }
+
+    fn write_structure(&self, nex_version: NexVersion) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        write_field(&mut content, &self.token)?;
+        write_field(&mut content, &self.ngs_version)?;
+        write_field(&mut content, &self.token_type)?;
+        write_field(&mut content, &self.server_version)?;
+
+        let mut data = Data {}.write_structure(nex_version)?;
+        data.extend(write_structure_content(
+            Self::STRUCTURE_VERSION,
+            nex_version,
+            content,
+        )?);
+        Ok(data)
+    }
+}
+
+impl_endian_for_nex_structure!(AuthenticationInfo);
+
+#[async_trait(?Send)]
+pub trait TicketGrantingProtocol: Server + ClientContextProvider {
+    async fn login(
+        &self,
+        client: &mut ClientConnection,
+        username: String,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn login_ex(
+        &self,
+        client: &mut ClientConnection,
+        username: String,
+        ticket_granting_info: AnyDataHolder,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn request_ticket(
+        &self,
+        client: &mut ClientConnection,
+        user_pid: u32,
+        server_pid: u32,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn get_pid(
+        &self,
+        client: &mut ClientConnection,
+        username: String,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn get_name(
+        &self,
+        client: &mut ClientConnection,
+        user_pid: u32,
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn login_with_param(&self, client: &mut ClientConnection) -> Result<Vec<u8>, ResultCode>;
+
+    /// Dispatches a request to its method's handler.
+    async fn handle_request(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> Result<(), &'static str> {
+        let rmc_request = packet.get_rmc_request();
+        let method = TicketGrantingMethod::try_from(rmc_request.method_id);
+
+        #[cfg(feature = "tracing")]
+        let span = request_span(
+            "TicketGranting",
+            method.as_ref().ok(),
+            rmc_request.method_id,
+            rmc_request.call_id,
+            client.get_pid(),
+        );
+
+        let dispatch = async move {
+            let request = match self.begin_request(client, packet, false) {
+                Ok(request) => request,
+                Err(error_code) => {
+                    return self.send_response(client, packet, Err(error_code)).await
+                }
+            };
+
+            let result = match method {
+                Ok(TicketGrantingMethod::Login) => self.handle_login(client, packet).await,
+                Ok(TicketGrantingMethod::LoginEx) => self.handle_login_ex(client, packet).await,
+                Ok(TicketGrantingMethod::RequestTicket) => {
+                    self.handle_request_ticket(client, packet).await
+                }
+                Ok(TicketGrantingMethod::GetPID) => self.handle_get_pid(client, packet).await,
+                Ok(TicketGrantingMethod::GetName) => self.handle_get_name(client, packet).await,
+                Ok(TicketGrantingMethod::LoginWithParam) => {
+                    self.handle_login_with_param(client, packet).await
+                }
+                Err(_) => self.handle_unknown_method(client, packet).await,
+            };
+
+            self.finish_request(client, request, result).await
+        };
+
+        #[cfg(feature = "tracing")]
+        let dispatch = tracing::Instrument::instrument(dispatch, span);
+
+        dispatch.await
+    }
+
+    /// Called for method ids without a handler, e.g. to prototype undocumented methods.
+    /// Responds with `Core_NotImplemented` by default.
+    async fn handle_unknown(
+        &self,
+        _client: &mut ClientConnection,
+        _method_id: u32,
+        _parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn handle_unknown_method(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+
+        let result = self
+            .handle_unknown(client, request.method_id, parameters)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_login(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let rmc_request = packet.get_rmc_request();
+        let parameters = rmc_request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let username: String = parameters_stream
+            .read_stream_le::<NexString>()
+            .map_err(|_| "Can not read username")?
+            .into();
+
+        if username.trim().is_empty() {
+            return Err("Failed to read username");
+        }
+
+        trace_parameters!(username);
+        let result = self.login(client, username).await;
+        Ok(result)
+    }
+
+    async fn handle_login_ex(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let rmc_request = packet.get_rmc_request();
+        let parameters = rmc_request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let username: String = parameters_stream
+            .read_stream_le::<NexString>()
+            .map_err(|_| "Can not read username")?
+            .into();
+
+        if username.trim().is_empty() {
+            return Err("Failed to read username");
+        }
+
+        let nex_version = self.get_title_profile(client).nex_version;
+        let ticket_granting_info = self
+            .get_client_context_table()
+            .get_data_holders()
+            .read(&mut parameters_stream, nex_version)
+            .map_err(|_| "Can not read data holder")?;
+
+        trace_parameters!(username, ticket_granting_info);
+        let result = self.login_ex(client, username, ticket_granting_info).await;
+        Ok(result)
+    }
+
+    async fn handle_request_ticket(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let rmc_request = packet.get_rmc_request();
+        let parameters = rmc_request.parameters.as_slice();
+        if parameters.len() != 8 {
+            return Err("[TicketGrantingProtocol::request_ticket] Parameters length not 8");
+        }
+
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let user_pid: u32 = parameters_stream
+            .read_stream_le()
+            .map_err(|_| "[TicketGrantingProtocol::request_ticket] Failed to read user pid")?;
+        let server_pid: u32 = parameters_stream
+            .read_stream_le()
+            .map_err(|_| "[TicketGrantingProtocol::request_ticket] Failed to read server pid")?;
+
+        trace_parameters!(user_pid, server_pid);
+        let result = self.request_ticket(client, user_pid, server_pid).await;
+        Ok(result)
+    }
+
+    async fn handle_get_pid(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let rmc_request = packet.get_rmc_request();
+        let parameters = rmc_request.parameters.as_slice();
+        let mut parameters_stream = StreamContainer::new(parameters);
+        let username: String = parameters_stream
+            .read_stream_le::<NexString>()
+            .map_err(|_| "Can not read username")?
+            .into();
+
+        if username.trim().is_empty() {
+            return Err("[TicketGrantingProtocol::get_pid] Failed to read username");
+        }
+
+        trace_parameters!(username);
+        let result = self.get_pid(client, username).await;
+        Ok(result)
+    }
+
+    async fn handle_login_with_param(
+        &self,
+        client: &mut ClientConnection,
+        _packet: &PacketV1,
+    ) -> HandlerResult {
+        let result = self.login_with_param(client).await;
+        Ok(result)
+    }
+
+    async fn handle_get_name(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let rmc_request = packet.get_rmc_request();
+        let parameters = rmc_request.parameters.as_slice();
+
+        if parameters.len() != 4 {
+            return Err("[TicketGrantingProtocol::get_name] Parameters length not 4");
+        }
+
+        let mut parameters_stream = StreamContainer::new(parameters);
+
+        let user_pid: u32 = parameters_stream
+            .read_stream_le()
+            .map_err(|_| "[TicketGrantingProtocol::get_name] Failed to read user PID")?;
+
+        trace_parameters!(user_pid);
+        let result = self.get_name(client, user_pid).await;
+        Ok(result)
+    }
+}
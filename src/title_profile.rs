@@ -0,0 +1,93 @@
+use crate::structure::{NexVersion, DEFAULT_NEX_VERSION};
+use std::collections::HashMap;
+
+pub const POKEMON_ULTRA_SUN_TITLE_ID: u64 = 0x00040000001B5000;
+pub const POKEMON_ULTRA_MOON_TITLE_ID: u64 = 0x00040000001B5100;
+
+/// The NEX library versions a title was built with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TitleProfile {
+    pub name: String,
+    pub title_ids: Vec<u64>,
+    pub nex_version: NexVersion,
+    pub datastore_version: NexVersion,
+    pub match_making_version: NexVersion,
+}
+
+impl Default for TitleProfile {
+    fn default() -> Self {
+        Self::new("Default", vec![], DEFAULT_NEX_VERSION)
+    }
+}
+
+impl TitleProfile {
+    /// Creates a profile where every protocol uses the same NEX version.
+    pub fn new(name: &str, title_ids: Vec<u64>, nex_version: NexVersion) -> Self {
+        Self {
+            name: name.to_string(),
+            title_ids,
+            nex_version,
+            datastore_version: nex_version,
+            match_making_version: nex_version,
+        }
+    }
+
+    pub fn usum() -> Self {
+        Self::new(
+            "Pokemon Ultra Sun and Ultra Moon",
+            vec![POKEMON_ULTRA_SUN_TITLE_ID, POKEMON_ULTRA_MOON_TITLE_ID],
+            DEFAULT_NEX_VERSION,
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TitleProfileRegistry {
+    default_profile: TitleProfile,
+    profiles: HashMap<u64, TitleProfile>,
+}
+
+impl Default for TitleProfileRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty(TitleProfile::default());
+        registry.register(TitleProfile::usum());
+        registry
+    }
+}
+
+impl TitleProfileRegistry {
+    /// Creates a registry with the built-in profiles.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry without built-in profiles.
+    pub fn empty(default_profile: TitleProfile) -> Self {
+        Self {
+            default_profile,
+            profiles: HashMap::new(),
+        }
+    }
+
+    /// Registers a profile for each of its title ids, replacing existing ones.
+    pub fn register(&mut self, profile: TitleProfile) {
+        for title_id in profile.title_ids.iter() {
+            self.profiles.insert(*title_id, profile.clone());
+        }
+    }
+
+    pub fn set_default_profile(&mut self, profile: TitleProfile) {
+        self.default_profile = profile;
+    }
+
+    pub fn get_default_profile(&self) -> &TitleProfile {
+        &self.default_profile
+    }
+
+    /// Returns the title's profile, or the default profile for unknown titles.
+    pub fn get_profile(&self, title_id: u64) -> &TitleProfile {
+        self.profiles
+            .get(&title_id)
+            .unwrap_or(&self.default_profile)
+    }
+}
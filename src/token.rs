@@ -0,0 +1,326 @@
+//! Service tokens handed to clients by the account server and sent back in
+//! [AuthenticationInfo] when logging in.
+//!
+//! [TokenValidator] is format agnostic. The only format implemented here is a custom one
+//! used by [CustomTokenValidator]. It is **not** the NNAS/Pretendo service token layout, so
+//! tokens issued by those account servers won't validate with it.
+//!
+//! A custom token is base64 encoded `iv || AES-128-CBC(plaintext) || tag` with PKCS7 padding,
+//! where the little endian plaintext is:
+//!
+//! | Field       | Type |
+//! |-------------|------|
+//! | system_type | u8   |
+//! | token_type  | u8   |
+//! | pid         | u32  |
+//! | expire_time | u64  |
+//! | title_id    | u64  |
+//!
+//! `expire_time` is a unix timestamp in milliseconds and `tag` is the HMAC-SHA256 of
+//! `iv || ciphertext`, which is checked before anything is decrypted.
+
+use crate::any_data_holder::AnyDataHolder;
+use crate::ticket_granting::AuthenticationInfo;
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use nex_rs::nex_types::ResultCode;
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+type HmacSha256 = Hmac<Sha256>;
+
+pub const CUSTOM_TOKEN_KEY_SIZE: usize = 16;
+pub const CUSTOM_TOKEN_MAC_KEY_SIZE: usize = 32;
+const CUSTOM_TOKEN_IV_SIZE: usize = 16;
+const CUSTOM_TOKEN_TAG_SIZE: usize = 32;
+const CUSTOM_TOKEN_SIZE: usize = 22;
+
+/// The keys custom format tokens are encrypted and authenticated with.
+/// The two keys should be generated independently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomTokenKey {
+    pub encryption_key: [u8; CUSTOM_TOKEN_KEY_SIZE],
+    pub mac_key: [u8; CUSTOM_TOKEN_MAC_KEY_SIZE],
+}
+
+impl CustomTokenKey {
+    fn new_mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.mac_key).expect("HMAC accepts keys of any size")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServiceToken {
+    pub system_type: u8,
+    pub token_type: u8,
+    pub pid: u32,
+    pub expire_time: u64,
+    pub title_id: u64,
+}
+
+impl ServiceToken {
+    fn to_plaintext(self) -> Vec<u8> {
+        let mut plaintext = Vec::with_capacity(CUSTOM_TOKEN_SIZE);
+        plaintext.push(self.system_type);
+        plaintext.push(self.token_type);
+        plaintext.extend_from_slice(&self.pid.to_le_bytes());
+        plaintext.extend_from_slice(&self.expire_time.to_le_bytes());
+        plaintext.extend_from_slice(&self.title_id.to_le_bytes());
+        plaintext
+    }
+
+    fn from_plaintext(plaintext: &[u8]) -> Result<Self, ResultCode> {
+        if plaintext.len() != CUSTOM_TOKEN_SIZE {
+            return Err(ResultCode::Authentication_TokenParseError);
+        }
+
+        Ok(Self {
+            system_type: plaintext[0],
+            token_type: plaintext[1],
+            pid: u32::from_le_bytes(plaintext[2..6].try_into().unwrap()),
+            expire_time: u64::from_le_bytes(plaintext[6..14].try_into().unwrap()),
+            title_id: u64::from_le_bytes(plaintext[14..22].try_into().unwrap()),
+        })
+    }
+
+    /// Encrypts and encodes the token in the custom format, e.g. to issue tokens from a local
+    /// account server.
+    pub fn encrypt_custom(&self, key: &CustomTokenKey, iv: &[u8; CUSTOM_TOKEN_IV_SIZE]) -> String {
+        let ciphertext = Aes128CbcEnc::new((&key.encryption_key).into(), iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(&self.to_plaintext());
+
+        let mut data = iv.to_vec();
+        data.extend(ciphertext);
+
+        let mut mac = key.new_mac();
+        mac.update(&data);
+        data.extend(mac.finalize().into_bytes());
+
+        STANDARD.encode(data)
+    }
+
+    pub fn decrypt_custom(token: &str, key: &CustomTokenKey) -> Result<Self, ResultCode> {
+        let data = STANDARD
+            .decode(token.trim())
+            .map_err(|_| ResultCode::Authentication_TokenParseError)?;
+
+        if data.len() <= CUSTOM_TOKEN_IV_SIZE + CUSTOM_TOKEN_TAG_SIZE {
+            return Err(ResultCode::Authentication_TokenParseError);
+        }
+
+        let (data, tag) = data.split_at(data.len() - CUSTOM_TOKEN_TAG_SIZE);
+
+        let mut mac = key.new_mac();
+        mac.update(data);
+        mac.verify_slice(tag)
+            .map_err(|_| ResultCode::Authentication_ValidationFailed)?;
+
+        let (iv, ciphertext) = data.split_at(CUSTOM_TOKEN_IV_SIZE);
+        let plaintext = Aes128CbcDec::new((&key.encryption_key).into(), iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+            .map_err(|_| ResultCode::Authentication_TokenParseError)?;
+
+        Self::from_plaintext(&plaintext)
+    }
+
+    /// `now` is a unix timestamp in milliseconds.
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expire_time <= now
+    }
+}
+
+pub fn get_current_time_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+pub trait TokenValidator {
+    fn validate_token(&self, token: &str) -> Result<ServiceToken, ResultCode>;
+
+    fn validate_authentication_info(
+        &self,
+        authentication_info: &AuthenticationInfo,
+    ) -> Result<ServiceToken, ResultCode> {
+        let token: String = authentication_info.token.clone().into();
+        let service_token = self.validate_token(&token)?;
+
+        if service_token.token_type != authentication_info.token_type {
+            return Err(ResultCode::Authentication_ValidationFailed);
+        }
+
+        Ok(service_token)
+    }
+
+    /// Returns the title a LoginEx or RegisterEx DataHolder was issued for, validating its token.
+    /// Returns `None` for classes that don't identify a title.
+    fn get_title_id(&self, data_holder: &AnyDataHolder) -> Result<Option<u64>, ResultCode> {
+        let title_id = match data_holder {
+            AnyDataHolder::AuthenticationInfo(authentication_info) => {
+                self.validate_authentication_info(authentication_info)?
+                    .title_id
+            }
+            AnyDataHolder::NintendoLoginData(login_data) => {
+                let token: String = login_data.token.clone().into();
+                self.validate_token(&token)?.title_id
+            }
+            AnyDataHolder::GameKey(game_key) => game_key.title_id,
+            _ => return Ok(None),
+        };
+
+        Ok(Some(title_id))
+    }
+}
+
+/// Validates custom format tokens encrypted with a locally configured key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomTokenValidator {
+    key: CustomTokenKey,
+    allowed_title_ids: Vec<u64>,
+}
+
+impl CustomTokenValidator {
+    /// Creates a validator accepting tokens for any title.
+    pub fn new(key: CustomTokenKey) -> Self {
+        Self {
+            key,
+            allowed_title_ids: vec![],
+        }
+    }
+
+    /// Creates a validator only accepting tokens issued for `allowed_title_ids`.
+    pub fn with_allowed_title_ids(key: CustomTokenKey, allowed_title_ids: Vec<u64>) -> Self {
+        Self {
+            key,
+            allowed_title_ids,
+        }
+    }
+
+    pub fn validate_token_at(&self, token: &str, now: u64) -> Result<ServiceToken, ResultCode> {
+        let service_token = ServiceToken::decrypt_custom(token, &self.key)?;
+
+        if service_token.is_expired(now) {
+            return Err(ResultCode::Authentication_TokenExpired);
+        }
+
+        if !self.allowed_title_ids.is_empty()
+            && !self.allowed_title_ids.contains(&service_token.title_id)
+        {
+            return Err(ResultCode::Authentication_ValidationFailed);
+        }
+
+        Ok(service_token)
+    }
+}
+
+impl TokenValidator for CustomTokenValidator {
+    fn validate_token(&self, token: &str) -> Result<ServiceToken, ResultCode> {
+        self.validate_token_at(token, get_current_time_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000_000;
+    const IV: [u8; CUSTOM_TOKEN_IV_SIZE] = [7; CUSTOM_TOKEN_IV_SIZE];
+
+    fn key() -> CustomTokenKey {
+        CustomTokenKey {
+            encryption_key: [1; CUSTOM_TOKEN_KEY_SIZE],
+            mac_key: [2; CUSTOM_TOKEN_MAC_KEY_SIZE],
+        }
+    }
+
+    fn token(expire_time: u64) -> ServiceToken {
+        ServiceToken {
+            system_type: 2,
+            token_type: 1,
+            pid: 1_800_000_000,
+            expire_time,
+            title_id: 0x0004_0000_001B_5000,
+        }
+    }
+
+    fn tamper(token: &str, index: usize) -> String {
+        let mut data = STANDARD.decode(token).unwrap();
+        data[index] ^= 1;
+        STANDARD.encode(data)
+    }
+
+    #[test]
+    fn round_trip() {
+        let service_token = token(NOW + 1000);
+        let encrypted = service_token.encrypt_custom(&key(), &IV);
+
+        assert_eq!(
+            CustomTokenValidator::new(key()).validate_token_at(&encrypted, NOW),
+            Ok(service_token)
+        );
+    }
+
+    #[test]
+    fn rejects_expired_tokens() {
+        let encrypted = token(NOW).encrypt_custom(&key(), &IV);
+
+        assert_eq!(
+            CustomTokenValidator::new(key()).validate_token_at(&encrypted, NOW),
+            Err(ResultCode::Authentication_TokenExpired)
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_keys() {
+        let encrypted = token(NOW + 1000).encrypt_custom(&key(), &IV);
+
+        let mut wrong_mac_key = key();
+        wrong_mac_key.mac_key[0] ^= 1;
+        assert_eq!(
+            ServiceToken::decrypt_custom(&encrypted, &wrong_mac_key),
+            Err(ResultCode::Authentication_ValidationFailed)
+        );
+
+        let mut wrong_encryption_key = key();
+        wrong_encryption_key.encryption_key[0] ^= 1;
+        assert!(ServiceToken::decrypt_custom(&encrypted, &wrong_encryption_key).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_iv() {
+        let encrypted = tamper(&token(NOW + 1000).encrypt_custom(&key(), &IV), 0);
+
+        assert_eq!(
+            ServiceToken::decrypt_custom(&encrypted, &key()),
+            Err(ResultCode::Authentication_ValidationFailed)
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_ciphertext() {
+        let encrypted = tamper(
+            &token(NOW + 1000).encrypt_custom(&key(), &IV),
+            CUSTOM_TOKEN_IV_SIZE,
+        );
+
+        assert_eq!(
+            ServiceToken::decrypt_custom(&encrypted, &key()),
+            Err(ResultCode::Authentication_ValidationFailed)
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_tag() {
+        let encrypted = token(NOW + 1000).encrypt_custom(&key(), &IV);
+        let last = STANDARD.decode(&encrypted).unwrap().len() - 1;
+
+        assert_eq!(
+            ServiceToken::decrypt_custom(&tamper(&encrypted, last), &key()),
+            Err(ResultCode::Authentication_ValidationFailed)
+        );
+    }
+}
@@ -1,3 +1,8 @@
+use crate::client_context::ClientContextProvider;
+use crate::middleware::{HandlerResult, RequestPipeline};
+#[cfg(feature = "tracing")]
+use crate::request_trace::request_span;
+use crate::request_trace::trace_parameters;
 use async_trait::async_trait;
 use nex_rs::client::ClientConnection;
 use nex_rs::nex_types::ResultCode;
@@ -17,171 +22,171 @@ pub enum USUM117Method {
     Unknown15 = 0xF,
 }
 
+/// The methods of this title specific protocol haven't been documented yet,
+/// so each one receives its raw RMC parameters.
+///
+/// Named methods with request and response types are still open (see "Open work" in the
+/// README) until captures show what each method carries. Until then the `unknown_*` names
+/// follow their method ids.
 #[async_trait(?Send)]
-pub trait USUM117Protocol: Server {
-    async fn unknown_1(&self, client: &mut ClientConnection) -> Result<Vec<u8>, ResultCode>;
-    async fn unknown_7(&self, client: &mut ClientConnection) -> Result<Vec<u8>, ResultCode>;
-    async fn unknown_9(&self, client: &mut ClientConnection) -> Result<Vec<u8>, ResultCode>;
-    async fn unknown_10(&self, client: &mut ClientConnection) -> Result<Vec<u8>, ResultCode>;
-    async fn unknown_15(&self, client: &mut ClientConnection) -> Result<Vec<u8>, ResultCode>;
+pub trait USUM117Protocol: Server + ClientContextProvider {
+    async fn unknown_1(
+        &self,
+        client: &mut ClientConnection,
+        parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn unknown_7(
+        &self,
+        client: &mut ClientConnection,
+        parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn unknown_9(
+        &self,
+        client: &mut ClientConnection,
+        parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn unknown_10(
+        &self,
+        client: &mut ClientConnection,
+        parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode>;
+    async fn unknown_15(
+        &self,
+        client: &mut ClientConnection,
+        parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode>;
 
-    async fn handle_unknown_1(
+    /// Dispatches a request to its method's handler.
+    async fn handle_request(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
     ) -> Result<(), &'static str> {
+        let rmc_request = packet.get_rmc_request();
+        let method = USUM117Method::try_from(rmc_request.method_id);
+
+        #[cfg(feature = "tracing")]
+        let span = request_span(
+            "USUM117",
+            method.as_ref().ok(),
+            rmc_request.method_id,
+            rmc_request.call_id,
+            client.get_pid(),
+        );
+
+        let dispatch = async move {
+            let request = match self.begin_request(client, packet, true) {
+                Ok(request) => request,
+                Err(error_code) => {
+                    return self.send_response(client, packet, Err(error_code)).await
+                }
+            };
+
+            let result = match method {
+                Ok(USUM117Method::Unknown1) => self.handle_unknown_1(client, packet).await,
+                Ok(USUM117Method::Unknown7) => self.handle_unknown_7(client, packet).await,
+                Ok(USUM117Method::Unknown9) => self.handle_unknown_9(client, packet).await,
+                Ok(USUM117Method::Unknown10) => self.handle_unknown_10(client, packet).await,
+                Ok(USUM117Method::Unknown15) => self.handle_unknown_15(client, packet).await,
+                Err(_) => self.handle_unknown_method(client, packet).await,
+            };
+
+            self.finish_request(client, request, result).await
+        };
+
+        #[cfg(feature = "tracing")]
+        let dispatch = tracing::Instrument::instrument(dispatch, span);
+
+        dispatch.await
+    }
+
+    /// Called for method ids without a handler, e.g. to prototype undocumented methods.
+    /// Responds with `Core_NotImplemented` by default.
+    async fn handle_unknown(
+        &self,
+        _client: &mut ClientConnection,
+        _method_id: u32,
+        _parameters: &[u8],
+    ) -> Result<Vec<u8>, ResultCode> {
+        Err(ResultCode::Core_NotImplemented)
+    }
+
+    async fn handle_unknown_method(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
+        let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
+
+        let result = self
+            .handle_unknown(client, request.method_id, parameters)
+            .await;
+        Ok(result)
+    }
+
+    async fn handle_unknown_1(
+        &self,
+        client: &mut ClientConnection,
+        packet: &PacketV1,
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
 
-        match self.unknown_1(client).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        trace_parameters!(parameters);
+        let result = self.unknown_1(client, parameters).await;
+        Ok(result)
     }
 
     async fn handle_unknown_7(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
 
-        match self.unknown_7(client).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        trace_parameters!(parameters);
+        let result = self.unknown_7(client, parameters).await;
+        Ok(result)
     }
 
     async fn handle_unknown_9(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
 
-        match self.unknown_9(client).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        trace_parameters!(parameters);
+        let result = self.unknown_9(client, parameters).await;
+        Ok(result)
     }
 
     async fn handle_unknown_10(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
 
-        match self.unknown_10(client).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        trace_parameters!(parameters);
+        let result = self.unknown_10(client, parameters).await;
+        Ok(result)
     }
 
     async fn handle_unknown_15(
         &self,
         client: &mut ClientConnection,
         packet: &PacketV1,
-    ) -> Result<(), &'static str> {
+    ) -> HandlerResult {
         let request = packet.get_rmc_request();
+        let parameters = request.parameters.as_slice();
 
-        match self.unknown_15(client).await {
-            Ok(data) => {
-                self.send_success(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    data,
-                )
-                .await?
-            }
-            Err(error_code) => {
-                self.send_error(
-                    client,
-                    request.protocol_id,
-                    request.method_id,
-                    request.call_id,
-                    error_code.into(),
-                )
-                .await?
-            }
-        }
-        Ok(())
+        trace_parameters!(parameters);
+        let result = self.unknown_15(client, parameters).await;
+        Ok(result)
     }
 }
@@ -0,0 +1,212 @@
+//! Layout regression guards for the wire types.
+//!
+//! Each fixture in `tests/fixtures` is read into its type, which has to consume every byte,
+//! then written back and compared with the fixture. The fixtures are hand-built from the
+//! current layouts, so these tests catch accidental layout changes but don't prove a layout
+//! matches what consoles send. See `tests/fixtures/README.md` for how to replace them with
+//! captured bytes.
+
+use nex_protocols_rs::{datastore_usum, matchmake_extension, secure_connection, ticket_granting};
+use no_std_io::{EndianRead, EndianWrite, Error, StreamContainer, StreamReader};
+use std::path::Path;
+
+fn read_fixture(fixture: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(fixture);
+    let text = std::fs::read_to_string(&path)
+        .unwrap_or_else(|error| panic!("Can not read {}: {}", path.display(), error));
+
+    let hex: String = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(|line| line.chars().filter(|c| !c.is_whitespace()))
+        .collect();
+
+    assert!(
+        hex.len() % 2 == 0,
+        "{} has an odd number of digits",
+        fixture
+    );
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| {
+            u8::from_str_radix(&hex[index..index + 2], 16)
+                .unwrap_or_else(|_| panic!("{} has invalid hex at digit {}", fixture, index))
+        })
+        .collect()
+}
+
+fn assert_round_trip<T: EndianRead + EndianWrite + std::fmt::Debug>(fixture: &str) {
+    let bytes = read_fixture(fixture);
+    let mut stream = StreamContainer::new(bytes.as_slice());
+
+    let value = stream
+        .read_stream_le::<T>()
+        .unwrap_or_else(|error| panic!("Can not read {}: {:?}", fixture, error));
+    assert_eq!(
+        stream.get_index(),
+        bytes.len(),
+        "{} was not fully read: {:?}",
+        fixture,
+        value
+    );
+
+    let mut written = vec![0; value.get_size()];
+    value
+        .try_write_le(&mut written)
+        .unwrap_or_else(|error| panic!("Can not write {}: {:?}", fixture, error));
+
+    if let Some(offset) = bytes.iter().zip(written.iter()).position(|(a, b)| a != b) {
+        panic!(
+            "{} differs at byte {}: expected {:02x}, wrote {:02x}",
+            fixture, offset, bytes[offset], written[offset]
+        );
+    }
+
+    assert_eq!(
+        written.len(),
+        bytes.len(),
+        "{} was written with a different length",
+        fixture
+    );
+}
+
+macro_rules! round_trip_tests {
+    ($($test:ident: $type:ty => $fixture:literal,)*) => {
+        $(
+            #[test]
+            fn $test() {
+                assert_round_trip::<$type>($fixture);
+            }
+        )*
+    };
+}
+
+round_trip_tests! {
+    change_metas_request: datastore_usum::ChangeMetasRequest => "datastore_usum/ChangeMetasRequest.hex",
+    change_metas_response: datastore_usum::ChangeMetasResponse => "datastore_usum/ChangeMetasResponse.hex",
+    data_store_change_meta_compare_param: datastore_usum::DataStoreChangeMetaCompareParam => "datastore_usum/DataStoreChangeMetaCompareParam.hex",
+    data_store_change_meta_param: datastore_usum::DataStoreChangeMetaParam => "datastore_usum/DataStoreChangeMetaParam.hex",
+    data_store_get_meta_param: datastore_usum::DataStoreGetMetaParam => "datastore_usum/DataStoreGetMetaParam.hex",
+    data_store_meta_info: datastore_usum::DataStoreMetaInfo => "datastore_usum/DataStoreMetaInfo.hex",
+    data_store_permission: datastore_usum::DataStorePermission => "datastore_usum/DataStorePermission.hex",
+    data_store_persistence_init_param: datastore_usum::DataStorePersistenceInitParam => "datastore_usum/DataStorePersistenceInitParam.hex",
+    data_store_persistence_target: datastore_usum::DataStorePersistenceTarget => "datastore_usum/DataStorePersistenceTarget.hex",
+    data_store_prepare_post_param: datastore_usum::DataStorePreparePostParam => "datastore_usum/DataStorePreparePostParam.hex",
+    data_store_rate_object_param: datastore_usum::DataStoreRateObjectParam => "datastore_usum/DataStoreRateObjectParam.hex",
+    data_store_rating_info: datastore_usum::DataStoreRatingInfo => "datastore_usum/DataStoreRatingInfo.hex",
+    data_store_rating_info_with_slot: datastore_usum::DataStoreRatingInfoWithSlot => "datastore_usum/DataStoreRatingInfoWithSlot.hex",
+    data_store_rating_init_param: datastore_usum::DataStoreRatingInitParam => "datastore_usum/DataStoreRatingInitParam.hex",
+    data_store_rating_init_param_with_slot: datastore_usum::DataStoreRatingInitParamWithSlot => "datastore_usum/DataStoreRatingInitParamWithSlot.hex",
+    data_store_rating_target: datastore_usum::DataStoreRatingTarget => "datastore_usum/DataStoreRatingTarget.hex",
+    delete_pokemon_request: datastore_usum::DeletePokemonRequest => "datastore_usum/DeletePokemonRequest.hex",
+    download_my_pokemon_request: datastore_usum::DownloadMyPokemonRequest => "datastore_usum/DownloadMyPokemonRequest.hex",
+    download_my_pokemon_response: datastore_usum::DownloadMyPokemonResponse => "datastore_usum/DownloadMyPokemonResponse.hex",
+    download_other_pokemon_request: datastore_usum::DownloadOtherPokemonRequest => "datastore_usum/DownloadOtherPokemonRequest.hex",
+    download_other_pokemon_response: datastore_usum::DownloadOtherPokemonResponse => "datastore_usum/DownloadOtherPokemonResponse.hex",
+    get_metas_request: datastore_usum::GetMetasRequest => "datastore_usum/GetMetasRequest.hex",
+    get_metas_response: datastore_usum::GetMetasResponse => "datastore_usum/GetMetasResponse.hex",
+    global_trade_station_data: datastore_usum::GlobalTradeStationData => "datastore_usum/GlobalTradeStationData.hex",
+    global_trade_station_delete_pokemon_param: datastore_usum::GlobalTradeStationDeletePokemonParam => "datastore_usum/GlobalTradeStationDeletePokemonParam.hex",
+    global_trade_station_download_my_pokemon_param: datastore_usum::GlobalTradeStationDownloadMyPokemonParam => "datastore_usum/GlobalTradeStationDownloadMyPokemonParam.hex",
+    global_trade_station_download_my_pokemon_result: datastore_usum::GlobalTradeStationDownloadMyPokemonResult => "datastore_usum/GlobalTradeStationDownloadMyPokemonResult.hex",
+    global_trade_station_download_other_pokemon_param: datastore_usum::GlobalTradeStationDownloadOtherPokemonParam => "datastore_usum/GlobalTradeStationDownloadOtherPokemonParam.hex",
+    global_trade_station_download_pokemon_result: datastore_usum::GlobalTradeStationDownloadPokemonResult => "datastore_usum/GlobalTradeStationDownloadPokemonResult.hex",
+    global_trade_station_prepare_trade_pokemon_param: datastore_usum::GlobalTradeStationPrepareTradePokemonParam => "datastore_usum/GlobalTradeStationPrepareTradePokemonParam.hex",
+    global_trade_station_prepare_trade_pokemon_result: datastore_usum::GlobalTradeStationPrepareTradePokemonResult => "datastore_usum/GlobalTradeStationPrepareTradePokemonResult.hex",
+    global_trade_station_record_key: datastore_usum::GlobalTradeStationRecordKey => "datastore_usum/GlobalTradeStationRecordKey.hex",
+    global_trade_station_search_pokemon_param: datastore_usum::GlobalTradeStationSearchPokemonParam => "datastore_usum/GlobalTradeStationSearchPokemonParam.hex",
+    global_trade_station_search_pokemon_result: datastore_usum::GlobalTradeStationSearchPokemonResult => "datastore_usum/GlobalTradeStationSearchPokemonResult.hex",
+    global_trade_station_trade_key: datastore_usum::GlobalTradeStationTradeKey => "datastore_usum/GlobalTradeStationTradeKey.hex",
+    global_trade_station_trade_pokemon_param: datastore_usum::GlobalTradeStationTradePokemonParam => "datastore_usum/GlobalTradeStationTradePokemonParam.hex",
+    global_trade_station_trade_pokemon_result: datastore_usum::GlobalTradeStationTradePokemonResult => "datastore_usum/GlobalTradeStationTradePokemonResult.hex",
+    global_trade_station_upload_pokemon_param: datastore_usum::GlobalTradeStationUploadPokemonParam => "datastore_usum/GlobalTradeStationUploadPokemonParam.hex",
+    post_meta_binary_request: datastore_usum::PostMetaBinaryRequest => "datastore_usum/PostMetaBinaryRequest.hex",
+    post_meta_binary_response: datastore_usum::PostMetaBinaryResponse => "datastore_usum/PostMetaBinaryResponse.hex",
+    prepare_trade_pokemon_request: datastore_usum::PrepareTradePokemonRequest => "datastore_usum/PrepareTradePokemonRequest.hex",
+    prepare_trade_pokemon_response: datastore_usum::PrepareTradePokemonResponse => "datastore_usum/PrepareTradePokemonResponse.hex",
+    prepare_upload_pokemon_response: datastore_usum::PrepareUploadPokemonResponse => "datastore_usum/PrepareUploadPokemonResponse.hex",
+    rate_object_request: datastore_usum::RateObjectRequest => "datastore_usum/RateObjectRequest.hex",
+    rate_object_response: datastore_usum::RateObjectResponse => "datastore_usum/RateObjectResponse.hex",
+    search_pokemon_v2_request: datastore_usum::SearchPokemonV2Request => "datastore_usum/SearchPokemonV2Request.hex",
+    search_pokemon_v2_response: datastore_usum::SearchPokemonV2Response => "datastore_usum/SearchPokemonV2Response.hex",
+    trade_pokemon_request: datastore_usum::TradePokemonRequest => "datastore_usum/TradePokemonRequest.hex",
+    trade_pokemon_response: datastore_usum::TradePokemonResponse => "datastore_usum/TradePokemonResponse.hex",
+    upload_pokemon_request: datastore_usum::UploadPokemonRequest => "datastore_usum/UploadPokemonRequest.hex",
+    attraction_status: matchmake_extension::AttractionStatus => "matchmake_extension/AttractionStatus.hex",
+    auto_matchmake_param: matchmake_extension::AutoMatchmakeParam => "matchmake_extension/AutoMatchmakeParam.hex",
+    community: matchmake_extension::Community => "matchmake_extension/Community.hex",
+    create_matchmake_session_param: matchmake_extension::CreateMatchmakeSessionParam => "matchmake_extension/CreateMatchmakeSessionParam.hex",
+    gathering: matchmake_extension::Gathering => "matchmake_extension/Gathering.hex",
+    gathering_urls: matchmake_extension::GatheringURLs => "matchmake_extension/GatheringURLs.hex",
+    join_matchmake_session_param: matchmake_extension::JoinMatchmakeSessionParam => "matchmake_extension/JoinMatchmakeSessionParam.hex",
+    matchmake_param: matchmake_extension::MatchmakeParam => "matchmake_extension/MatchmakeParam.hex",
+    matchmake_session: matchmake_extension::MatchmakeSession => "matchmake_extension/MatchmakeSession.hex",
+    matchmake_session_search_criteria: matchmake_extension::MatchmakeSessionSearchCriteria => "matchmake_extension/MatchmakeSessionSearchCriteria.hex",
+    persistent_gathering: matchmake_extension::PersistentGathering => "matchmake_extension/PersistentGathering.hex",
+    simple_community: matchmake_extension::SimpleCommunity => "matchmake_extension/SimpleCommunity.hex",
+    simple_matchmake_host_info: matchmake_extension::SimpleMatchmakeHostInfo => "matchmake_extension/SimpleMatchmakeHostInfo.hex",
+    simple_playing_session: matchmake_extension::SimplePlayingSession => "matchmake_extension/SimplePlayingSession.hex",
+    update_matchmake_session_param: matchmake_extension::UpdateMatchmakeSessionParam => "matchmake_extension/UpdateMatchmakeSessionParam.hex",
+    connection_data: secure_connection::ConnectionData => "secure_connection/ConnectionData.hex",
+    authentication_info: ticket_granting::AuthenticationInfo => "ticket_granting/AuthenticationInfo.hex",
+}
+
+/// Replaces the first occurrence of `pattern` in a fixture, e.g. to corrupt a length prefix.
+fn patch_fixture(fixture: &str, pattern: &[u8], replacement: &[u8]) -> Vec<u8> {
+    let mut bytes = read_fixture(fixture);
+    let offset = bytes
+        .windows(pattern.len())
+        .position(|window| window == pattern)
+        .unwrap_or_else(|| panic!("{} doesn't contain {:02x?}", fixture, pattern));
+    bytes[offset..offset + replacement.len()].copy_from_slice(replacement);
+    bytes
+}
+
+#[test]
+fn rejects_oversized_nested_lists() {
+    // The tags count of the first DataStoreChangeMetaParam, followed by its only tag
+    let bytes = patch_fixture(
+        "datastore_usum/ChangeMetasRequest.hex",
+        &[0x01, 0x00, 0x00, 0x00, 0x06, 0x00, b't', b'a', b'g'],
+        &u32::MAX.to_le_bytes(),
+    );
+    let result = StreamContainer::new(bytes.as_slice())
+        .read_stream_le::<datastore_usum::ChangeMetasRequest>();
+
+    assert!(
+        matches!(
+            result,
+            Err(Error::InvalidRead {
+                message: "List length exceeds the limit"
+            })
+        ),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn rejects_oversized_nested_buffers() {
+    // The meta binary of the first DataStoreChangeMetaParam
+    let bytes = patch_fixture(
+        "datastore_usum/ChangeMetasRequest.hex",
+        &[0x03, 0x00, 0xcc, 0xdd, 0xee],
+        &u16::MAX.to_le_bytes(),
+    );
+    let result = StreamContainer::new(bytes.as_slice())
+        .read_stream_le::<datastore_usum::ChangeMetasRequest>();
+
+    assert!(
+        matches!(
+            result,
+            Err(Error::InvalidRead {
+                message: "qBuffer length exceeds the limit"
+            })
+        ),
+        "{:?}",
+        result
+    );
+}